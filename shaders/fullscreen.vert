@@ -0,0 +1,8 @@
+#version 450
+
+// Covers the screen with one oversized triangle built from gl_VertexIndex, so no vertex
+// buffer is bound. Draw with a vertex count of 3.
+void main() {
+    vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    gl_Position = vec4(uv * 2.0 - 1.0, 0.0, 1.0);
+}
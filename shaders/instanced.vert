@@ -0,0 +1,34 @@
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec2 tex_coords;
+
+// Per-instance data from the instance-rate binding; a mat4 takes locations 3 to 6
+layout(location = 3) in mat4 instance_model;
+layout(location = 7) in vec4 instance_color;
+
+layout(binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+// Per-batch data, laid out like shader.vert; the model matrix comes from the instance instead
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 color;
+} pc;
+
+// Outputs match shader.vert so the same fragment shaders can follow either stage
+layout(location = 0) out vec3 fragNormal;
+layout(location = 1) out vec2 fragTexCoord;
+layout(location = 2) out vec3 fragPosition;
+layout(location = 3) out vec4 fragColor;
+
+void main() {
+    gl_Position = ubo.proj * ubo.view * instance_model * vec4(position, 1.0);
+    fragNormal = mat3(instance_model) * normal;
+    fragTexCoord = tex_coords;
+    fragPosition = (instance_model * vec4(position, 1.0)).xyz;
+    fragColor = pc.color * instance_color;
+}
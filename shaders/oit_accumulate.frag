@@ -0,0 +1,44 @@
+#version 450
+
+// Inputs mirror shader.frag so this can replace it behind shader.vert
+layout(location = 0) in vec3 fragNormal;
+layout(location = 1) in vec2 fragTexCoord;
+layout(location = 2) in vec3 fragPosition;
+layout(location = 3) in vec4 fragColor;
+
+// Weighted blended OIT targets, blended additively and multiplicatively respectively
+layout(location = 0) out vec4 outAccumulation;
+layout(location = 1) out float outRevealage;
+
+// Directional lights of the frame, filled from the render queue's lights
+#define MAX_LIGHTS 4
+layout(set = 1, binding = 0) uniform LightBlock {
+    vec4 directions[MAX_LIGHTS];
+    vec4 colors[MAX_LIGHTS];
+    int count;
+} lights;
+
+void main() {
+    vec3 normal = normalize(fragNormal);
+
+    // Same lighting as shader.frag
+    float ambientStrength = 0.2;
+    vec3 ambient = ambientStrength * vec3(1.0, 1.0, 1.0);
+    vec3 diffuse = vec3(0.0);
+    for (int i = 0; i < lights.count; ++i) {
+        float diff = max(dot(normal, -normalize(lights.directions[i].xyz)), 0.0);
+        diffuse += diff * lights.colors[i].rgb;
+    }
+    vec3 color = (ambient + diffuse) * fragColor.rgb;
+    float alpha = fragColor.a;
+
+    // Depth weight from McGuire and Bavoil 2013, favouring nearer and more opaque surfaces
+    float weight = clamp(
+        pow(min(1.0, alpha * 10.0) + 0.01, 3.0) * 1e8 * pow(1.0 - gl_FragCoord.z * 0.9, 3.0),
+        1e-2,
+        3e3
+    );
+
+    outAccumulation = vec4(color * alpha, alpha) * weight;
+    outRevealage = alpha;
+}
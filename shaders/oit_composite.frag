@@ -0,0 +1,20 @@
+#version 450
+
+// OIT targets written by oit_accumulate.frag in the previous subpass
+layout(input_attachment_index = 0, set = 0, binding = 0) uniform subpassInput accumulation;
+layout(input_attachment_index = 1, set = 0, binding = 1) uniform subpassInput revealage;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    // Revealage stays at its clear value where no transparent surface was drawn
+    float reveal = subpassLoad(revealage).r;
+    if (reveal >= 1.0) {
+        discard;
+    }
+
+    // Weighted average color, blended over the opaque scene by the remaining coverage
+    vec4 accum = subpassLoad(accumulation);
+    vec3 average = accum.rgb / max(accum.a, 1e-5);
+    outColor = vec4(average, 1.0 - reveal);
+}
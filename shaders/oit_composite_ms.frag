@@ -0,0 +1,32 @@
+#version 450
+
+// Multisampled variant of oit_composite.frag, used when MSAA is enabled
+layout(input_attachment_index = 0, set = 0, binding = 0) uniform subpassInputMS accumulation;
+layout(input_attachment_index = 1, set = 0, binding = 1) uniform subpassInputMS revealage;
+
+layout(push_constant) uniform CompositeConstants {
+    int sample_count;
+} constants;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    // Average the OIT targets over all samples of the pixel
+    vec4 accum = vec4(0.0);
+    float reveal = 0.0;
+    for (int i = 0; i < constants.sample_count; i++) {
+        accum += subpassLoad(accumulation, i);
+        reveal += subpassLoad(revealage, i).r;
+    }
+    accum /= float(constants.sample_count);
+    reveal /= float(constants.sample_count);
+
+    // Revealage stays at its clear value where no transparent surface was drawn
+    if (reveal >= 1.0) {
+        discard;
+    }
+
+    // Weighted average color, blended over the opaque scene by the remaining coverage
+    vec3 average = accum.rgb / max(accum.a, 1e-5);
+    outColor = vec4(average, 1.0 - reveal);
+}
@@ -3,25 +3,36 @@
 layout(location = 0) in vec3 fragNormal;
 layout(location = 1) in vec2 fragTexCoord;
 layout(location = 2) in vec3 fragPosition;
+layout(location = 3) in vec4 fragColor;
 
 layout(location = 0) out vec4 outColor;
 
+// Directional lights of the frame, filled from the render queue's lights
+#define MAX_LIGHTS 4
+layout(set = 1, binding = 0) uniform LightBlock {
+    vec4 directions[MAX_LIGHTS];
+    vec4 colors[MAX_LIGHTS];
+    int count;
+} lights;
+
 void main() {
-    vec3 lightDir = normalize(vec3(1.0, 1.0, 1.0));
     vec3 normal = normalize(fragNormal);
     
     // Ambient lighting
     float ambientStrength = 0.2;
     vec3 ambient = ambientStrength * vec3(1.0, 1.0, 1.0);
     
-    // Diffuse lighting
-    float diff = max(dot(normal, lightDir), 0.0);
-    vec3 diffuse = diff * vec3(1.0, 1.0, 1.0);
+    // Diffuse lighting; directions point the way the light travels
+    vec3 diffuse = vec3(0.0);
+    for (int i = 0; i < lights.count; ++i) {
+        float diff = max(dot(normal, -normalize(lights.directions[i].xyz)), 0.0);
+        diffuse += diff * lights.colors[i].rgb;
+    }
     
-    // Base color (could be from texture)
-    vec3 baseColor = vec3(0.7, 0.2, 0.2);
+    // Base color from the material; alpha drives blending
+    vec3 baseColor = fragColor.rgb;
     
     // Final color
     vec3 result = (ambient + diffuse) * baseColor;
-    outColor = vec4(result, 1.0);
+    outColor = vec4(result, fragColor.a);
 }
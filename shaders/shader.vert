@@ -5,18 +5,25 @@ layout(location = 1) in vec3 normal;
 layout(location = 2) in vec2 tex_coords;
 
 layout(binding = 0) uniform UniformBufferObject {
-    mat4 model;
     mat4 view;
     mat4 proj;
 } ubo;
 
+// Per-draw data
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 color;
+} pc;
+
 layout(location = 0) out vec3 fragNormal;
 layout(location = 1) out vec2 fragTexCoord;
 layout(location = 2) out vec3 fragPosition;
+layout(location = 3) out vec4 fragColor;
 
 void main() {
-    gl_Position = ubo.proj * ubo.view * ubo.model * vec4(position, 1.0);
-    fragNormal = mat3(ubo.model) * normal;
+    gl_Position = ubo.proj * ubo.view * pc.model * vec4(position, 1.0);
+    fragNormal = mat3(pc.model) * normal;
     fragTexCoord = tex_coords;
-    fragPosition = (ubo.model * vec4(position, 1.0)).xyz;
+    fragPosition = (pc.model * vec4(position, 1.0)).xyz;
+    fragColor = pc.color;
 }
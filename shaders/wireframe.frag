@@ -0,0 +1,15 @@
+#version 450
+
+// Inputs mirror shader.frag so this can replace it behind shader.vert
+layout(location = 0) in vec3 fragNormal;
+layout(location = 1) in vec2 fragTexCoord;
+layout(location = 2) in vec3 fragPosition;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+    // Flat wire color, slightly shaded so overlapping edges stay readable
+    vec3 wireColor = vec3(0.1, 1.0, 0.3);
+    float shade = 0.75 + 0.25 * abs(normalize(fragNormal).z);
+    outColor = vec4(wireColor * shade, 1.0);
+}
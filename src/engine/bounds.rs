@@ -0,0 +1,293 @@
+use nalgebra::{Matrix4, Point3, Vector3, Vector4};
+
+/// An axis-aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    /// Returns the smallest box around the points, or an empty box at the origin for no points
+    pub fn from_points(points: impl IntoIterator<Item = Point3<f32>>) -> Self {
+        let mut points = points.into_iter();
+        let Some(first) = points.next() else {
+            return Self {
+                min: Point3::origin(),
+                max: Point3::origin(),
+            };
+        };
+
+        points.fold(Self { min: first, max: first }, |bounds, point| Self {
+            min: bounds.min.inf(&point),
+            max: bounds.max.sup(&point),
+        })
+    }
+
+    /// Returns the center of the box
+    pub fn center(&self) -> Point3<f32> {
+        nalgebra::center(&self.min, &self.max)
+    }
+
+    /// Returns half the size of the box along each axis
+    pub fn half_extents(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    /// Returns the box around this box after transforming it, e.g. into world space
+    ///
+    /// Uses the absolute values of the matrix to project the half extents, so the result
+    /// stays tight for rotations without transforming all eight corners.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Self {
+        let center = matrix.transform_point(&self.center());
+        let linear = matrix.fixed_view::<3, 3>(0, 0).abs();
+        let half_extents = linear * self.half_extents();
+        Self {
+            min: center - half_extents,
+            max: center + half_extents,
+        }
+    }
+}
+
+/// A bounding sphere
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Point3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Returns a sphere centered on the box around the points that encloses all of them
+    pub fn from_points(points: &[Point3<f32>]) -> Self {
+        let center = Aabb::from_points(points.iter().copied()).center();
+        let radius = points
+            .iter()
+            .map(|point| (point - center).norm())
+            .fold(0.0, f32::max);
+        Self { center, radius }
+    }
+
+    /// Returns the sphere around this sphere after transforming it, e.g. into world space
+    ///
+    /// The radius grows with the largest scale of the matrix, so non-uniform scaling gives
+    /// a conservative sphere.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Self {
+        let linear = matrix.fixed_view::<3, 3>(0, 0);
+        let scale = (0..3)
+            .map(|axis| linear.column(axis).norm())
+            .fold(0.0, f32::max);
+        Self {
+            center: matrix.transform_point(&self.center),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// The six planes bounding what a camera sees, with normals pointing inwards
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    /// Plane equations `(normal, distance)` with unit normals; inside is where the dot is positive
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes of a Vulkan view-projection matrix (depth 0..1)
+    pub fn from_matrix(view_proj: &Matrix4<f32>) -> Self {
+        let row = |index: usize| view_proj.row(index).transpose();
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|plane| {
+            let length = plane.xyz().norm();
+            if length > 0.0 {
+                plane / length
+            } else {
+                plane
+            }
+        });
+        Self { planes }
+    }
+
+    /// Returns whether any part of the sphere may be inside the frustum
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes.iter().all(|plane| {
+            plane.xyz().dot(&sphere.center.coords) + plane.w >= -sphere.radius
+        })
+    }
+
+    /// Returns whether any part of the box may be inside the frustum
+    ///
+    /// Conservative: boxes near a frustum corner can pass although they are outside.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let center = aabb.center().coords;
+        let half_extents = aabb.half_extents();
+        self.planes.iter().all(|plane| {
+            let normal = plane.xyz();
+            let reach = normal.abs().dot(&half_extents);
+            normal.dot(&center) + plane.w >= -reach
+        })
+    }
+}
+
+/// Counts of one frame's frustum culling
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CullStats {
+    /// Draws and instances tested against the frustum
+    pub tested: usize,
+    /// Draws and instances outside the frustum that were skipped
+    pub culled: usize,
+}
+
+impl CullStats {
+    /// Returns the number of draws and instances that passed the test
+    pub fn visible(&self) -> usize {
+        self.tested - self.culled
+    }
+}
+
+impl std::ops::AddAssign for CullStats {
+    fn add_assign(&mut self, other: Self) {
+        self.tested += other.tested;
+        self.culled += other.culled;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::camera::Camera;
+    use nalgebra::{Isometry3, Translation3, UnitQuaternion};
+    use std::f32::consts::FRAC_PI_4;
+
+    /// A 90 degree square view down -Z with near 1 and far 10, depth mapped to 0..1
+    fn projection() -> Matrix4<f32> {
+        let (near, far) = (1.0, 10.0);
+        let range = near - far;
+        Matrix4::new(
+            1.0, 0.0, 0.0, 0.0,
+            0.0, -1.0, 0.0, 0.0,
+            0.0, 0.0, far / range, near * far / range,
+            0.0, 0.0, -1.0, 0.0,
+        )
+    }
+
+    fn cube(center: [f32; 3], half_size: f32) -> Aabb {
+        let center = Point3::from(center);
+        let half_extents = Vector3::repeat(half_size);
+        Aabb {
+            min: center - half_extents,
+            max: center + half_extents,
+        }
+    }
+
+    fn sphere(center: [f32; 3], radius: f32) -> BoundingSphere {
+        BoundingSphere {
+            center: Point3::from(center),
+            radius,
+        }
+    }
+
+    fn assert_close(a: Vector4<f32>, b: Vector4<f32>) {
+        assert!((a - b).norm() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn frustum_planes_of_a_perspective_matrix() {
+        let frustum = Frustum::from_matrix(&projection());
+        let side = std::f32::consts::FRAC_1_SQRT_2;
+        assert_close(frustum.planes[0], Vector4::new(side, 0.0, -side, 0.0));
+        assert_close(frustum.planes[1], Vector4::new(-side, 0.0, -side, 0.0));
+        // Vulkan depth starts at zero, so the near plane is `z` alone rather than `w + z`
+        assert_close(frustum.planes[4], Vector4::new(0.0, 0.0, -1.0, -1.0));
+        assert_close(frustum.planes[5], Vector4::new(0.0, 0.0, 1.0, 10.0));
+        for plane in frustum.planes {
+            assert!((plane.xyz().norm() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn boxes_and_spheres_against_the_frustum() {
+        let frustum = Frustum::from_matrix(&projection());
+        let cases = [
+            ([0.0, 0.0, -5.0], true),
+            // Straddling the side, near and far planes
+            ([5.0, 0.0, -5.0], true),
+            ([0.0, 0.0, -1.0], true),
+            ([0.0, 0.0, -10.0], true),
+            // Outside the side, in front of the near plane, behind the far plane and the camera
+            ([7.0, 0.0, -5.0], false),
+            ([0.0, -7.0, -5.0], false),
+            ([0.0, 0.0, -0.2], false),
+            ([0.0, 0.0, -10.8], false),
+            ([0.0, 0.0, 5.0], false),
+        ];
+        for (center, visible) in cases {
+            assert_eq!(frustum.intersects_aabb(&cube(center, 0.5)), visible, "{:?}", center);
+            assert_eq!(frustum.intersects_sphere(&sphere(center, 0.5)), visible, "{:?}", center);
+        }
+    }
+
+    #[test]
+    fn camera_frustum_is_in_world_space() {
+        let camera = Camera::look_at(Point3::new(0.0, 0.0, 5.0), Point3::origin(), Vector3::y());
+        let frustum = camera.frustum(1.0);
+        assert!(frustum.intersects_sphere(&sphere([0.0, 0.0, 0.0], 1.0)));
+        assert!(!frustum.intersects_sphere(&sphere([0.0, 0.0, 10.0], 1.0)));
+        assert!(!frustum.intersects_aabb(&cube([30.0, 0.0, 0.0], 1.0)));
+    }
+
+    #[test]
+    fn aabb_transform_contains_the_transformed_corners() {
+        let aabb = Aabb {
+            min: Point3::new(-1.0, -2.0, -3.0),
+            max: Point3::new(1.0, 2.0, 3.0),
+        };
+
+        // A quarter turn maps the box onto a box, so the result is exact
+        let quarter = Isometry3::from_parts(
+            Translation3::new(10.0, 0.0, 0.0),
+            UnitQuaternion::from_axis_angle(&Vector3::y_axis(), std::f32::consts::FRAC_PI_2),
+        )
+        .to_homogeneous();
+        let turned = aabb.transform(&quarter);
+        assert!((turned.min - Point3::new(7.0, -2.0, -1.0)).norm() < 1e-5);
+        assert!((turned.max - Point3::new(13.0, 2.0, 1.0)).norm() < 1e-5);
+
+        let matrix = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), FRAC_PI_4)
+            .to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&Vector3::new(2.0, 1.0, 0.5));
+        let transformed = aabb.transform(&matrix);
+        for corner in 0..8 {
+            let point = Point3::new(
+                if corner & 1 == 0 { aabb.min.x } else { aabb.max.x },
+                if corner & 2 == 0 { aabb.min.y } else { aabb.max.y },
+                if corner & 4 == 0 { aabb.min.z } else { aabb.max.z },
+            );
+            let point = matrix.transform_point(&point);
+            assert!((0..3).all(|axis| {
+                point[axis] >= transformed.min[axis] - 1e-5
+                    && point[axis] <= transformed.max[axis] + 1e-5
+            }));
+        }
+    }
+
+    #[test]
+    fn sphere_transform_grows_with_the_largest_scale() {
+        let points = [Point3::new(-1.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0)];
+        let bounds = BoundingSphere::from_points(&points);
+        assert_eq!(bounds, sphere([0.0, 0.0, 0.0], 1.0));
+
+        let matrix = Matrix4::new_translation(&Vector3::new(0.0, 4.0, 0.0))
+            * Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 3.0, 2.0));
+        let transformed = bounds.transform(&matrix);
+        assert_eq!(transformed.center, Point3::new(0.0, 4.0, 0.0));
+        assert!((transformed.radius - 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn empty_point_sets_give_empty_bounds_at_the_origin() {
+        let aabb = Aabb::from_points(std::iter::empty());
+        assert_eq!(aabb.min, Point3::origin());
+        assert_eq!(aabb.max, Point3::origin());
+        assert_eq!(BoundingSphere::from_points(&[]).radius, 0.0);
+    }
+}
@@ -2,15 +2,20 @@ use anyhow::Result;
 use log::info;
 use nalgebra::Matrix4;
 use std::sync::Arc;
-use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
-use vulkano::memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator};
+use vulkano::buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator};
 
 /// Vertex structure for our 3D models
-#[derive(Default, Debug, Clone, Copy)]
+///
+/// Field names match the vertex shader inputs so the layout can be validated by reflection.
+#[derive(Default, Debug, Clone, Copy, vulkano::pipeline::graphics::vertex_input::Vertex)]
 #[repr(C)]
 pub struct Vertex {
+    #[format(R32G32B32_SFLOAT)]
     pub position: [f32; 3],
+    #[format(R32G32B32_SFLOAT)]
     pub normal: [f32; 3],
+    #[format(R32G32_SFLOAT)]
     pub tex_coords: [f32; 2],
 }
 
@@ -18,28 +23,71 @@ pub struct Vertex {
 unsafe impl bytemuck::Pod for Vertex {}
 unsafe impl bytemuck::Zeroable for Vertex {}
 
-/// Uniform buffer object for model-view-projection matrices
+/// Per-instance data read from the instance-rate binding of `shaders/instanced.vert`
+///
+/// The color multiplies the material color of the batch, so white keeps the material as is.
+#[derive(Debug, Clone, Copy, vulkano::pipeline::graphics::vertex_input::Vertex)]
 #[repr(C)]
-#[derive(Debug, Clone, Copy)]
-pub struct UniformBufferObject {
-    pub model: Matrix4<f32>,
-    pub view: Matrix4<f32>,
-    pub proj: Matrix4<f32>,
+pub struct InstanceData {
+    #[format(R32G32B32A32_SFLOAT)]
+    pub instance_model: [[f32; 4]; 4],
+    #[format(R32G32B32A32_SFLOAT)]
+    pub instance_color: [f32; 4],
 }
 
-// Implement Pod and Zeroable for UniformBufferObject
-unsafe impl bytemuck::Pod for UniformBufferObject {}
-unsafe impl bytemuck::Zeroable for UniformBufferObject {}
+impl InstanceData {
+    /// An instance with the given model matrix and the batch's material color
+    pub fn new(model: Matrix4<f32>) -> Self {
+        Self::with_color(model, [1.0; 4])
+    }
 
-/// Creates a vertex buffer from a list of vertices
-pub fn create_vertex_buffer(
+    /// An instance with the given model matrix, tinting the batch's material color
+    pub fn with_color(model: Matrix4<f32>, color: [f32; 4]) -> Self {
+        Self {
+            instance_model: model.into(),
+            instance_color: color,
+        }
+    }
+}
+
+unsafe impl bytemuck::Pod for InstanceData {}
+unsafe impl bytemuck::Zeroable for InstanceData {}
+
+/// Uniform buffer object for the view and projection matrices
+///
+/// Generated from the `ubo` block in `shaders/shader.vert`, so its layout always matches the shader.
+pub use crate::shaders::vertex::vs::UniformBufferObject;
+
+/// Per-draw push constants holding the model matrix
+///
+/// Generated from the `pc` block in `shaders/shader.vert`.
+pub use crate::shaders::vertex::vs::PushConstants;
+
+/// Uniform block holding the frame's directional lights
+///
+/// Generated from the `lights` block in `shaders/shader.frag`.
+pub use crate::shaders::fragment::fs::LightBlock;
+
+/// Host-visible allocation used for buffers filled from the CPU
+fn upload_allocation() -> AllocationCreateInfo {
+    AllocationCreateInfo {
+        usage: MemoryUsage::Upload,
+        ..Default::default()
+    }
+}
+
+/// Creates a vertex buffer from a list of vertices of any vertex stream type
+pub fn create_vertex_buffer<V: BufferContents + Copy>(
     allocator: &StandardMemoryAllocator,
-    vertices: &[Vertex],
-) -> Result<Subbuffer<[Vertex]>> {
+    vertices: &[V],
+) -> Result<Subbuffer<[V]>> {
     let buffer = Buffer::from_iter(
         allocator,
-        BufferCreateInfo::default(),
-        AllocationCreateInfo::default(),
+        BufferCreateInfo {
+            usage: BufferUsage::VERTEX_BUFFER,
+            ..Default::default()
+        },
+        upload_allocation(),
         vertices.iter().cloned(),
     )?;
 
@@ -54,8 +102,11 @@ pub fn create_index_buffer(
 ) -> Result<Subbuffer<[u32]>> {
     let buffer = Buffer::from_iter(
         allocator,
-        BufferCreateInfo::default(),
-        AllocationCreateInfo::default(),
+        BufferCreateInfo {
+            usage: BufferUsage::INDEX_BUFFER,
+            ..Default::default()
+        },
+        upload_allocation(),
         indices.iter().cloned(),
     )?;
 
@@ -63,18 +114,46 @@ pub fn create_index_buffer(
     Ok(buffer)
 }
 
-/// Creates a uniform buffer for storing transformation matrices
+/// Creates a storage buffer that compute shaders can write
+///
+/// `extra_usage` adds further usages, e.g. `BufferUsage::VERTEX_BUFFER` when a compute pass
+/// generates vertices that are drawn later in the frame.
+pub fn create_storage_buffer<T>(
+    allocator: &StandardMemoryAllocator,
+    data: &[T],
+    extra_usage: BufferUsage,
+) -> Result<Subbuffer<[T]>>
+where
+    T: BufferContents + Copy,
+{
+    let buffer = Buffer::from_iter(
+        allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER | extra_usage,
+            ..Default::default()
+        },
+        upload_allocation(),
+        data.iter().copied(),
+    )?;
+
+    info!("Storage buffer created with {} elements", data.len());
+    Ok(buffer)
+}
+
+/// Creates a uniform buffer for storing the camera matrices
 pub fn create_uniform_buffer(
     allocator: &StandardMemoryAllocator,
 ) -> Result<Subbuffer<UniformBufferObject>> {
     let buffer = Buffer::from_data(
         allocator,
-        BufferCreateInfo::default(),
-        AllocationCreateInfo::default(),
+        BufferCreateInfo {
+            usage: BufferUsage::UNIFORM_BUFFER,
+            ..Default::default()
+        },
+        upload_allocation(),
         UniformBufferObject {
-            model: Matrix4::identity(),
-            view: Matrix4::identity(),
-            proj: Matrix4::identity(),
+            view: Matrix4::<f32>::identity().into(),
+            proj: Matrix4::<f32>::identity().into(),
         },
     )?;
 
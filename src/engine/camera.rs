@@ -0,0 +1,63 @@
+use nalgebra::{Isometry3, Matrix4, Point3, Vector3};
+
+use crate::engine::bounds::Frustum;
+
+/// A perspective camera placed in the world
+#[derive(Debug, Clone, Copy)]
+pub struct Camera {
+    /// Camera-to-world transform; the camera looks down its local -Z axis
+    pub transform: Isometry3<f32>,
+    /// Vertical field of view in radians
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Camera {
+    /// Creates a camera at `eye` looking at `target`
+    pub fn look_at(eye: Point3<f32>, target: Point3<f32>, up: Vector3<f32>) -> Self {
+        Self {
+            transform: Isometry3::look_at_rh(&eye, &target, &up).inverse(),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the camera position in world space
+    pub fn position(&self) -> Point3<f32> {
+        self.transform.translation.vector.into()
+    }
+
+    /// Returns the world-to-view matrix
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        self.transform.inverse().to_homogeneous()
+    }
+
+    /// Returns a Vulkan projection matrix (Y down, depth 0..1) for the given aspect ratio
+    pub fn projection_matrix(&self, aspect: f32) -> Matrix4<f32> {
+        let f = 1.0 / (self.fov_y / 2.0).tan();
+        let range = self.near - self.far;
+
+        Matrix4::new(
+            f / aspect, 0.0, 0.0, 0.0,
+            0.0, -f, 0.0, 0.0,
+            0.0, 0.0, self.far / range, self.near * self.far / range,
+            0.0, 0.0, -1.0, 0.0,
+        )
+    }
+
+    /// Returns the world-space frustum seen with the given aspect ratio
+    pub fn frustum(&self, aspect: f32) -> Frustum {
+        Frustum::from_matrix(&(self.projection_matrix(aspect) * self.view_matrix()))
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            transform: Isometry3::identity(),
+            fov_y: std::f32::consts::FRAC_PI_4,
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+}
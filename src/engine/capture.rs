@@ -0,0 +1,358 @@
+use anyhow::{anyhow, Result};
+use image::{DynamicImage, Rgba32FImage, RgbaImage};
+use log::info;
+use std::path::Path;
+use std::sync::Arc;
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::allocator::CommandBufferAllocator;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CopyImageToBufferInfo};
+use vulkano::format::Format;
+use vulkano::image::ImageAccess;
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator};
+
+/// Image file formats a captured frame can be saved as
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureFormat {
+    /// 8-bit sRGB with alpha
+    Png,
+    /// 8-bit sRGB without alpha
+    Jpeg,
+    /// 32-bit float linear RGBA, keeping HDR values above 1.0
+    Exr,
+}
+
+impl CaptureFormat {
+    /// Picks the format from a file extension
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+
+        match extension.as_deref() {
+            Some("png") => Ok(Self::Png),
+            Some("jpg" | "jpeg") => Ok(Self::Jpeg),
+            Some("exr") => Ok(Self::Exr),
+            _ => Err(anyhow!("Unsupported capture file type: {}", path.display())),
+        }
+    }
+}
+
+/// A host-visible buffer that an image is copied into for reading back on the CPU
+pub struct CaptureBuffer {
+    buffer: Subbuffer<[u8]>,
+    format: Format,
+    extent: [u32; 2],
+}
+
+impl CaptureBuffer {
+    /// Allocates a buffer large enough for an image of the given format and extent
+    pub fn new(allocator: &StandardMemoryAllocator, format: Format, extent: [u32; 2]) -> Result<Self> {
+        texel_layout(format)?;
+        let texel_size = format
+            .block_size()
+            .ok_or_else(|| anyhow!("Format {:?} has no texel size", format))?;
+        let size = texel_size * extent[0] as u64 * extent[1] as u64;
+
+        let buffer = Buffer::new_slice(
+            allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Download,
+                ..Default::default()
+            },
+            size,
+        )?;
+
+        Ok(Self {
+            buffer,
+            format,
+            extent,
+        })
+    }
+
+    /// Records a copy of the whole image into the buffer
+    pub fn record_copy<L, A>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+        image: Arc<dyn ImageAccess>,
+    ) -> Result<()>
+    where
+        A: CommandBufferAllocator,
+    {
+        builder.copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+            image,
+            self.buffer.clone(),
+        ))?;
+        Ok(())
+    }
+
+    /// Reads the copied texels back; the copy must have finished executing
+    pub fn read(&self) -> Result<CapturedImage> {
+        CapturedImage::from_raw(self.format, self.extent, self.buffer.read()?.to_vec())
+    }
+}
+
+/// Texels read back from the GPU, still in their Vulkan format
+pub struct CapturedImage {
+    data: Vec<u8>,
+    format: Format,
+    extent: [u32; 2],
+}
+
+impl CapturedImage {
+    /// Wraps tightly packed texels of a capturable format
+    pub fn from_raw(format: Format, extent: [u32; 2], data: Vec<u8>) -> Result<Self> {
+        let expected = texel_layout(format)?.size() * extent[0] as usize * extent[1] as usize;
+        if data.len() != expected {
+            return Err(anyhow!(
+                "Expected {} bytes for a {}x{} {:?} image, got {}",
+                expected,
+                extent[0],
+                extent[1],
+                format,
+                data.len()
+            ));
+        }
+        Ok(Self {
+            data,
+            format,
+            extent,
+        })
+    }
+
+    /// Returns the size in pixels
+    pub fn extent(&self) -> [u32; 2] {
+        self.extent
+    }
+
+    /// Converts to 8-bit sRGB-encoded RGBA
+    ///
+    /// 8-bit formats are stored as they are displayed and only reordered; float and depth
+    /// formats are linear and are clamped and encoded.
+    pub fn to_rgba8(&self) -> Result<RgbaImage> {
+        let encoded = self.texels()?.map(|(mut texel, linear)| {
+            if linear {
+                // Alpha is never sRGB encoded
+                for channel in &mut texel[..3] {
+                    *channel = linear_to_srgb(*channel);
+                }
+            }
+            texel.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8)
+        });
+        let data = encoded.flatten().collect();
+
+        RgbaImage::from_raw(self.extent[0], self.extent[1], data)
+            .ok_or_else(|| anyhow!("Captured data does not match its extent"))
+    }
+
+    /// Converts to linear 32-bit float RGBA, decoding sRGB-encoded 8-bit formats
+    pub fn to_rgba32f(&self) -> Result<Rgba32FImage> {
+        let linear = self.texels()?.map(|(mut texel, linear)| {
+            if !linear {
+                // Alpha is never sRGB encoded
+                for channel in &mut texel[..3] {
+                    *channel = srgb_to_linear(*channel);
+                }
+            }
+            texel
+        });
+        let data = linear.flatten().collect();
+
+        Rgba32FImage::from_raw(self.extent[0], self.extent[1], data)
+            .ok_or_else(|| anyhow!("Captured data does not match its extent"))
+    }
+
+    /// Saves the image, choosing PNG, JPEG or EXR from the file extension
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        match CaptureFormat::from_path(path)? {
+            CaptureFormat::Png => self.to_rgba8()?.save(path)?,
+            CaptureFormat::Jpeg => DynamicImage::ImageRgba8(self.to_rgba8()?).to_rgb8().save(path)?,
+            CaptureFormat::Exr => self.to_rgba32f()?.save(path)?,
+        }
+
+        info!(
+            "Captured {}x{} {:?} image to {}",
+            self.extent[0],
+            self.extent[1],
+            self.format,
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Decodes every texel to RGBA in storage order, with whether its values are linear
+    fn texels(&self) -> Result<impl Iterator<Item = ([f32; 4], bool)> + '_> {
+        let layout = texel_layout(self.format)?;
+        let size = layout.size();
+        Ok(self.data.chunks_exact(size).map(move |bytes| layout.decode(bytes)))
+    }
+}
+
+/// How the texels of a capturable format are laid out in memory
+#[derive(Clone, Copy)]
+enum TexelLayout {
+    Rgba8,
+    Bgra8,
+    Rgba16Float,
+    Rgba32Float,
+    Depth16,
+    Depth32Float,
+}
+
+impl TexelLayout {
+    /// Returns the size of one texel in bytes
+    fn size(self) -> usize {
+        match self {
+            Self::Rgba8 | Self::Bgra8 | Self::Depth32Float => 4,
+            Self::Rgba16Float => 8,
+            Self::Rgba32Float => 16,
+            Self::Depth16 => 2,
+        }
+    }
+
+    /// Decodes one texel to RGBA, with whether the values are linear rather than sRGB encoded
+    fn decode(self, bytes: &[u8]) -> ([f32; 4], bool) {
+        let unorm8 = |index: usize| bytes[index] as f32 / 255.0;
+        let sfloat16 =
+            |index: usize| half_to_f32(u16::from_le_bytes([bytes[index], bytes[index + 1]]));
+        let sfloat32 = |index: usize| {
+            f32::from_le_bytes([bytes[index], bytes[index + 1], bytes[index + 2], bytes[index + 3]])
+        };
+
+        match self {
+            Self::Rgba8 => ([unorm8(0), unorm8(1), unorm8(2), unorm8(3)], false),
+            Self::Bgra8 => ([unorm8(2), unorm8(1), unorm8(0), unorm8(3)], false),
+            Self::Rgba16Float => ([sfloat16(0), sfloat16(2), sfloat16(4), sfloat16(6)], true),
+            Self::Rgba32Float => ([sfloat32(0), sfloat32(4), sfloat32(8), sfloat32(12)], true),
+            Self::Depth16 => {
+                let depth = u16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 65535.0;
+                ([depth, depth, depth, 1.0], true)
+            }
+            Self::Depth32Float => {
+                let depth = sfloat32(0);
+                ([depth, depth, depth, 1.0], true)
+            }
+        }
+    }
+}
+
+/// Returns the texel layout of a format that can be captured
+fn texel_layout(format: Format) -> Result<TexelLayout> {
+    match format {
+        Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB => Ok(TexelLayout::Rgba8),
+        Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB => Ok(TexelLayout::Bgra8),
+        Format::R16G16B16A16_SFLOAT => Ok(TexelLayout::Rgba16Float),
+        Format::R32G32B32A32_SFLOAT => Ok(TexelLayout::Rgba32Float),
+        Format::D16_UNORM => Ok(TexelLayout::Depth16),
+        Format::D32_SFLOAT => Ok(TexelLayout::Depth32Float),
+        _ => Err(anyhow!("Capturing {:?} images is not supported", format)),
+    }
+}
+
+/// Converts an sRGB-encoded value to linear
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a linear value to sRGB encoding
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Converts an IEEE 754 half-precision float to single precision
+fn half_to_f32(bits: u16) -> f32 {
+    let sign = ((bits >> 15) as u32) << 31;
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+
+    let bits = match (exponent, mantissa) {
+        (0, 0) => sign,
+        // Subnormal halves are normal singles; renormalize the mantissa
+        (0, _) => {
+            let shift = mantissa.leading_zeros() - 21;
+            let mantissa = (mantissa << shift) & 0x3ff;
+            sign | ((127 - 15 + 1 - shift) << 23) | (mantissa << 13)
+        }
+        (0x1f, _) => sign | 0x7f80_0000 | (mantissa << 13),
+        _ => sign | ((exponent + 127 - 15) << 23) | (mantissa << 13),
+    };
+    f32::from_bits(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_zero_keeps_its_sign() {
+        assert_eq!(half_to_f32(0x0000), 0.0);
+        assert!(half_to_f32(0x0000).is_sign_positive());
+        assert!(half_to_f32(0x8000).is_sign_negative());
+        assert_eq!(half_to_f32(0x3c00), 1.0);
+        assert_eq!(half_to_f32(0xc000), -2.0);
+    }
+
+    #[test]
+    fn half_subnormals_are_renormalized() {
+        assert_eq!(half_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(half_to_f32(0x03ff), 1023.0 / 1024.0 * 2f32.powi(-14));
+        assert_eq!(half_to_f32(0x8200), -2f32.powi(-15));
+    }
+
+    #[test]
+    fn half_infinity_and_nan() {
+        assert_eq!(half_to_f32(0x7c00), f32::INFINITY);
+        assert_eq!(half_to_f32(0xfc00), f32::NEG_INFINITY);
+        assert!(half_to_f32(0x7e00).is_nan());
+        assert!(half_to_f32(0x7c01).is_nan());
+    }
+
+    #[test]
+    fn bgra_is_swizzled_to_rgba() {
+        let data = vec![10, 20, 30, 40, 50, 60, 70, 80];
+        let image = CapturedImage::from_raw(Format::B8G8R8A8_UNORM, [2, 1], data).unwrap();
+        assert_eq!(image.to_rgba8().unwrap().into_raw(), [30, 20, 10, 40, 70, 60, 50, 80]);
+    }
+
+    #[test]
+    fn srgb_encoding_round_trips() {
+        for value in 0..=255u8 {
+            let encoded = value as f32 / 255.0;
+            let round_trip = linear_to_srgb(srgb_to_linear(encoded));
+            assert!((round_trip - encoded).abs() < 1e-5, "{} -> {}", encoded, round_trip);
+        }
+
+        // Decoding to linear floats and encoding back restores the 8-bit values
+        let data: Vec<u8> = (0..=255).collect();
+        let image = CapturedImage::from_raw(Format::R8G8B8A8_SRGB, [64, 1], data.clone()).unwrap();
+        let linear = image.to_rgba32f().unwrap();
+        let bytes = linear.into_raw().iter().flat_map(|value| value.to_le_bytes()).collect();
+        let linear = CapturedImage::from_raw(Format::R32G32B32A32_SFLOAT, [64, 1], bytes).unwrap();
+        assert_eq!(linear.to_rgba8().unwrap().into_raw(), data);
+    }
+
+    #[test]
+    fn raw_data_must_match_the_extent() {
+        let error = CapturedImage::from_raw(Format::R8G8B8A8_UNORM, [2, 2], vec![0; 15]);
+        assert!(error.is_err());
+        assert!(CapturedImage::from_raw(Format::R8G8B8A8_UNORM, [2, 2], vec![0; 16]).is_ok());
+        assert!(CapturedImage::from_raw(Format::R8_UNORM, [1, 1], vec![0]).is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use nalgebra::Vector3;
+use std::sync::Arc;
+
+use crate::engine::ecs::{Entity, World};
+use crate::engine::lod::{screen_size, LodMesh};
+use crate::engine::mesh::Mesh;
+use crate::engine::render_queue::{DrawItem, Material, RenderQueue};
+
+pub use crate::engine::camera::Camera;
+pub use crate::engine::render_queue::Light;
+pub use crate::engine::scene::Transform;
+
+/// Draws a mesh at the entity's `Transform`
+#[derive(Clone)]
+pub struct MeshRenderer {
+    pub mesh: Arc<Mesh>,
+    pub material: Material,
+}
+
+/// Draws the level of a LOD mesh matching the entity's size on screen
+#[derive(Clone)]
+pub struct LodRenderer {
+    pub mesh: Arc<LodMesh>,
+    pub material: Material,
+    /// Level drawn, updated by `select_lods`
+    pub level: usize,
+}
+
+impl LodRenderer {
+    /// Starts at the most detailed level
+    pub fn new(mesh: Arc<LodMesh>, material: Material) -> Self {
+        Self {
+            mesh,
+            material,
+            level: 0,
+        }
+    }
+}
+
+/// Returns the first camera of a world, placed by its `Transform` if it has one
+fn active_camera(world: &World) -> Option<Camera> {
+    let (entity, camera) = world.query::<Camera>().next()?;
+    let mut camera = *camera;
+    if let Some(transform) = world.get::<Transform>(entity) {
+        camera.transform = transform.isometry;
+    }
+    Some(camera)
+}
+
+/// Picks the level of every `LodRenderer` from its screen size seen by the world's camera
+///
+/// Does nothing without a camera.
+pub fn select_lods(world: &mut World) {
+    let Some(camera) = active_camera(world) else {
+        return;
+    };
+
+    let levels: Vec<(Entity, usize)> = world
+        .query::<LodRenderer>()
+        .filter_map(|(entity, renderer)| {
+            let model = world.get::<Transform>(entity)?.matrix();
+            let size = screen_size(renderer.mesh.mesh(renderer.level), &model, &camera);
+            Some((entity, renderer.mesh.select(renderer.level, size)))
+        })
+        .collect();
+
+    for (entity, level) in levels {
+        if let Some(renderer) = world.get_mut::<LodRenderer>(entity) {
+            renderer.level = level;
+        }
+    }
+}
+
+/// Fills a render queue from the renderable components of a world
+///
+/// Every entity with both a `Transform` and a `MeshRenderer` or `LodRenderer` becomes a draw.
+/// The first entity with a `Camera` provides the view, placed by its `Transform` if it has one;
+/// without any camera the queue keeps its current one. Entities with a `Light` are added as
+/// lights, aimed along their `Transform`'s -Z axis if they have one.
+pub fn extract_render_list(world: &World, queue: &mut RenderQueue) {
+    queue.clear();
+
+    if let Some(camera) = active_camera(world) {
+        queue.camera = camera;
+    }
+
+    for (entity, light) in world.query::<Light>() {
+        let mut light = *light;
+        if let Some(transform) = world.get::<Transform>(entity) {
+            light.direction = transform.isometry.rotation * -Vector3::z();
+        }
+        queue.push_light(light);
+    }
+
+    for (entity, renderer) in world.query::<MeshRenderer>() {
+        if let Some(transform) = world.get::<Transform>(entity) {
+            queue.push(DrawItem {
+                mesh: renderer.mesh.clone(),
+                model: transform.matrix(),
+                material: renderer.material,
+            });
+        }
+    }
+
+    for (entity, renderer) in world.query::<LodRenderer>() {
+        if let Some(transform) = world.get::<Transform>(entity) {
+            queue.push(DrawItem {
+                mesh: renderer.mesh.mesh(renderer.level).clone(),
+                model: transform.matrix(),
+                material: renderer.material,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::{Point3, UnitQuaternion};
+    use std::f32::consts::FRAC_PI_2;
+
+    #[test]
+    fn render_list_takes_the_camera_and_lights_from_the_world() {
+        let mut world = World::new();
+        let camera = world.spawn();
+        world.insert(camera, Camera::default());
+        let placed = Transform::from_translation(Vector3::new(1.0, 2.0, 3.0));
+        world.insert(camera, placed);
+
+        // An aimed light, a light keeping its own direction and a despawned light
+        let aimed = world.spawn();
+        world.insert(aimed, Light::default());
+        let down = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), -FRAC_PI_2);
+        world.insert(aimed, Transform::identity().with_rotation(down));
+        let fixed = world.spawn();
+        let fixed_light = Light {
+            direction: Vector3::x(),
+            ..Light::default()
+        };
+        world.insert(fixed, fixed_light);
+        let removed = world.spawn();
+        world.insert(removed, Light::default());
+        world.despawn(removed);
+
+        let mut queue = RenderQueue::new(Camera::look_at(
+            Point3::new(0.0, 0.0, 5.0),
+            Point3::origin(),
+            Vector3::y(),
+        ));
+        queue.push_light(Light::default());
+        extract_render_list(&world, &mut queue);
+
+        assert_eq!(queue.camera.transform, placed.isometry);
+        assert_eq!(queue.lights().len(), 2);
+        assert!((queue.lights()[0].direction - -Vector3::y()).norm() < 1e-6);
+        assert_eq!(queue.lights()[1], fixed_light);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn render_list_keeps_the_queue_camera_without_a_camera_entity() {
+        let world = World::new();
+        let camera = Camera::look_at(Point3::new(0.0, 0.0, 5.0), Point3::origin(), Vector3::y());
+        let mut queue = RenderQueue::new(camera);
+        queue.push_light(Light::default());
+        extract_render_list(&world, &mut queue);
+
+        assert_eq!(queue.camera.transform, camera.transform);
+        assert!(queue.lights().is_empty());
+    }
+}
@@ -0,0 +1,248 @@
+use anyhow::{anyhow, Result};
+use log::info;
+use std::sync::Arc;
+use vulkano::buffer::{BufferContents, Subbuffer};
+use vulkano::command_buffer::allocator::{CommandBufferAllocator, StandardCommandBufferAllocator};
+use vulkano::command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::device::{Device, Queue};
+use vulkano::image::view::ImageViewAbstract;
+use vulkano::pipeline::cache::PipelineCache;
+use vulkano::pipeline::{ComputePipeline as VkComputePipeline, Pipeline, PipelineBindPoint};
+use vulkano::sampler::Sampler;
+use vulkano::shader::{ShaderModule, SpecializationConstants};
+use vulkano::sync::GpuFuture;
+
+use crate::engine::constants::{validate_push_constants, validate_specialization_constants};
+use crate::engine::reflection::create_pipeline_layout;
+
+/// A resource bound to a compute shader binding
+pub enum ComputeBinding {
+    /// A storage buffer (`buffer` block) at the given binding
+    StorageBuffer(u32, Subbuffer<[u8]>),
+    /// A uniform buffer (`uniform` block) at the given binding
+    UniformBuffer(u32, Subbuffer<[u8]>),
+    /// A storage image (`image2D` etc.) at the given binding
+    StorageImage(u32, Arc<dyn ImageViewAbstract>),
+    /// A combined image sampler (`sampler2D` etc.) at the given binding
+    SampledImage(u32, Arc<dyn ImageViewAbstract>, Arc<Sampler>),
+}
+
+impl ComputeBinding {
+    /// Binds any typed buffer as a storage buffer
+    pub fn storage_buffer<T: ?Sized>(binding: u32, buffer: Subbuffer<T>) -> Self {
+        ComputeBinding::StorageBuffer(binding, buffer.into_bytes())
+    }
+
+    /// Binds any typed buffer as a uniform buffer
+    pub fn uniform_buffer<T: ?Sized>(binding: u32, buffer: Subbuffer<T>) -> Self {
+        ComputeBinding::UniformBuffer(binding, buffer.into_bytes())
+    }
+
+    /// Binds an image view as a storage image
+    pub fn storage_image(binding: u32, view: Arc<dyn ImageViewAbstract>) -> Self {
+        ComputeBinding::StorageImage(binding, view)
+    }
+
+    /// Binds an image view with a sampler as a combined image sampler
+    pub fn sampled_image(
+        binding: u32,
+        view: Arc<dyn ImageViewAbstract>,
+        sampler: Arc<Sampler>,
+    ) -> Self {
+        ComputeBinding::SampledImage(binding, view, sampler)
+    }
+
+    fn into_write(self) -> WriteDescriptorSet {
+        match self {
+            ComputeBinding::StorageBuffer(binding, buffer)
+            | ComputeBinding::UniformBuffer(binding, buffer) => {
+                WriteDescriptorSet::buffer(binding, buffer)
+            }
+            ComputeBinding::StorageImage(binding, view) => {
+                WriteDescriptorSet::image_view(binding, view)
+            }
+            ComputeBinding::SampledImage(binding, view, sampler) => {
+                WriteDescriptorSet::image_view_sampler(binding, view, sampler)
+            }
+        }
+    }
+}
+
+/// A compute pipeline with a layout reflected from its shader
+pub struct ComputePipeline {
+    pipeline: Arc<VkComputePipeline>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+}
+
+impl ComputePipeline {
+    /// Creates a compute pipeline from a shader module and entry point name
+    pub fn new(
+        device: Arc<Device>,
+        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+        shader: Arc<ShaderModule>,
+        entry_point: &str,
+        cache: Option<Arc<PipelineCache>>,
+    ) -> Result<Arc<Self>> {
+        Self::new_specialized(
+            device,
+            descriptor_set_allocator,
+            shader,
+            entry_point,
+            &(),
+            cache,
+        )
+    }
+
+    /// Creates a compute pipeline with specialization constant values, e.g. the workgroup size
+    pub fn new_specialized<Css: SpecializationConstants>(
+        device: Arc<Device>,
+        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+        shader: Arc<ShaderModule>,
+        entry_point: &str,
+        constants: &Css,
+        cache: Option<Arc<PipelineCache>>,
+    ) -> Result<Arc<Self>> {
+        let entry = shader
+            .entry_point(entry_point)
+            .ok_or_else(|| anyhow!("Compute shader has no `{}` entry point", entry_point))?;
+
+        validate_specialization_constants::<Css>(&entry)?;
+
+        // Derive the layout from the shader's descriptor and push constant requirements
+        let layout = create_pipeline_layout(device.clone(), &[&entry])?;
+
+        let pipeline =
+            VkComputePipeline::with_pipeline_layout(device, entry, constants, layout, cache)?;
+
+        info!("Compute pipeline created successfully");
+        Ok(Arc::new(Self {
+            pipeline,
+            descriptor_set_allocator,
+        }))
+    }
+
+    /// Returns the underlying vulkano pipeline
+    pub fn pipeline(&self) -> &Arc<VkComputePipeline> {
+        &self.pipeline
+    }
+
+    /// Creates a descriptor set for the given set index from a list of bindings
+    pub fn create_descriptor_set(
+        &self,
+        set: u32,
+        bindings: impl IntoIterator<Item = ComputeBinding>,
+    ) -> Result<Arc<PersistentDescriptorSet>> {
+        let layout = self
+            .pipeline
+            .layout()
+            .set_layouts()
+            .get(set as usize)
+            .ok_or_else(|| anyhow!("Compute shader declares no descriptor set {}", set))?
+            .clone();
+
+        let descriptor_set = PersistentDescriptorSet::new(
+            &*self.descriptor_set_allocator,
+            layout,
+            bindings.into_iter().map(ComputeBinding::into_write),
+        )?;
+
+        Ok(descriptor_set)
+    }
+
+    /// Prepares a dispatch of this pipeline with the given descriptor sets
+    pub fn dispatch_with(
+        self: &Arc<Self>,
+        group_counts: [u32; 3],
+        descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
+    ) -> ComputeDispatch {
+        ComputeDispatch {
+            pipeline: self.clone(),
+            descriptor_sets,
+            push_constants: None,
+            group_counts,
+        }
+    }
+}
+
+/// A recorded-later compute dispatch: pipeline, descriptor sets and workgroup counts
+///
+/// Recording several dispatches and draws into the same command buffer lets vulkano insert the
+/// pipeline barriers between a compute write and a later vertex or texture read.
+#[derive(Clone)]
+pub struct ComputeDispatch {
+    pipeline: Arc<ComputePipeline>,
+    descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
+    push_constants: Option<Vec<u32>>,
+    group_counts: [u32; 3],
+}
+
+impl ComputeDispatch {
+    /// Attaches push constants to the dispatch, validated against the shader's declared range
+    pub fn with_push_constants<T>(mut self, data: T) -> Result<Self>
+    where
+        T: BufferContents + bytemuck::Pod,
+    {
+        validate_push_constants::<T>(self.pipeline.pipeline().layout(), 0)?;
+        self.push_constants = Some(
+            bytemuck::bytes_of(&data)
+                .chunks_exact(4)
+                .map(|word| u32::from_ne_bytes([word[0], word[1], word[2], word[3]]))
+                .collect(),
+        );
+        Ok(self)
+    }
+
+    /// Records the dispatch into an existing command buffer, such as the frame's
+    pub fn record<L, A>(&self, builder: &mut AutoCommandBufferBuilder<L, A>) -> Result<()>
+    where
+        A: CommandBufferAllocator,
+    {
+        let pipeline = self.pipeline.pipeline();
+
+        builder.bind_pipeline_compute(pipeline.clone());
+        if !self.descriptor_sets.is_empty() {
+            builder.bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                pipeline.layout().clone(),
+                0,
+                self.descriptor_sets.clone(),
+            );
+        }
+        if let Some(push_constants) = &self.push_constants {
+            // The data is type-erased, so push it one validated word at a time
+            for (index, word) in push_constants.iter().enumerate() {
+                builder.push_constants(pipeline.layout().clone(), index as u32 * 4, *word);
+            }
+        }
+        builder.dispatch(self.group_counts)?;
+
+        Ok(())
+    }
+
+    /// Records the dispatch into its own command buffer and submits it after `after`
+    ///
+    /// The returned future must be joined with (or waited on by) any work that reads the results,
+    /// for example by passing it to `Renderer::wait_for`.
+    pub fn submit(
+        &self,
+        queue: Arc<Queue>,
+        command_buffer_allocator: &StandardCommandBufferAllocator,
+        after: Box<dyn GpuFuture>,
+    ) -> Result<Box<dyn GpuFuture>> {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            command_buffer_allocator,
+            queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        self.record(&mut builder)?;
+        let command_buffer = builder.build()?;
+
+        let future = after
+            .then_execute(queue, command_buffer)?
+            .then_signal_semaphore_and_flush()?;
+
+        Ok(future.boxed())
+    }
+}
@@ -0,0 +1,176 @@
+use anyhow::{anyhow, Result};
+use log::warn;
+use std::mem::size_of;
+use std::sync::Arc;
+use vulkano::buffer::BufferContents;
+use vulkano::command_buffer::allocator::CommandBufferAllocator;
+use vulkano::command_buffer::AutoCommandBufferBuilder;
+use vulkano::pipeline::layout::PushConstantRange;
+use vulkano::pipeline::PipelineLayout;
+use vulkano::shader::{EntryPoint, ShaderStages, SpecializationConstants};
+
+/// Checks a specialization constant type against the constants declared by a shader entry point
+///
+/// Every constant the shader declares must be present in `S` with the same size. Entries in `S`
+/// that the shader does not declare are ignored by Vulkan, so they only produce a warning.
+pub fn validate_specialization_constants<S: SpecializationConstants>(
+    entry_point: &EntryPoint,
+) -> Result<()> {
+    let entries = S::descriptors();
+
+    for (constant_id, requirements) in entry_point.specialization_constant_requirements() {
+        let entry = entries
+            .iter()
+            .find(|entry| entry.constant_id == constant_id)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Shader declares specialization constant {} but no value was supplied",
+                    constant_id
+                )
+            })?;
+
+        if entry.size as u64 != requirements.size {
+            return Err(anyhow!(
+                "Specialization constant {} is {} bytes in the shader but {} bytes in the supplied values",
+                constant_id,
+                requirements.size,
+                entry.size
+            ));
+        }
+    }
+
+    for entry in entries {
+        if !entry_point
+            .specialization_constant_requirements()
+            .any(|(constant_id, _)| constant_id == entry.constant_id)
+        {
+            warn!(
+                "Specialization constant {} is not declared by the shader and will be ignored",
+                entry.constant_id
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that a push constant block of type `T` at `offset` lies within the layout's ranges
+///
+/// Returns the stages that see the block. Vulkan requires every one of them to have a single
+/// range covering the whole block; partial ranges that only cover it together are rejected.
+pub fn validate_push_constants<T: BufferContents>(
+    layout: &PipelineLayout,
+    offset: u32,
+) -> Result<ShaderStages> {
+    push_constant_stages(layout.push_constant_ranges(), offset, size_of::<T>() as u32)
+}
+
+/// Returns the stages whose ranges overlap `offset..offset + size`, if each has one range
+/// covering all of it
+fn push_constant_stages(
+    ranges: &[PushConstantRange],
+    offset: u32,
+    size: u32,
+) -> Result<ShaderStages> {
+    let end = offset + size;
+
+    if !offset.is_multiple_of(4) || !size.is_multiple_of(4) {
+        return Err(anyhow!(
+            "Push constants must be 4-byte aligned (offset {}, size {})",
+            offset,
+            size
+        ));
+    }
+
+    let mut stages = ShaderStages::empty();
+    let mut covering = ShaderStages::empty();
+    for range in ranges {
+        let range_end = range.offset + range.size;
+        if range.offset < end && range_end > offset {
+            stages |= range.stages;
+        }
+        if range.offset <= offset && range_end >= end {
+            covering |= range.stages;
+        }
+    }
+
+    if stages.is_empty() || !covering.contains(stages) {
+        return Err(anyhow!(
+            "Push constants at bytes {}..{} are not covered by one range for stages {:?} \
+             (ranges: {:?})",
+            offset,
+            end,
+            stages.difference(covering),
+            ranges
+        ));
+    }
+
+    Ok(stages)
+}
+
+/// Records push constants after validating them against the pipeline layout
+///
+/// Vulkano derives the stage flags of each pushed range from the layout itself.
+pub fn push_constants<T, L, A>(
+    builder: &mut AutoCommandBufferBuilder<L, A>,
+    layout: &Arc<PipelineLayout>,
+    offset: u32,
+    data: T,
+) -> Result<()>
+where
+    T: BufferContents,
+    A: CommandBufferAllocator,
+{
+    validate_push_constants::<T>(layout, offset)?;
+    builder.push_constants(layout.clone(), offset, data);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(stages: ShaderStages, offset: u32, size: u32) -> PushConstantRange {
+        PushConstantRange {
+            stages,
+            offset,
+            size,
+        }
+    }
+
+    #[test]
+    fn block_inside_one_range_returns_its_stages() {
+        let ranges = [range(ShaderStages::VERTEX | ShaderStages::FRAGMENT, 0, 64)];
+        let stages = push_constant_stages(&ranges, 16, 16).unwrap();
+        assert_eq!(stages, ShaderStages::VERTEX | ShaderStages::FRAGMENT);
+    }
+
+    #[test]
+    fn union_of_partial_ranges_is_rejected() {
+        let ranges = [
+            range(ShaderStages::VERTEX, 0, 16),
+            range(ShaderStages::VERTEX, 16, 16),
+        ];
+        assert!(push_constant_stages(&ranges, 0, 32).is_err());
+    }
+
+    #[test]
+    fn every_stage_seeing_the_block_needs_a_covering_range() {
+        let ranges = [
+            range(ShaderStages::VERTEX, 0, 32),
+            range(ShaderStages::FRAGMENT, 16, 16),
+        ];
+        assert!(push_constant_stages(&ranges, 0, 32).is_err());
+        assert_eq!(push_constant_stages(&ranges, 0, 16).unwrap(), ShaderStages::VERTEX);
+        let both = push_constant_stages(&ranges, 16, 16).unwrap();
+        assert_eq!(both, ShaderStages::VERTEX | ShaderStages::FRAGMENT);
+    }
+
+    #[test]
+    fn uncovered_or_misaligned_blocks_are_rejected() {
+        let ranges = [range(ShaderStages::COMPUTE, 0, 16)];
+        assert!(push_constant_stages(&ranges, 16, 16).is_err());
+        assert!(push_constant_stages(&ranges, 2, 4).is_err());
+        assert!(push_constant_stages(&ranges, 0, 6).is_err());
+    }
+}
@@ -0,0 +1,323 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+/// Handle to an entity; stale handles of despawned entities never match a new entity
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+/// Type-erased access to a component storage, so despawning can clear every component type
+trait AnyStorage: Any {
+    fn remove_entity(&mut self, entity: Entity);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+/// Sparse set of one component type: dense arrays for iteration, indexed by entity
+struct Storage<T> {
+    sparse: Vec<Option<usize>>,
+    entities: Vec<Entity>,
+    components: Vec<T>,
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Self {
+        Self {
+            sparse: Vec::new(),
+            entities: Vec::new(),
+            components: Vec::new(),
+        }
+    }
+}
+
+impl<T> Storage<T> {
+    /// Returns the dense index of an entity's component, checking its generation
+    fn dense_index(&self, entity: Entity) -> Option<usize> {
+        let dense = (*self.sparse.get(entity.index as usize)?)?;
+        (self.entities[dense] == entity).then_some(dense)
+    }
+
+    /// Inserts or replaces a component, returning the previous one
+    fn insert(&mut self, entity: Entity, component: T) -> Option<T> {
+        if let Some(dense) = self.dense_index(entity) {
+            return Some(std::mem::replace(&mut self.components[dense], component));
+        }
+
+        let index = entity.index as usize;
+        if self.sparse.len() <= index {
+            self.sparse.resize(index + 1, None);
+        }
+        self.sparse[index] = Some(self.components.len());
+        self.entities.push(entity);
+        self.components.push(component);
+        None
+    }
+
+    /// Removes an entity's component, if it has one
+    fn remove(&mut self, entity: Entity) -> Option<T> {
+        let dense = self.dense_index(entity)?;
+        Some(self.swap_remove(dense))
+    }
+
+    /// Removes the component at a dense index, moving the last one into its place
+    fn swap_remove(&mut self, dense: usize) -> T {
+        let removed = self.entities.swap_remove(dense);
+        self.sparse[removed.index as usize] = None;
+        if let Some(moved) = self.entities.get(dense) {
+            self.sparse[moved.index as usize] = Some(dense);
+        }
+        self.components.swap_remove(dense)
+    }
+}
+
+impl<T: 'static> AnyStorage for Storage<T> {
+    fn remove_entity(&mut self, entity: Entity) {
+        self.remove(entity);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// A minimal entity-component store
+///
+/// Any `'static` type can be a component. Each component type lives in its own sparse set,
+/// so iterating one type is a linear walk and lookups by entity are constant time.
+#[derive(Default)]
+pub struct World {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+}
+
+impl World {
+    /// Creates an empty world
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an entity without components
+    pub fn spawn(&mut self) -> Entity {
+        match self.free.pop() {
+            Some(index) => {
+                self.alive[index as usize] = true;
+                Entity {
+                    index,
+                    generation: self.generations[index as usize],
+                }
+            }
+            None => {
+                self.generations.push(0);
+                self.alive.push(true);
+                Entity {
+                    index: self.generations.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    /// Removes an entity and all of its components; returns whether it was alive
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+
+        for storage in self.storages.values_mut() {
+            storage.remove_entity(entity);
+        }
+        let index = entity.index as usize;
+        self.alive[index] = false;
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.free.push(entity.index);
+        true
+    }
+
+    /// Returns whether the entity has been spawned and not despawned
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        let index = entity.index as usize;
+        self.alive.get(index) == Some(&true) && self.generations[index] == entity.generation
+    }
+
+    /// Returns the number of live entities
+    pub fn len(&self) -> usize {
+        self.alive.iter().filter(|&&alive| alive).count()
+    }
+
+    /// Returns whether there are no live entities
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds or replaces a component; returns the replaced one
+    ///
+    /// Components added to dead entities are dropped.
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) -> Option<T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.storage_mut::<T>().insert(entity, component)
+    }
+
+    /// Removes and returns a component
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        self.storages
+            .get_mut(&TypeId::of::<T>())?
+            .as_any_mut()
+            .downcast_mut::<Storage<T>>()?
+            .remove(entity)
+    }
+
+    /// Returns a component of an entity
+    pub fn get<T: 'static>(&self, entity: Entity) -> Option<&T> {
+        let storage = self.storage::<T>()?;
+        storage
+            .dense_index(entity)
+            .map(|dense| &storage.components[dense])
+    }
+
+    /// Returns a component of an entity for modification
+    pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
+        let storage = self
+            .storages
+            .get_mut(&TypeId::of::<T>())?
+            .as_any_mut()
+            .downcast_mut::<Storage<T>>()?;
+        let dense = storage.dense_index(entity)?;
+        Some(&mut storage.components[dense])
+    }
+
+    /// Iterates every entity with a component of type `T`
+    pub fn query<T: 'static>(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.storage::<T>()
+            .into_iter()
+            .flat_map(|storage| storage.entities.iter().copied().zip(&storage.components))
+    }
+
+    /// Iterates every entity with a component of type `T`, for modification
+    pub fn query_mut<T: 'static>(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.storages
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any_mut().downcast_mut::<Storage<T>>())
+            .into_iter()
+            .flat_map(|storage| storage.entities.iter().copied().zip(&mut storage.components))
+    }
+
+    fn storage<T: 'static>(&self) -> Option<&Storage<T>> {
+        self.storages
+            .get(&TypeId::of::<T>())?
+            .as_any()
+            .downcast_ref::<Storage<T>>()
+    }
+
+    fn storage_mut<T: 'static>(&mut self) -> &mut Storage<T> {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::<Storage<T>>::default())
+            .as_any_mut()
+            .downcast_mut::<Storage<T>>()
+            .expect("storage is keyed by its component type")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that the sparse array points at every dense entry and nothing else
+    fn assert_consistent<T: 'static>(world: &World) {
+        let Some(storage) = world.storage::<T>() else {
+            return;
+        };
+        assert_eq!(storage.entities.len(), storage.components.len());
+        for (dense, entity) in storage.entities.iter().enumerate() {
+            assert_eq!(storage.sparse[entity.index as usize], Some(dense));
+        }
+        let mapped = storage.sparse.iter().flatten().count();
+        assert_eq!(mapped, storage.entities.len());
+    }
+
+    #[test]
+    fn despawn_removes_the_entity_and_its_components() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        let other = world.spawn();
+        world.insert(entity, 1u32);
+        world.insert(entity, "name");
+        world.insert(other, 2u32);
+
+        assert!(world.despawn(entity));
+        assert!(!world.is_alive(entity));
+        assert!(!world.despawn(entity));
+        assert_eq!(world.len(), 1);
+        assert_eq!(world.get::<u32>(entity), None);
+        assert_eq!(world.get::<&str>(entity), None);
+        assert_eq!(world.get::<u32>(other), Some(&2));
+        assert_eq!(world.query::<u32>().count(), 1);
+
+        // Components cannot be added to a dead entity
+        assert_eq!(world.insert(entity, 3u32), None);
+        assert_eq!(world.get::<u32>(entity), None);
+    }
+
+    #[test]
+    fn reused_slots_get_a_new_generation() {
+        let mut world = World::new();
+        let old = world.spawn();
+        world.insert(old, 1u32);
+        world.despawn(old);
+
+        let new = world.spawn();
+        assert_eq!(new.index, old.index);
+        assert_ne!(new.generation, old.generation);
+        assert!(world.is_alive(new));
+        assert!(!world.is_alive(old));
+
+        // The stale handle neither sees nor changes the new entity's components
+        world.insert(new, 2u32);
+        assert_eq!(world.get::<u32>(old), None);
+        assert_eq!(world.remove::<u32>(old), None);
+        assert!(!world.despawn(old));
+        assert_eq!(world.get::<u32>(new), Some(&2));
+    }
+
+    #[test]
+    fn swap_remove_keeps_sparse_and_dense_arrays_consistent() {
+        let mut world = World::new();
+        let entities: Vec<Entity> = (0..6).map(|_| world.spawn()).collect();
+        for (value, &entity) in entities.iter().enumerate() {
+            world.insert(entity, value);
+        }
+
+        // Remove from the front, the middle and the end, moving other entries around
+        assert_eq!(world.remove::<usize>(entities[0]), Some(0));
+        assert_consistent::<usize>(&world);
+        world.despawn(entities[3]);
+        assert_consistent::<usize>(&world);
+        assert_eq!(world.remove::<usize>(entities[5]), Some(5));
+        assert_consistent::<usize>(&world);
+
+        for (value, &entity) in entities.iter().enumerate() {
+            let expected = [1, 2, 4].contains(&value).then_some(value);
+            assert_eq!(world.get::<usize>(entity).copied(), expected);
+        }
+
+        // Replacing keeps the entry in place
+        assert_eq!(world.insert(entities[2], 20usize), Some(2));
+        if let Some(value) = world.get_mut::<usize>(entities[4]) {
+            *value = 40;
+        }
+        assert_consistent::<usize>(&world);
+        let mut values: Vec<usize> = world.query::<usize>().map(|(_, &value)| value).collect();
+        values.sort_unstable();
+        assert_eq!(values, [1, 20, 40]);
+    }
+}
@@ -0,0 +1,534 @@
+use anyhow::{anyhow, Result};
+use log::info;
+use nalgebra::Matrix4;
+use std::sync::Arc;
+use vulkano::buffer::allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo};
+use vulkano::buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer};
+use vulkano::command_buffer::allocator::CommandBufferAllocator;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, ClearColorImageInfo, CopyBufferInfo, DrawIndexedIndirectCommand,
+};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::device::Device;
+use vulkano::format::{ClearColorValue, Format};
+use vulkano::image::view::{ImageView, ImageViewAbstract, ImageViewCreateInfo};
+use vulkano::image::{
+    ImageAccess, ImageCreateFlags, ImageDimensions, ImageLayout, ImageSubresourceRange,
+    ImageUsage, ImmutableImage, MipmapsCount,
+};
+use vulkano::memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator};
+use vulkano::pipeline::cache::PipelineCache;
+use vulkano::pipeline::PipelineLayout;
+use vulkano::sampler::{
+    Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode, LOD_CLAMP_NONE,
+};
+
+use crate::engine::buffer::{InstanceData, PushConstants, Vertex};
+use crate::engine::camera::Camera;
+use crate::engine::compute::{ComputeBinding, ComputeDispatch, ComputePipeline};
+use crate::engine::constants::push_constants;
+use crate::engine::mesh::Mesh;
+use crate::engine::pipeline::VertexLayout;
+use crate::engine::render_queue::{InstanceBatch, Material};
+use crate::shaders::gpu_cull::cs::CullParams;
+
+/// Objects culled by one workgroup of `shaders/gpu_cull.comp`
+const CULL_WORKGROUP_SIZE: u32 = 64;
+
+/// Texels per axis reduced by one workgroup of `shaders/hi_z_downsample.comp`
+const HI_Z_WORKGROUP_SIZE: u32 = 8;
+
+/// An object as read by `shaders/gpu_cull.comp`
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct CullObject {
+    model: [[f32; 4]; 4],
+    color: [f32; 4],
+    /// Model-space bounding sphere: center and radius
+    sphere: [f32; 4],
+    group: u32,
+    _padding: [u32; 3],
+}
+
+unsafe impl bytemuck::Pod for CullObject {}
+unsafe impl bytemuck::Zeroable for CullObject {}
+
+/// A mesh and material drawn with one indirect command
+struct DrawGroup {
+    mesh: Arc<Mesh>,
+    material: Material,
+    /// First slot of the group in the instance buffer
+    base: u32,
+    /// Number of objects in the group, the most instances it can draw
+    capacity: u32,
+}
+
+/// Instance batches uploaded once and culled on the GPU every frame
+///
+/// Meant for large static sets of objects: the objects are never touched by the CPU again,
+/// so moving one means building a new scene.
+pub struct GpuScene {
+    objects: Subbuffer<[CullObject]>,
+    group_bases: Subbuffer<[u32]>,
+    groups: Vec<DrawGroup>,
+    object_count: u32,
+    vertex_layout: VertexLayout,
+}
+
+impl GpuScene {
+    /// Uploads the instances of opaque batches; each batch becomes one indirect draw
+    ///
+    /// All meshes must share one vertex layout, since the draws use a single pipeline. The
+    /// objects are copied into device-local memory by commands recorded into `builder`,
+    /// which has to be executed before the scene is culled.
+    pub fn new<L, A>(
+        allocator: &StandardMemoryAllocator,
+        batches: &[InstanceBatch],
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+    ) -> Result<Arc<Self>>
+    where
+        A: CommandBufferAllocator,
+    {
+        if let Some(batch) = batches.iter().find(|batch| batch.material.is_transparent()) {
+            return Err(anyhow!(
+                "GPU scenes only draw opaque materials, got {:?}",
+                batch.material.blend
+            ));
+        }
+
+        let vertex_layout = match batches.first() {
+            Some(batch) => batch.mesh.vertex_layout().clone(),
+            None => VertexLayout::of::<Vertex>(),
+        };
+        if batches.iter().any(|batch| *batch.mesh.vertex_layout() != vertex_layout) {
+            return Err(anyhow!("GPU scene meshes must all have the same vertex layout"));
+        }
+
+        let mut objects = Vec::new();
+        let mut groups = Vec::with_capacity(batches.len());
+        for (group, batch) in batches.iter().enumerate() {
+            let sphere = batch.mesh.bounding_sphere();
+            let center = sphere.center;
+            groups.push(DrawGroup {
+                mesh: batch.mesh.clone(),
+                material: batch.material,
+                base: objects.len() as u32,
+                capacity: batch.instances.len() as u32,
+            });
+            objects.extend(batch.instances.iter().map(|instance| CullObject {
+                model: instance.instance_model,
+                color: instance.instance_color,
+                sphere: [center.x, center.y, center.z, sphere.radius],
+                group: group as u32,
+                _padding: [0; 3],
+            }));
+        }
+        if objects.is_empty() {
+            return Err(anyhow!("GPU scene has no instances"));
+        }
+
+        let object_count = objects.len() as u32;
+        let objects = upload_storage_buffer(allocator, builder, objects)?;
+        let bases = groups.iter().map(|group| group.base);
+        let group_bases = upload_storage_buffer(allocator, builder, bases)?;
+
+        info!(
+            "GPU scene created with {} objects in {} groups",
+            object_count,
+            groups.len()
+        );
+        Ok(Arc::new(Self {
+            objects,
+            group_bases,
+            groups,
+            object_count,
+            vertex_layout,
+        }))
+    }
+
+    /// Returns the vertex layout shared by the scene's meshes
+    pub fn vertex_layout(&self) -> &VertexLayout {
+        &self.vertex_layout
+    }
+
+    /// Returns the number of objects culled every frame
+    pub fn object_count(&self) -> u32 {
+        self.object_count
+    }
+
+    /// Returns the number of indirect draws, one per mesh and material
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+}
+
+/// Uploads data into a device-local storage buffer read by compute shaders
+///
+/// The data is written to a staging buffer and copied over by a command recorded into
+/// `builder`, so the shaders never read it across the bus.
+fn upload_storage_buffer<T, L, A>(
+    allocator: &StandardMemoryAllocator,
+    builder: &mut AutoCommandBufferBuilder<L, A>,
+    data: impl IntoIterator<Item = T, IntoIter = impl ExactSizeIterator<Item = T>>,
+) -> Result<Subbuffer<[T]>>
+where
+    T: bytemuck::Pod + Send + Sync,
+    A: CommandBufferAllocator,
+{
+    let staging = Buffer::from_iter(
+        allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::Upload,
+            ..Default::default()
+        },
+        data,
+    )?;
+    let buffer = Buffer::new_slice::<T>(
+        allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::DeviceOnly,
+            ..Default::default()
+        },
+        staging.len(),
+    )?;
+
+    builder.copy_buffer(CopyBufferInfo::buffers(staging, buffer.clone()))?;
+    Ok(buffer)
+}
+
+/// Mip chain holding the farthest depth of each texel's footprint, for occlusion culling
+///
+/// Built every frame from a depth image rendered from the same camera, typically a depth
+/// prepass of large occluders. Objects entirely behind it are culled.
+pub struct HiZPyramid {
+    view: Arc<ImageView<ImmutableImage>>,
+    levels: Vec<Arc<ImageView<ImmutableImage>>>,
+    extent: [u32; 2],
+}
+
+impl HiZPyramid {
+    /// Creates a pyramid for a depth image of the given size, recording its initial clear
+    pub fn new<L, A>(
+        allocator: &StandardMemoryAllocator,
+        extent: [u32; 2],
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+    ) -> Result<Self>
+    where
+        A: CommandBufferAllocator,
+    {
+        let (image, initialization) = ImmutableImage::uninitialized(
+            allocator,
+            ImageDimensions::Dim2d {
+                width: extent[0],
+                height: extent[1],
+                array_layers: 1,
+            },
+            Format::R32_SFLOAT,
+            MipmapsCount::Log2,
+            ImageUsage::STORAGE | ImageUsage::SAMPLED | ImageUsage::TRANSFER_DST,
+            ImageCreateFlags::empty(),
+            ImageLayout::General,
+            [],
+        )?;
+
+        // Move the image into the layout it is tracked in, cleared to the far plane
+        builder.clear_color_image(ClearColorImageInfo {
+            clear_value: ClearColorValue::Float([1.0; 4]),
+            ..ClearColorImageInfo::image(initialization)
+        })?;
+
+        let levels = (0..image.mip_levels())
+            .map(|level| {
+                ImageView::new(
+                    image.clone(),
+                    ImageViewCreateInfo {
+                        subresource_range: ImageSubresourceRange {
+                            mip_levels: level..level + 1,
+                            ..image.subresource_range()
+                        },
+                        ..ImageViewCreateInfo::from_image(&image)
+                    },
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let view = ImageView::new_default(image)?;
+
+        info!(
+            "Hi-Z pyramid created at {}x{} with {} levels",
+            extent[0],
+            extent[1],
+            levels.len()
+        );
+        Ok(Self {
+            view,
+            levels,
+            extent,
+        })
+    }
+
+    /// Returns the size of the first level, equal to the depth image it is built from
+    pub fn extent(&self) -> [u32; 2] {
+        self.extent
+    }
+
+    /// Returns a view of the whole mip chain
+    pub fn view(&self) -> &Arc<ImageView<ImmutableImage>> {
+        &self.view
+    }
+
+    /// Returns the size of a mip level
+    fn level_extent(&self, level: usize) -> [u32; 2] {
+        self.extent.map(|size| (size >> level).max(1))
+    }
+}
+
+/// Compute pipelines and per-frame buffers for culling GPU scenes
+pub struct GpuCuller {
+    cull: Arc<ComputePipeline>,
+    cull_hi_z: Arc<ComputePipeline>,
+    downsample: Arc<ComputePipeline>,
+    sampler: Arc<Sampler>,
+    command_allocator: SubbufferAllocator,
+    instance_allocator: SubbufferAllocator,
+    params_allocator: SubbufferAllocator,
+}
+
+impl GpuCuller {
+    /// Creates the culling and Hi-Z pipelines
+    pub fn new(
+        device: Arc<Device>,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+        cache: Option<Arc<PipelineCache>>,
+    ) -> Result<Self> {
+        let cull = ComputePipeline::new(
+            device.clone(),
+            descriptor_set_allocator.clone(),
+            crate::shaders::gpu_cull::load(device.clone())?,
+            "main",
+            cache.clone(),
+        )?;
+        let cull_hi_z = ComputePipeline::new(
+            device.clone(),
+            descriptor_set_allocator.clone(),
+            crate::shaders::gpu_cull::load_hi_z(device.clone())?,
+            "main",
+            cache.clone(),
+        )?;
+        let downsample = ComputePipeline::new(
+            device.clone(),
+            descriptor_set_allocator,
+            crate::shaders::hi_z_downsample::load(device.clone())?,
+            "main",
+            cache,
+        )?;
+
+        // Depth is read texel by texel, and the pyramid at whichever level fits the object
+        let sampler = Sampler::new(
+            device,
+            SamplerCreateInfo {
+                mag_filter: Filter::Nearest,
+                min_filter: Filter::Nearest,
+                mipmap_mode: SamplerMipmapMode::Nearest,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                lod: 0.0..=LOD_CLAMP_NONE,
+                ..Default::default()
+            },
+        )?;
+
+        // Commands are reset from the CPU every frame; instances are only written by the GPU
+        let command_allocator = SubbufferAllocator::new(
+            memory_allocator.clone(),
+            SubbufferAllocatorCreateInfo {
+                buffer_usage: BufferUsage::STORAGE_BUFFER | BufferUsage::INDIRECT_BUFFER,
+                memory_usage: MemoryUsage::Upload,
+                ..Default::default()
+            },
+        );
+        let instance_allocator = SubbufferAllocator::new(
+            memory_allocator.clone(),
+            SubbufferAllocatorCreateInfo {
+                buffer_usage: BufferUsage::STORAGE_BUFFER | BufferUsage::VERTEX_BUFFER,
+                memory_usage: MemoryUsage::DeviceOnly,
+                ..Default::default()
+            },
+        );
+        let params_allocator = SubbufferAllocator::new(
+            memory_allocator,
+            SubbufferAllocatorCreateInfo {
+                buffer_usage: BufferUsage::UNIFORM_BUFFER,
+                memory_usage: MemoryUsage::Upload,
+                ..Default::default()
+            },
+        );
+
+        Ok(Self {
+            cull,
+            cull_hi_z,
+            downsample,
+            sampler,
+            command_allocator,
+            instance_allocator,
+            params_allocator,
+        })
+    }
+
+    /// Prepares this frame's culling of a scene seen through `camera`
+    ///
+    /// With a pyramid, objects hidden behind its depth are culled as well as those outside
+    /// the frustum.
+    pub fn prepare(
+        &self,
+        scene: &Arc<GpuScene>,
+        camera: &Camera,
+        aspect: f32,
+        hi_z: Option<&HiZPyramid>,
+    ) -> Result<CulledScene> {
+        // Every frame starts from empty commands that the shader appends instances to
+        let commands = self
+            .command_allocator
+            .allocate_slice::<DrawIndexedIndirectCommand>(scene.groups.len() as u64)?;
+        for (command, group) in commands.write()?.iter_mut().zip(&scene.groups) {
+            *command = DrawIndexedIndirectCommand {
+                index_count: group.mesh.index_count(),
+                instance_count: 0,
+                first_index: 0,
+                vertex_offset: 0,
+                first_instance: 0,
+            };
+        }
+        let instances = self
+            .instance_allocator
+            .allocate_slice::<InstanceData>(scene.object_count as u64)?;
+
+        let view_proj = camera.projection_matrix(aspect) * camera.view_matrix();
+        let params = self.params_allocator.allocate_sized::<CullParams>()?;
+        *params.write()? = CullParams {
+            view_proj: view_proj.into(),
+            planes: camera.frustum(aspect).planes.map(Into::into),
+            hi_z_size: hi_z.map_or([0.0; 2], |hi_z| hi_z.extent().map(|size| size as f32)),
+            object_count: scene.object_count,
+        };
+
+        let mut bindings = vec![
+            ComputeBinding::storage_buffer(0, scene.objects.clone()),
+            ComputeBinding::storage_buffer(1, scene.group_bases.clone()),
+            ComputeBinding::storage_buffer(2, commands.clone()),
+            ComputeBinding::storage_buffer(3, instances.clone()),
+            ComputeBinding::uniform_buffer(4, params),
+        ];
+        let pipeline = match hi_z {
+            Some(hi_z) => {
+                bindings.push(ComputeBinding::sampled_image(
+                    5,
+                    hi_z.view().clone(),
+                    self.sampler.clone(),
+                ));
+                &self.cull_hi_z
+            }
+            None => &self.cull,
+        };
+
+        let set = pipeline.create_descriptor_set(0, bindings)?;
+        let group_count = scene.object_count.div_ceil(CULL_WORKGROUP_SIZE);
+        Ok(CulledScene {
+            scene: scene.clone(),
+            commands,
+            instances,
+            dispatch: pipeline.dispatch_with([group_count, 1, 1], vec![set]),
+        })
+    }
+
+    /// Prepares the dispatches rebuilding a pyramid from a depth image, one per level
+    pub fn build_hi_z(
+        &self,
+        pyramid: &HiZPyramid,
+        depth: Arc<dyn ImageViewAbstract>,
+    ) -> Result<Vec<ComputeDispatch>> {
+        let mut source = depth;
+        let mut dispatches = Vec::with_capacity(pyramid.levels.len());
+        for (level, destination) in pyramid.levels.iter().enumerate() {
+            let set = self.downsample.create_descriptor_set(
+                0,
+                [
+                    ComputeBinding::sampled_image(0, source, self.sampler.clone()),
+                    ComputeBinding::storage_image(1, destination.clone()),
+                ],
+            )?;
+            let [width, height] = pyramid.level_extent(level);
+            let group_counts = [
+                width.div_ceil(HI_Z_WORKGROUP_SIZE),
+                height.div_ceil(HI_Z_WORKGROUP_SIZE),
+                1,
+            ];
+            dispatches.push(self.downsample.dispatch_with(group_counts, vec![set]));
+            source = destination.clone();
+        }
+        Ok(dispatches)
+    }
+}
+
+/// A GPU scene's culling dispatch for one frame and the buffers it fills
+pub struct CulledScene {
+    scene: Arc<GpuScene>,
+    commands: Subbuffer<[DrawIndexedIndirectCommand]>,
+    instances: Subbuffer<[InstanceData]>,
+    dispatch: ComputeDispatch,
+}
+
+impl CulledScene {
+    /// Returns the vertex layout of the culled scene's meshes
+    pub fn vertex_layout(&self) -> &VertexLayout {
+        self.scene.vertex_layout()
+    }
+
+    /// Returns the draw commands written by the culling dispatch
+    pub fn commands(&self) -> &Subbuffer<[DrawIndexedIndirectCommand]> {
+        &self.commands
+    }
+
+    /// Returns the visible instances written by the culling dispatch
+    pub fn instances(&self) -> &Subbuffer<[InstanceData]> {
+        &self.instances
+    }
+
+    /// Records the culling dispatch
+    pub fn record_cull<L, A>(&self, builder: &mut AutoCommandBufferBuilder<L, A>) -> Result<()>
+    where
+        A: CommandBufferAllocator,
+    {
+        self.dispatch.record(builder)
+    }
+
+    /// Records one indirect draw per group with an instanced pipeline that is already bound
+    pub fn record_draws<L, A>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+        layout: &Arc<PipelineLayout>,
+    ) -> Result<()>
+    where
+        A: CommandBufferAllocator,
+    {
+        for (index, group) in self.scene.groups.iter().enumerate() {
+            let group_constants = PushConstants {
+                model: Matrix4::identity().into(),
+                color: group.material.color,
+            };
+            push_constants(builder, layout, 0, group_constants)?;
+
+            let start = group.base as u64;
+            let instances = self.instances.clone().slice(start..start + group.capacity as u64);
+            let command = self.commands.clone().slice(index as u64..index as u64 + 1);
+            group.mesh.draw_indirect(builder, instances, command)?;
+        }
+        Ok(())
+    }
+}
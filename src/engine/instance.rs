@@ -2,7 +2,7 @@ use anyhow::{anyhow, Result};
 use log::info;
 use std::sync::Arc;
 use vulkano::device::physical::{PhysicalDevice, PhysicalDeviceType};
-use vulkano::device::{Device, DeviceCreateInfo, DeviceExtensions, QueueCreateInfo};
+use vulkano::device::{Device, DeviceCreateInfo, DeviceExtensions, Features, QueueCreateInfo};
 use vulkano::instance::Instance;
 use vulkano::swapchain::Surface;
 
@@ -24,12 +24,14 @@ pub fn select_physical_device(
             p.supported_extensions().contains(&device_extensions)
         })
         .filter_map(|p| {
-            // Find a queue family that supports graphics and presentation
+            // Find a queue family that supports graphics, compute and presentation
             p.queue_family_properties()
                 .iter()
                 .enumerate()
                 .position(|(i, q)| {
-                    q.queue_flags.contains(vulkano::device::QueueFlags::GRAPHICS)
+                    q.queue_flags.contains(
+                        vulkano::device::QueueFlags::GRAPHICS | vulkano::device::QueueFlags::COMPUTE,
+                    )
                         && p.surface_support(i as u32, surface).unwrap_or(false)
                 })
                 .map(|i| (p, i as u32))
@@ -67,6 +69,16 @@ pub fn create_logical_device(
         ..DeviceExtensions::empty()
     };
 
+    // Enable optional rasterization and blending features when the device supports them
+    let supported_features = physical_device.supported_features();
+    let enabled_features = Features {
+        fill_mode_non_solid: supported_features.fill_mode_non_solid,
+        wide_lines: supported_features.wide_lines,
+        depth_bias_clamp: supported_features.depth_bias_clamp,
+        independent_blend: supported_features.independent_blend,
+        ..Features::empty()
+    };
+
     // Create the logical device and queues
     let (device, mut queues) = Device::new(
         physical_device,
@@ -76,6 +88,7 @@ pub fn create_logical_device(
                 ..Default::default()
             }],
             enabled_extensions: device_extensions,
+            enabled_features,
             ..Default::default()
         },
     )?;
@@ -0,0 +1,298 @@
+use anyhow::{anyhow, Result};
+use log::info;
+use nalgebra::Matrix4;
+use std::sync::Arc;
+use vulkano::memory::allocator::StandardMemoryAllocator;
+
+use crate::engine::buffer::Vertex;
+use crate::engine::camera::Camera;
+use crate::engine::mesh::Mesh;
+use crate::engine::simplify::simplify;
+
+/// How a level-of-detail chain is generated and switched
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LodSettings {
+    /// Maximum number of simplified levels after the original mesh
+    pub max_levels: usize,
+    /// Fraction of the previous level's triangles each level aims for
+    pub reduction: f32,
+    /// Largest distance in model units the surface may move; stops the chain early
+    pub max_error: f32,
+    /// Screen size below which the first simplified level is used, as a fraction of the
+    /// viewport height; every further level halves it
+    pub screen_size: f32,
+    /// Relative margin around each switching size, so objects near it do not flicker
+    pub hysteresis: f32,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            max_levels: 4,
+            reduction: 0.5,
+            max_error: f32::INFINITY,
+            screen_size: 0.25,
+            hysteresis: 0.1,
+        }
+    }
+}
+
+/// One level of a level-of-detail chain
+pub struct LodLevel {
+    pub mesh: Arc<Mesh>,
+    /// Smallest screen size, as a fraction of the viewport height, this level is used at
+    pub min_screen_size: f32,
+    /// Distance in model units the surface moved while simplifying this level
+    pub error: f32,
+}
+
+/// A mesh with simplified versions for drawing it smaller on screen
+pub struct LodMesh {
+    levels: Vec<LodLevel>,
+    hysteresis: f32,
+}
+
+impl LodMesh {
+    /// Uploads a mesh together with a chain of simplified levels generated from it
+    ///
+    /// All levels share the original vertex buffer, each with its own index buffer. The
+    /// chain ends early when a level would barely shrink or would exceed `max_error`.
+    pub fn generate(
+        allocator: &StandardMemoryAllocator,
+        vertices: &[Vertex],
+        indices: &[u32],
+        settings: &LodSettings,
+    ) -> Result<Arc<Self>> {
+        let original = Mesh::new(allocator, vertices, indices)?;
+        let mut levels = vec![LodLevel {
+            mesh: original.clone(),
+            min_screen_size: settings.screen_size,
+            error: 0.0,
+        }];
+        for level in simplified_levels(vertices, indices, settings) {
+            levels.push(LodLevel {
+                mesh: original.with_indices(allocator, &level.indices)?,
+                min_screen_size: level.min_screen_size,
+                error: level.error,
+            });
+        }
+
+        if let Some(last) = levels.last_mut() {
+            last.min_screen_size = 0.0;
+        }
+        info!(
+            "Generated {} LOD levels with {:?} triangles",
+            levels.len(),
+            levels
+                .iter()
+                .map(|level| level.mesh.index_count() / 3)
+                .collect::<Vec<_>>()
+        );
+        Self::from_levels(levels, settings.hysteresis)
+    }
+
+    /// Creates a chain from levels ordered from most to least detailed
+    pub fn from_levels(mut levels: Vec<LodLevel>, hysteresis: f32) -> Result<Arc<Self>> {
+        if levels.is_empty() {
+            return Err(anyhow!("A LOD mesh needs at least one level"));
+        }
+        if levels
+            .windows(2)
+            .any(|pair| pair[1].min_screen_size > pair[0].min_screen_size)
+        {
+            return Err(anyhow!("LOD levels must be ordered by decreasing screen size"));
+        }
+        if let Some(last) = levels.last_mut() {
+            last.min_screen_size = 0.0;
+        }
+        Ok(Arc::new(Self {
+            levels,
+            hysteresis: hysteresis.clamp(0.0, 0.9),
+        }))
+    }
+
+    /// Returns the levels from most to least detailed
+    pub fn levels(&self) -> &[LodLevel] {
+        &self.levels
+    }
+
+    /// Returns the mesh of a level, or of the least detailed one if it is out of range
+    pub fn mesh(&self, level: usize) -> &Arc<Mesh> {
+        &self.levels[level.min(self.levels.len() - 1)].mesh
+    }
+
+    /// Returns the level to draw at `screen_size`, coming from level `current`
+    ///
+    /// The current level is kept while the size is within the hysteresis margin of its
+    /// range, so objects hovering around a switching size do not pop back and forth.
+    pub fn select(&self, current: usize, screen_size: f32) -> usize {
+        let thresholds = self.levels.iter().map(|level| level.min_screen_size);
+        select_level(thresholds, self.hysteresis, current, screen_size)
+    }
+}
+
+/// Returns the level to draw at `screen_size` for levels with the given `min_screen_size`
+/// thresholds, ordered from most to least detailed, coming from level `current`
+///
+/// The current level is kept while the size is within `hysteresis` of its range. Sizes
+/// below every threshold, and levels past the end, select the last level.
+pub fn select_level(
+    thresholds: impl Iterator<Item = f32> + Clone,
+    hysteresis: f32,
+    current: usize,
+    screen_size: f32,
+) -> usize {
+    let finest = level_for(thresholds.clone(), screen_size * (1.0 + hysteresis));
+    let coarsest = level_for(thresholds, screen_size * (1.0 - hysteresis));
+    current.clamp(finest, coarsest)
+}
+
+/// Returns the first level whose threshold `screen_size` reaches, or the last level
+fn level_for(thresholds: impl Iterator<Item = f32>, screen_size: f32) -> usize {
+    let mut last = 0;
+    for (index, threshold) in thresholds.enumerate() {
+        if screen_size >= threshold {
+            return index;
+        }
+        last = index;
+    }
+    last
+}
+
+/// A simplified level planned before it is uploaded
+struct SimplifiedLevel {
+    indices: Vec<u32>,
+    min_screen_size: f32,
+    error: f32,
+}
+
+/// Simplifies the levels after the original mesh, each aiming for `reduction` of the
+/// previous level's triangles at half its screen size
+///
+/// The chain stops after `max_levels`, when the target drops below one triangle, or when
+/// simplification stalls, e.g. at the error limit.
+fn simplified_levels(
+    vertices: &[Vertex],
+    indices: &[u32],
+    settings: &LodSettings,
+) -> Vec<SimplifiedLevel> {
+    let mut levels = Vec::new();
+    let mut previous = indices.len();
+    let mut screen_size = settings.screen_size;
+    while levels.len() < settings.max_levels {
+        let target = ((previous as f32 * settings.reduction) as usize / 3) * 3;
+        if target == 0 {
+            break;
+        }
+        let simplified = simplify(vertices, indices, target, settings.max_error);
+        if simplified.indices.is_empty() || simplified.indices.len() * 10 > previous * 9 {
+            break;
+        }
+
+        screen_size *= 0.5;
+        previous = simplified.indices.len();
+        levels.push(SimplifiedLevel {
+            indices: simplified.indices,
+            min_screen_size: screen_size,
+            error: simplified.error,
+        });
+    }
+    levels
+}
+
+/// Returns the fraction of the viewport height covered by a mesh's bounding sphere
+///
+/// Grows past 1.0 for objects larger than the view, including when the camera is inside.
+pub fn screen_size(mesh: &Mesh, model: &Matrix4<f32>, camera: &Camera) -> f32 {
+    let sphere = mesh.bounding_sphere().transform(model);
+    let distance = (sphere.center - camera.position()).norm();
+    if distance <= sphere.radius {
+        return f32::INFINITY;
+    }
+    sphere.radius / (distance * (camera.fov_y / 2.0).tan())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::primitives;
+
+    const THRESHOLDS: [f32; 3] = [0.25, 0.125, 0.0];
+
+    fn select(current: usize, screen_size: f32) -> usize {
+        select_level(THRESHOLDS.into_iter(), 0.1, current, screen_size)
+    }
+
+    #[test]
+    fn level_boundaries_without_hysteresis() {
+        let level = |screen_size| select_level(THRESHOLDS.into_iter(), 0.0, 0, screen_size);
+        assert_eq!(level(1.0), 0);
+        assert_eq!(level(0.25), 0);
+        assert_eq!(level(0.24), 1);
+        assert_eq!(level(0.125), 1);
+        assert_eq!(level(0.12), 2);
+        assert_eq!(level(0.0), 2);
+    }
+
+    #[test]
+    fn hysteresis_keeps_the_level_near_a_boundary() {
+        // Shrinking past 0.25 keeps the fine level until the size leaves the band
+        assert_eq!(select(0, 0.24), 0);
+        assert_eq!(select(0, 0.22), 1);
+
+        // Growing past 0.25 keeps the coarse level until the size leaves the band
+        assert_eq!(select(1, 0.26), 1);
+        assert_eq!(select(1, 0.28), 0);
+
+        // Far from any boundary the level follows the size at once
+        assert_eq!(select(2, 1.0), 0);
+        assert_eq!(select(0, 0.01), 2);
+    }
+
+    #[test]
+    fn selection_clamps_at_the_last_level() {
+        assert_eq!(select(5, 0.01), 2);
+        assert_eq!(select(5, 1.0), 0);
+        let thresholds = [0.5, 0.25].into_iter();
+        assert_eq!(select_level(thresholds, 0.0, 0, 0.1), 1);
+        assert_eq!(select_level([0.5].into_iter(), 0.1, 0, 0.0), 0);
+    }
+
+    #[test]
+    fn levels_aim_for_the_reduction_ratio() {
+        let (vertices, indices) = primitives::uv_sphere(1.0, 32, 16);
+        let settings = LodSettings {
+            max_levels: 3,
+            ..Default::default()
+        };
+        let levels = simplified_levels(&vertices, &indices, &settings);
+
+        assert_eq!(levels.len(), 3);
+        let mut previous = indices.len();
+        let mut screen_size = settings.screen_size;
+        for level in &levels {
+            assert!(level.indices.len() <= previous / 2);
+            assert!(level.indices.len() * 10 <= previous * 9);
+            screen_size *= 0.5;
+            assert_eq!(level.min_screen_size, screen_size);
+            previous = level.indices.len();
+        }
+    }
+
+    #[test]
+    fn chain_stops_below_one_triangle() {
+        let vertices = vec![Vertex::default(); 3];
+        let settings = LodSettings::default();
+        assert!(simplified_levels(&vertices, &[0, 1, 2], &settings).is_empty());
+    }
+
+    #[test]
+    fn chain_stops_when_simplification_stalls() {
+        let (vertices, indices) = primitives::uv_sphere(1.0, 32, 16);
+        let settings = LodSettings {
+            max_error: 0.0,
+            ..Default::default()
+        };
+        assert!(simplified_levels(&vertices, &indices, &settings).is_empty());
+    }
+}
@@ -0,0 +1,222 @@
+use anyhow::{anyhow, Result};
+use nalgebra::Point3;
+use std::sync::Arc;
+use vulkano::buffer::Subbuffer;
+use vulkano::command_buffer::allocator::CommandBufferAllocator;
+use vulkano::command_buffer::{AutoCommandBufferBuilder, DrawIndexedIndirectCommand};
+use vulkano::memory::allocator::StandardMemoryAllocator;
+use vulkano::pipeline::graphics::vertex_input::VertexBufferDescription;
+
+use crate::engine::bounds::{Aabb, BoundingSphere};
+use crate::engine::buffer::{create_index_buffer, create_vertex_buffer, InstanceData, Vertex};
+use crate::engine::pipeline::VertexLayout;
+use crate::engine::vertex_format::VertexStream;
+
+/// Indexed triangle geometry uploaded to the GPU
+///
+/// The vertex attributes are read from one or more streams, each in its own vertex buffer
+/// bound in order. Their layout selects the pipeline variant the mesh is drawn with.
+pub struct Mesh {
+    vertex_buffers: Vec<Subbuffer<[u8]>>,
+    vertex_layout: VertexLayout,
+    standard_layout: bool,
+    index_buffer: Subbuffer<[u32]>,
+    index_count: u32,
+    aabb: Aabb,
+    sphere: BoundingSphere,
+}
+
+impl Mesh {
+    /// Uploads vertices and indices into new GPU buffers and computes their bounds
+    pub fn new(
+        allocator: &StandardMemoryAllocator,
+        vertices: &[Vertex],
+        indices: &[u32],
+    ) -> Result<Arc<Self>> {
+        Self::builder(allocator).stream(vertices)?.build(indices)
+    }
+
+    /// Starts a mesh whose vertex attributes are split over several streams
+    pub fn builder(allocator: &StandardMemoryAllocator) -> MeshBuilder<'_> {
+        MeshBuilder {
+            allocator,
+            vertex_buffers: Vec::new(),
+            descriptions: Vec::new(),
+            vertex_count: None,
+            positions: None,
+        }
+    }
+
+    /// Creates a mesh drawing other indices into this mesh's vertex buffers
+    ///
+    /// Only the index buffer is uploaded. The bounds are kept, so they stay conservative
+    /// for meshes that use a subset of the vertices, such as simplified levels of detail.
+    pub fn with_indices(
+        &self,
+        allocator: &StandardMemoryAllocator,
+        indices: &[u32],
+    ) -> Result<Arc<Self>> {
+        Ok(Arc::new(Self {
+            vertex_buffers: self.vertex_buffers.clone(),
+            vertex_layout: self.vertex_layout.clone(),
+            standard_layout: self.standard_layout,
+            index_buffer: create_index_buffer(allocator, indices)?,
+            index_count: indices.len() as u32,
+            aabb: self.aabb,
+            sphere: self.sphere,
+        }))
+    }
+
+    /// Returns the per-vertex bindings of the mesh's streams, in binding order
+    pub fn vertex_layout(&self) -> &VertexLayout {
+        &self.vertex_layout
+    }
+
+    /// Returns whether the mesh is a single `Vertex` stream, as the default pipelines expect
+    pub fn has_standard_layout(&self) -> bool {
+        self.standard_layout
+    }
+
+    /// Returns the number of indices drawn
+    pub fn index_count(&self) -> u32 {
+        self.index_count
+    }
+
+    /// Returns the center of the mesh's bounding box in model space
+    pub fn center(&self) -> Point3<f32> {
+        self.aabb.center()
+    }
+
+    /// Returns the axis-aligned bounding box in model space
+    pub fn aabb(&self) -> &Aabb {
+        &self.aabb
+    }
+
+    /// Returns the bounding sphere in model space
+    pub fn bounding_sphere(&self) -> &BoundingSphere {
+        &self.sphere
+    }
+
+    /// Binds the mesh buffers and records an indexed draw
+    pub fn draw<L, A>(&self, builder: &mut AutoCommandBufferBuilder<L, A>) -> Result<()>
+    where
+        A: CommandBufferAllocator,
+    {
+        builder
+            .bind_vertex_buffers(0, self.vertex_buffers.clone())
+            .bind_index_buffer(self.index_buffer.clone())
+            .draw_indexed(self.index_count, 1, 0, 0, 0)?;
+        Ok(())
+    }
+
+    /// Binds the mesh buffers with an instance buffer and records one indexed draw per instance
+    pub fn draw_instanced<L, A>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+        instances: Subbuffer<[InstanceData]>,
+    ) -> Result<()>
+    where
+        A: CommandBufferAllocator,
+    {
+        let instance_count = instances.len() as u32;
+        builder
+            .bind_vertex_buffers(0, self.buffers_with_instances(instances))
+            .bind_index_buffer(self.index_buffer.clone())
+            .draw_indexed(self.index_count, instance_count, 0, 0, 0)?;
+        Ok(())
+    }
+
+    /// Binds the mesh buffers with an instance buffer and records an indirect indexed draw
+    ///
+    /// The command, e.g. written by a culling shader, decides how many instances are drawn.
+    pub fn draw_indirect<L, A>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+        instances: Subbuffer<[InstanceData]>,
+        command: Subbuffer<[DrawIndexedIndirectCommand]>,
+    ) -> Result<()>
+    where
+        A: CommandBufferAllocator,
+    {
+        builder
+            .bind_vertex_buffers(0, self.buffers_with_instances(instances))
+            .bind_index_buffer(self.index_buffer.clone())
+            .draw_indexed_indirect(command)?;
+        Ok(())
+    }
+
+    /// Returns the vertex streams followed by an instance buffer, in binding order
+    fn buffers_with_instances(&self, instances: Subbuffer<[InstanceData]>) -> Vec<Subbuffer<[u8]>> {
+        let mut buffers = self.vertex_buffers.clone();
+        buffers.push(instances.into_bytes());
+        buffers
+    }
+}
+
+/// Uploads the vertex streams of a mesh one at a time
+pub struct MeshBuilder<'a> {
+    allocator: &'a StandardMemoryAllocator,
+    vertex_buffers: Vec<Subbuffer<[u8]>>,
+    descriptions: Vec<VertexBufferDescription>,
+    vertex_count: Option<usize>,
+    positions: Option<Vec<[f32; 3]>>,
+}
+
+impl MeshBuilder<'_> {
+    /// Uploads a stream of vertex attributes, bound after the streams added before it
+    ///
+    /// Every stream needs the same number of vertices, and no attribute may appear twice.
+    pub fn stream<S: VertexStream>(mut self, vertices: &[S]) -> Result<Self> {
+        if let Some(count) = self.vertex_count.filter(|&count| count != vertices.len()) {
+            return Err(anyhow!(
+                "Vertex stream has {} vertices, but the mesh has {}",
+                vertices.len(),
+                count
+            ));
+        }
+
+        let description = S::per_vertex();
+        let duplicate = description.members.keys().find(|name| {
+            self.descriptions
+                .iter()
+                .any(|other| other.members.contains_key(*name))
+        });
+        if let Some(name) = duplicate {
+            return Err(anyhow!("Vertex attribute `{}` is provided by two streams", name));
+        }
+
+        if let Some(positions) = S::positions(vertices) {
+            if self.positions.is_some() {
+                return Err(anyhow!("Only one vertex stream may provide positions"));
+            }
+            self.positions = Some(positions);
+        }
+
+        let buffer = create_vertex_buffer(self.allocator, vertices)?;
+        self.vertex_buffers.push(buffer.into_bytes());
+        self.descriptions.push(description);
+        self.vertex_count = Some(vertices.len());
+        Ok(self)
+    }
+
+    /// Uploads the indices and computes the bounds from the position stream
+    pub fn build(self, indices: &[u32]) -> Result<Arc<Mesh>> {
+        let positions: Vec<_> = self
+            .positions
+            .ok_or_else(|| anyhow!("A mesh needs a vertex stream with positions"))?
+            .into_iter()
+            .map(Point3::from)
+            .collect();
+        let vertex_layout = VertexLayout(self.descriptions);
+
+        Ok(Arc::new(Mesh {
+            vertex_buffers: self.vertex_buffers,
+            standard_layout: vertex_layout == VertexLayout::of::<Vertex>(),
+            vertex_layout,
+            index_buffer: create_index_buffer(self.allocator, indices)?,
+            index_count: indices.len() as u32,
+            aabb: Aabb::from_points(positions.iter().copied()),
+            sphere: BoundingSphere::from_points(&positions),
+        }))
+    }
+}
@@ -0,0 +1,725 @@
+use anyhow::{anyhow, Result};
+use nalgebra::{Point3, Vector3};
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+
+use crate::engine::buffer::Vertex;
+
+/// Number of entries of the vertex cache `optimize_vertex_cache` optimizes for
+const CACHE_SIZE: usize = 32;
+
+/// Distance below which `compute_smooth_normals` treats positions as the same
+const POSITION_EPSILON: f32 = 1e-5;
+
+/// Post-transform cache size used to measure cache misses, typical of current GPUs
+pub const DEFAULT_CACHE_SIZE: usize = 16;
+
+fn position(vertex: &Vertex) -> Point3<f32> {
+    Point3::from(vertex.position)
+}
+
+/// Returns the corner positions of a triangle
+fn corners(vertices: &[Vertex], triangle: &[u32]) -> [Point3<f32>; 3] {
+    [0, 1, 2].map(|corner| position(&vertices[triangle[corner] as usize]))
+}
+
+/// Returns the cross product of two triangle edges: the face normal scaled by twice the area
+fn face_cross(vertices: &[Vertex], triangle: &[u32]) -> Vector3<f32> {
+    let [a, b, c] = corners(vertices, triangle);
+    (b - a).cross(&(c - a))
+}
+
+/// Returns the angle of a triangle at each of its corners
+fn corner_angles(vertices: &[Vertex], triangle: &[u32]) -> [f32; 3] {
+    let points = corners(vertices, triangle);
+    [0, 1, 2].map(|corner| {
+        let to_next = points[(corner + 1) % 3] - points[corner];
+        let to_previous = points[(corner + 2) % 3] - points[corner];
+        to_next.angle(&to_previous)
+    })
+}
+
+/// Replaces the normals with smooth ones averaged over the triangles around each position
+///
+/// Face normals are weighted by the angle of their corner, so the result does not depend
+/// on how faces are split into triangles. Vertices at the same position share their normal,
+/// so seams of split UVs stay invisible; use `compute_flat_normals` for hard edges.
+pub fn compute_smooth_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut grid = PointGrid::new(POSITION_EPSILON);
+    let mut slot_positions: Vec<Point3<f32>> = Vec::new();
+    let slots: Vec<usize> = vertices
+        .iter()
+        .map(|vertex| {
+            let point = position(vertex);
+            let existing = grid.find(vertex.position, |slot| {
+                (slot_positions[slot as usize] - point).amax() <= POSITION_EPSILON
+            });
+            existing.map(|slot| slot as usize).unwrap_or_else(|| {
+                slot_positions.push(point);
+                grid.insert(vertex.position, slot_positions.len() as u32 - 1);
+                slot_positions.len() - 1
+            })
+        })
+        .collect();
+
+    let mut sums = vec![Vector3::zeros(); slot_positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let Some(normal) = face_cross(vertices, triangle).try_normalize(f32::EPSILON) else {
+            continue;
+        };
+        let angles = corner_angles(vertices, triangle);
+        for (corner, angle) in triangle.iter().zip(angles) {
+            sums[slots[*corner as usize]] += normal * angle;
+        }
+    }
+
+    for (vertex, slot) in vertices.iter_mut().zip(slots) {
+        let normal = sums[slot].try_normalize(f32::EPSILON).unwrap_or_else(Vector3::y);
+        vertex.normal = normal.into();
+    }
+}
+
+/// Returns the mesh with every triangle given its own vertices carrying its face normal
+pub fn compute_flat_normals(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut flat = Vec::with_capacity(indices.len());
+    for triangle in indices.chunks_exact(3) {
+        let normal = face_cross(vertices, triangle)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(Vector3::y);
+        for &index in triangle {
+            flat.push(Vertex {
+                normal: normal.into(),
+                ..vertices[index as usize]
+            });
+        }
+    }
+    let indices = (0..flat.len() as u32).collect();
+    (flat, indices)
+}
+
+/// Generates MikkTSpace tangents, returned per vertex as `[x, y, z, handedness]`
+///
+/// Uses the reference MikkTSpace algorithm, so normal maps baked by common tools shade
+/// without seams. The bitangent is `cross(normal, tangent) * handedness`. MikkTSpace gives
+/// every triangle corner its own tangent; vertices whose corners disagree, e.g. on mirrored
+/// UV seams, are split, the copies appended to `vertices` and `indices` updated.
+pub fn generate_tangents(
+    vertices: &mut Vec<Vertex>,
+    indices: &mut [u32],
+) -> Result<Vec<[f32; 4]>> {
+    let mut geometry = TangentGeometry {
+        vertices,
+        indices,
+        corners: indices
+            .iter()
+            .map(|&index| fallback_tangent(vertices[index as usize].normal))
+            .collect(),
+    };
+    if !indices.is_empty() && !bevy_mikktspace::generate_tangents(&mut geometry) {
+        return Err(anyhow!("MikkTSpace could not generate tangents for the mesh"));
+    }
+    let corners = geometry.corners;
+
+    // Corners of one vertex with the same tangent share it; every other tangent gets a copy
+    let mut tangents: Vec<Option<[f32; 4]>> = vec![None; vertices.len()];
+    let mut copies: HashMap<(u32, [u32; 4]), u32> = HashMap::new();
+    for (index, tangent) in indices.iter_mut().zip(corners) {
+        let original = *index;
+        match tangents[original as usize] {
+            None => tangents[original as usize] = Some(tangent),
+            Some(kept) if kept == tangent => {}
+            Some(_) => {
+                *index = *copies
+                    .entry((original, tangent.map(f32::to_bits)))
+                    .or_insert_with(|| {
+                        vertices.push(vertices[original as usize]);
+                        tangents.push(Some(tangent));
+                        vertices.len() as u32 - 1
+                    });
+            }
+        }
+    }
+
+    // Vertices no triangle uses get any tangent perpendicular to the normal
+    Ok(tangents
+        .iter()
+        .zip(vertices.iter())
+        .map(|(tangent, vertex)| tangent.unwrap_or_else(|| fallback_tangent(vertex.normal)))
+        .collect())
+}
+
+/// A right-handed tangent perpendicular to `normal`, for corners MikkTSpace leaves out
+fn fallback_tangent(normal: [f32; 3]) -> [f32; 4] {
+    let normal = Vector3::from(normal);
+    let tangent = normal
+        .cross(&Vector3::y())
+        .try_normalize(f32::EPSILON)
+        .unwrap_or_else(Vector3::x);
+    [tangent.x, tangent.y, tangent.z, 1.0]
+}
+
+/// Triangles as MikkTSpace reads them, collecting one tangent per corner
+struct TangentGeometry<'a> {
+    vertices: &'a [Vertex],
+    indices: &'a [u32],
+    corners: Vec<[f32; 4]>,
+}
+
+impl TangentGeometry<'_> {
+    fn vertex(&self, face: usize, vert: usize) -> &Vertex {
+        &self.vertices[self.indices[face * 3 + vert] as usize]
+    }
+}
+
+impl bevy_mikktspace::Geometry for TangentGeometry<'_> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).position
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).normal
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.vertex(face, vert).tex_coords
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.corners[face * 3 + vert] = tangent;
+    }
+}
+
+/// Merges vertices whose position, normal and UVs all differ by at most `epsilon`
+///
+/// Returns the unique vertices in first-use order and the remapped indices. Useful after
+/// importers that store one vertex per triangle corner.
+pub fn weld_vertices(
+    vertices: &[Vertex],
+    indices: &[u32],
+    epsilon: f32,
+) -> (Vec<Vertex>, Vec<u32>) {
+    let attributes = |vertex: &Vertex| {
+        let [px, py, pz] = vertex.position;
+        let [nx, ny, nz] = vertex.normal;
+        let [u, v] = vertex.tex_coords;
+        [px, py, pz, nx, ny, nz, u, v]
+    };
+    let matches = |a: &Vertex, b: &Vertex| {
+        attributes(a)
+            .iter()
+            .zip(attributes(b))
+            .all(|(a, b)| (a - b).abs() <= epsilon)
+    };
+
+    let mut welded: Vec<Vertex> = Vec::new();
+    let mut grid = PointGrid::new(epsilon);
+    let mut remap: Vec<Option<u32>> = vec![None; vertices.len()];
+    let mut welded_indices = Vec::with_capacity(indices.len());
+
+    for &index in indices {
+        let vertex = &vertices[index as usize];
+        let target = *remap[index as usize].get_or_insert_with(|| {
+            let existing = grid.find(vertex.position, |candidate| {
+                matches(&welded[candidate as usize], vertex)
+            });
+            existing.unwrap_or_else(|| {
+                welded.push(*vertex);
+                let target = welded.len() as u32 - 1;
+                grid.insert(vertex.position, target);
+                target
+            })
+        });
+        welded_indices.push(target);
+    }
+    (welded, welded_indices)
+}
+
+/// Spatial hash of points for finding earlier ones within a small distance of a new one
+struct PointGrid {
+    cell_size: f32,
+    cells: HashMap<[i64; 3], Vec<u32>>,
+}
+
+impl PointGrid {
+    fn new(epsilon: f32) -> Self {
+        Self {
+            // Cells of at least twice the search distance only need their direct neighbours
+            cell_size: epsilon.max(1e-6) * 2.0,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell(&self, point: [f32; 3]) -> [i64; 3] {
+        point.map(|axis| (axis / self.cell_size).floor() as i64)
+    }
+
+    /// Returns the first point in this or a neighbouring cell accepted by `matches`
+    fn find(&self, point: [f32; 3], mut matches: impl FnMut(u32) -> bool) -> Option<u32> {
+        let [x, y, z] = self.cell(point);
+        for dx in -1..=1i64 {
+            for dy in -1..=1i64 {
+                for dz in -1..=1i64 {
+                    let cell = [x.wrapping_add(dx), y.wrapping_add(dy), z.wrapping_add(dz)];
+                    let found = self
+                        .cells
+                        .get(&cell)
+                        .and_then(|ids| ids.iter().copied().find(|&id| matches(id)));
+                    if found.is_some() {
+                        return found;
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn insert(&mut self, point: [f32; 3], id: u32) {
+        let cell = self.cell(point);
+        self.cells.entry(cell).or_default().push(id);
+    }
+}
+
+/// Returns the average number of vertex shader invocations per triangle with a FIFO cache
+///
+/// Ranges from about 0.5 for a perfectly ordered grid to 3.0 when no vertex is ever reused.
+pub fn average_cache_miss_ratio(indices: &[u32], cache_size: usize) -> f32 {
+    let triangles = indices.len() / 3;
+    if triangles == 0 {
+        return 0.0;
+    }
+    let mut cache = VecDeque::with_capacity(cache_size);
+    let mut misses = 0;
+    for &index in indices {
+        if !cache.contains(&index) {
+            misses += 1;
+            if cache.len() == cache_size {
+                cache.pop_front();
+            }
+            cache.push_back(index);
+        }
+    }
+    misses as f32 / triangles as f32
+}
+
+/// Score of a vertex for `optimize_vertex_cache`, from its cache position and open triangles
+fn vertex_score(cache_position: Option<usize>, open_triangles: usize) -> f32 {
+    if open_triangles == 0 {
+        return -1.0;
+    }
+    let cache_score = match cache_position {
+        // The last triangle's vertices are scored lower so strips do not turn back on themselves
+        Some(position) if position < 3 => 0.75,
+        Some(position) => {
+            let scale = 1.0 - (position - 3) as f32 / (CACHE_SIZE - 3) as f32;
+            scale.powf(1.5)
+        }
+        None => 0.0,
+    };
+    // Prefer vertices with few triangles left so they can leave the cache early
+    cache_score + 2.0 * (open_triangles as f32).powf(-0.5)
+}
+
+/// Reorders triangles so vertices are reused while still in the post-transform cache
+///
+/// Uses Forsyth's linear-speed greedy algorithm, which works well for any cache size
+/// without knowing the exact one. The triangles themselves and their winding are kept.
+pub fn optimize_vertex_cache(indices: &mut [u32], vertex_count: usize) {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return;
+    }
+
+    let mut vertex_triangles = vec![Vec::new(); vertex_count];
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        for &index in corners {
+            vertex_triangles[index as usize].push(triangle);
+        }
+    }
+
+    let mut open: Vec<usize> = vertex_triangles.iter().map(Vec::len).collect();
+    let mut cache_position: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut scores: Vec<f32> = open.iter().map(|&count| vertex_score(None, count)).collect();
+    let triangle_score = |scores: &[f32], triangle: usize| {
+        indices[triangle * 3..triangle * 3 + 3]
+            .iter()
+            .map(|&index| scores[index as usize])
+            .sum::<f32>()
+    };
+    let mut emitted = vec![false; triangle_count];
+    let mut order = Vec::with_capacity(triangle_count);
+    let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut next_unemitted = 0;
+
+    let mut best = (0..triangle_count)
+        .max_by(|&a, &b| triangle_score(&scores, a).total_cmp(&triangle_score(&scores, b)));
+
+    while let Some(triangle) = best {
+        emitted[triangle] = true;
+        order.push(triangle);
+        let corners = &indices[triangle * 3..triangle * 3 + 3];
+        for &index in corners {
+            open[index as usize] -= 1;
+        }
+
+        // Move the triangle's vertices to the front of the LRU cache
+        let mut new_cache: Vec<u32> = corners.to_vec();
+        new_cache.extend(cache.iter().copied().filter(|index| !corners.contains(index)));
+        for &evicted in new_cache.iter().skip(CACHE_SIZE) {
+            cache_position[evicted as usize] = None;
+            scores[evicted as usize] = vertex_score(None, open[evicted as usize]);
+        }
+        new_cache.truncate(CACHE_SIZE);
+        cache = new_cache;
+
+        for (position, &index) in cache.iter().enumerate() {
+            cache_position[index as usize] = Some(position);
+            scores[index as usize] = vertex_score(Some(position), open[index as usize]);
+        }
+
+        // Only triangles of cached vertices changed their score
+        best = cache
+            .iter()
+            .flat_map(|&index| vertex_triangles[index as usize].iter().copied())
+            .filter(|&candidate| !emitted[candidate])
+            .max_by(|&a, &b| triangle_score(&scores, a).total_cmp(&triangle_score(&scores, b)));
+
+        if best.is_none() {
+            while next_unemitted < triangle_count && emitted[next_unemitted] {
+                next_unemitted += 1;
+            }
+            best = (next_unemitted < triangle_count).then_some(next_unemitted);
+        }
+    }
+
+    let reordered: Vec<u32> = order
+        .iter()
+        .flat_map(|&triangle| indices[triangle * 3..triangle * 3 + 3].to_vec())
+        .collect();
+    indices.copy_from_slice(&reordered);
+}
+
+/// Reorders clusters of cache-optimized triangles so that outer surfaces are drawn first
+///
+/// Splits the triangles where the vertex cache starts over, merges neighbouring clusters
+/// while the cache miss ratio stays within `threshold` times the original (e.g. 1.05), then
+/// sorts the clusters to draw those facing away from the mesh center first, so the depth
+/// test rejects more of the fragments behind them. Call after `optimize_vertex_cache`.
+pub fn optimize_overdraw(vertices: &[Vertex], indices: &mut [u32], threshold: f32) {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return;
+    }
+
+    // Hard boundaries: triangles whose three vertices all miss the cache, and the first
+    // triangle, which may be degenerate or repeat an index but still starts a cluster
+    let mut cache = VecDeque::with_capacity(DEFAULT_CACHE_SIZE);
+    let mut starts = Vec::new();
+    let mut misses_before = Vec::with_capacity(triangle_count + 1);
+    let mut misses = 0;
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        misses_before.push(misses);
+        let mut triangle_misses = 0;
+        for &index in corners {
+            if !cache.contains(&index) {
+                triangle_misses += 1;
+                if cache.len() == DEFAULT_CACHE_SIZE {
+                    cache.pop_front();
+                }
+                cache.push_back(index);
+            }
+        }
+        if triangle == 0 || triangle_misses == 3 {
+            starts.push(triangle);
+        }
+        misses += triangle_misses;
+    }
+    misses_before.push(misses);
+
+    // Soft boundaries: grow clusters over hard boundaries until their own miss ratio is low
+    // enough, since a short cluster starts with a cold cache wherever it is drawn
+    let limit = misses as f32 / triangle_count as f32 * threshold;
+    let miss_ratio = |cluster: &Range<usize>| {
+        let cluster_misses = misses_before[cluster.end] - misses_before[cluster.start];
+        cluster_misses as f32 / cluster.len() as f32
+    };
+    let mut clusters: Vec<Range<usize>> = Vec::new();
+    for (position, &start) in starts.iter().enumerate() {
+        let end = starts.get(position + 1).copied().unwrap_or(triangle_count);
+        match clusters.last_mut() {
+            Some(cluster) if miss_ratio(cluster) > limit => cluster.end = end,
+            _ => clusters.push(start..end),
+        }
+    }
+
+    let area_weighted_center = |range: Range<usize>| {
+        let mut center = Vector3::zeros();
+        let mut normal = Vector3::zeros();
+        let mut area = 0.0;
+        for triangle in indices[range.start * 3..range.end * 3].chunks_exact(3) {
+            let cross = face_cross(vertices, triangle);
+            let [a, b, c] = corners(vertices, triangle);
+            let triangle_area = cross.norm() * 0.5;
+            center += (a.coords + b.coords + c.coords) / 3.0 * triangle_area;
+            normal += cross;
+            area += triangle_area;
+        }
+        let center = if area > 0.0 { center / area } else { center };
+        (center, normal.try_normalize(f32::EPSILON).unwrap_or_default(), area)
+    };
+
+    let (mesh_center, _, _) = area_weighted_center(0..triangle_count);
+    let mut sorted: Vec<(f32, Range<usize>)> = clusters
+        .into_iter()
+        .map(|cluster| {
+            let (center, normal, _) = area_weighted_center(cluster.clone());
+            ((center - mesh_center).dot(&normal), cluster)
+        })
+        .collect();
+    sorted.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let reordered: Vec<u32> = sorted
+        .iter()
+        .flat_map(|(_, cluster)| indices[cluster.start * 3..cluster.end * 3].to_vec())
+        .collect();
+    indices.copy_from_slice(&reordered);
+}
+
+/// Reorders vertices into the order the indices first use them, dropping unused ones
+///
+/// Improves memory locality of vertex fetches; call after reordering the triangles.
+pub fn optimize_vertex_fetch(vertices: &[Vertex], indices: &mut [u32]) -> Vec<Vertex> {
+    let mut remap: Vec<Option<u32>> = vec![None; vertices.len()];
+    let mut reordered = Vec::with_capacity(vertices.len());
+    for index in indices.iter_mut() {
+        let original = *index as usize;
+        *index = *remap[original].get_or_insert_with(|| {
+            reordered.push(vertices[original]);
+            reordered.len() as u32 - 1
+        });
+    }
+    reordered
+}
+
+/// Runs the cache, overdraw and fetch optimizations in order, for meshes about to be uploaded
+pub fn optimize_mesh(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut indices = indices.to_vec();
+    optimize_vertex_cache(&mut indices, vertices.len());
+    optimize_overdraw(vertices, &mut indices, 1.05);
+    let vertices = optimize_vertex_fetch(vertices, &mut indices);
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::buffer::create_cube;
+    use crate::engine::primitives::{plane, uv_sphere};
+
+    /// Returns the triangles as corner positions, rotated and sorted to compare meshes
+    fn triangle_set(vertices: &[Vertex], indices: &[u32]) -> Vec<[[u32; 3]; 3]> {
+        let mut triangles: Vec<[[u32; 3]; 3]> = indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let corners = [0, 1, 2]
+                    .map(|corner| vertices[triangle[corner] as usize].position.map(f32::to_bits));
+                // Rotate the smallest corner first, keeping the winding
+                let first = (0..3).min_by_key(|&corner| corners[corner]).unwrap_or(0);
+                [0, 1, 2].map(|offset| corners[(first + offset) % 3])
+            })
+            .collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    /// Shuffles the triangle order without changing any triangle
+    fn shuffled(indices: &[u32]) -> Vec<u32> {
+        let count = indices.len() / 3;
+        (0..count)
+            .flat_map(|triangle| {
+                let source = (triangle * 7919) % count;
+                indices[source * 3..source * 3 + 3].to_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn smooth_normals_of_a_sphere_point_outwards_across_seams() {
+        let (mut vertices, indices) = uv_sphere(1.0, 32, 16);
+        for vertex in &mut vertices {
+            vertex.normal = [0.0; 3];
+        }
+        compute_smooth_normals(&mut vertices, &indices);
+        for vertex in &vertices {
+            let expected = Vector3::from(vertex.position).normalize();
+            assert!((Vector3::from(vertex.normal) - expected).norm() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn flat_normals_match_the_face_of_every_triangle() {
+        let (vertices, indices) = uv_sphere(1.0, 16, 8);
+        let (flat, flat_indices) = compute_flat_normals(&vertices, &indices);
+        assert_eq!(flat.len(), indices.len());
+        for triangle in flat_indices.chunks_exact(3) {
+            let face = face_cross(&flat, triangle).normalize();
+            for &index in triangle {
+                assert!((Vector3::from(flat[index as usize].normal) - face).norm() < 1e-5);
+            }
+        }
+        assert_eq!(triangle_set(&vertices, &indices), triangle_set(&flat, &flat_indices));
+    }
+
+    #[test]
+    fn welding_merges_nearly_equal_vertices_only() {
+        let (vertices, indices) = create_cube();
+        let (mut unwelded, unwelded_indices) = compute_flat_normals(&vertices, &indices);
+        for (offset, vertex) in unwelded.iter_mut().enumerate() {
+            vertex.position[0] += offset as f32 * 1e-7;
+        }
+
+        let (welded, welded_indices) = weld_vertices(&unwelded, &unwelded_indices, 1e-4);
+        // Corners of different faces differ in their normals and must stay apart
+        assert_eq!(welded.len(), 24);
+        assert_eq!(welded_indices.len(), indices.len());
+
+        let (exact, _) = weld_vertices(&unwelded, &unwelded_indices, 0.0);
+        assert_eq!(exact.len(), unwelded.len());
+    }
+
+    fn assert_tangent(tangent: [f32; 4], expected: [f32; 4]) {
+        let distance = (Vector3::new(tangent[0], tangent[1], tangent[2])
+            - Vector3::new(expected[0], expected[1], expected[2]))
+        .norm();
+        assert!(distance < 1e-5, "tangent {:?}, expected {:?}", tangent, expected);
+        assert_eq!(tangent[3], expected[3]);
+    }
+
+    #[test]
+    fn tangents_split_a_mirrored_uv_seam() {
+        let (mut vertices, mut indices) = plane(2.0, 1.0, 2, 1);
+        // Mirror the texture on the right half, so U runs back along -X there
+        for vertex in &mut vertices {
+            vertex.tex_coords[0] = 1.0 - (2.0 * vertex.tex_coords[0] - 1.0).abs();
+        }
+
+        let tangents = generate_tangents(&mut vertices, &mut indices).unwrap();
+        assert_eq!(tangents.len(), vertices.len());
+        // Both vertices on the mirror line are split, one copy per side
+        assert_eq!(vertices.len(), 6 + 2);
+
+        // V runs along -Z, so the left half is right-handed and the mirrored half left-handed
+        for triangle in indices.chunks_exact(3) {
+            let right_half = triangle
+                .iter()
+                .any(|&index| vertices[index as usize].position[0] > 0.5);
+            let expected = if right_half {
+                [-1.0, 0.0, 0.0, -1.0]
+            } else {
+                [1.0, 0.0, 0.0, 1.0]
+            };
+            for &index in triangle {
+                assert_tangent(tangents[index as usize], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn tangents_stay_per_face_across_cube_seams() {
+        let (mut vertices, mut indices) = create_cube();
+        // Reference tangents of each face: the direction of +U and the bitangent sign
+        let expected = [
+            [1.0, 0.0, 0.0, 1.0],
+            [-1.0, 0.0, 0.0, 1.0],
+            [1.0, 0.0, 0.0, 1.0],
+            [-1.0, 0.0, 0.0, 1.0],
+            [0.0, 0.0, -1.0, 1.0],
+            [0.0, 0.0, 1.0, 1.0],
+        ];
+
+        // Corners on an edge share positions but not normals, so they are not averaged across
+        // the edge; faces already have their own vertices, so nothing needs splitting
+        let tangents = generate_tangents(&mut vertices, &mut indices).unwrap();
+        assert_eq!(vertices.len(), 24);
+        for (face, expected) in expected.into_iter().enumerate() {
+            for &index in &indices[face * 6..face * 6 + 6] {
+                assert_tangent(tangents[index as usize], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn tangents_of_an_empty_mesh() {
+        let tangents = generate_tangents(&mut Vec::new(), &mut []).unwrap();
+        assert!(tangents.is_empty());
+    }
+
+    #[test]
+    fn vertex_cache_optimization_reduces_misses_and_keeps_triangles() {
+        let (vertices, indices) = uv_sphere(1.0, 64, 32);
+        let mut indices = shuffled(&indices);
+        let before = average_cache_miss_ratio(&indices, DEFAULT_CACHE_SIZE);
+
+        optimize_vertex_cache(&mut indices, vertices.len());
+        let after = average_cache_miss_ratio(&indices, DEFAULT_CACHE_SIZE);
+        assert!(after < 0.8 && after < before, "{} -> {}", before, after);
+        assert_eq!(
+            triangle_set(&vertices, &indices),
+            triangle_set(&vertices, &shuffled(&uv_sphere(1.0, 64, 32).1))
+        );
+    }
+
+    #[test]
+    fn optimized_meshes_keep_their_triangles() {
+        let (vertices, indices) = uv_sphere(1.0, 48, 24);
+        let (optimized, optimized_indices) = optimize_mesh(&vertices, &shuffled(&indices));
+        assert_eq!(triangle_set(&vertices, &indices), triangle_set(&optimized, &optimized_indices));
+        assert!(optimized.len() <= vertices.len());
+
+        let mut cache_only = shuffled(&indices);
+        optimize_vertex_cache(&mut cache_only, vertices.len());
+        let cache_ratio = average_cache_miss_ratio(&cache_only, DEFAULT_CACHE_SIZE);
+        let final_ratio = average_cache_miss_ratio(&optimized_indices, DEFAULT_CACHE_SIZE);
+        assert!(final_ratio <= cache_ratio * 1.1, "{} -> {}", cache_ratio, final_ratio);
+    }
+
+    /// Returns the index triples sorted, to check a reordering is a permutation of triangles
+    fn sorted_triangles(indices: &[u32]) -> Vec<[u32; 3]> {
+        let mut triangles: Vec<[u32; 3]> = indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    #[test]
+    fn overdraw_optimization_keeps_a_degenerate_first_triangle() {
+        let (vertices, indices) = uv_sphere(1.0, 16, 8);
+        let mut with_degenerate = vec![0, 0, 1];
+        with_degenerate.extend_from_slice(&indices);
+        optimize_vertex_cache(&mut with_degenerate[3..], vertices.len());
+        let before = with_degenerate.clone();
+
+        optimize_overdraw(&vertices, &mut with_degenerate, 1.05);
+        assert_eq!(sorted_triangles(&before), sorted_triangles(&with_degenerate));
+    }
+
+    #[test]
+    fn overdraw_optimization_permutes_whole_triangles() {
+        let (vertices, indices) = uv_sphere(1.0, 48, 24);
+        let mut optimized = shuffled(&indices);
+        optimize_vertex_cache(&mut optimized, vertices.len());
+        let before = optimized.clone();
+
+        optimize_overdraw(&vertices, &mut optimized, 1.05);
+        assert_eq!(sorted_triangles(&before), sorted_triangles(&optimized));
+    }
+}
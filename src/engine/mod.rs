@@ -3,9 +3,31 @@
 pub mod instance;
 pub mod swapchain;
 pub mod pipeline;
+pub mod pipeline_cache;
 pub mod buffer;
+pub mod capture;
+pub mod bounds;
+pub mod primitives;
+pub mod mesh_processing;
+pub mod mesh;
+pub mod vertex_format;
+pub mod simplify;
+pub mod lod;
+pub mod camera;
+pub mod render_queue;
+pub mod scene;
+pub mod scene_file;
+pub mod ecs;
+pub mod components;
+pub mod render_graph;
+pub mod render_target;
+pub mod recording;
 pub mod renderer;
 pub mod shader_loader;
+pub mod reflection;
+pub mod compute;
+pub mod gpu_culling;
+pub mod constants;
 
 // Re-export commonly used types
 pub use renderer::Renderer;
@@ -1,63 +1,735 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use log::info;
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 use vulkano::device::Device;
 use vulkano::format::Format;
-use vulkano::pipeline::graphics::color_blend::{ColorBlendAttachmentState, ColorBlendState};
-use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
+use vulkano::image::{SampleCount, SampleCounts};
+use vulkano::pipeline::graphics::color_blend::{
+    AttachmentBlend, BlendFactor, BlendOp, ColorBlendState,
+};
+use vulkano::pipeline::graphics::depth_stencil::{CompareOp, DepthState, DepthStencilState};
 use vulkano::pipeline::graphics::multisample::MultisampleState;
-use vulkano::pipeline::graphics::rasterization::RasterizationState;
-use vulkano::pipeline::graphics::vertex_input::VertexInputState;
-use vulkano::pipeline::graphics::viewport::{Viewport, ViewportState};
-use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineLayout};
-use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
-use vulkano::shader::ShaderModule;
-
-use crate::engine::buffer::Vertex;
-
-/// Creates a render pass for our rendering pipeline
-pub fn create_render_pass(device: Arc<Device>, format: Format) -> Result<Arc<RenderPass>> {
-    let render_pass = vulkano::single_pass_renderpass!(
-        device.clone(),
-        attachments: {
-            color: {
-                load: Clear,
-                store: Store,
-                format: format,
-                samples: 1,
-            }
+use vulkano::pipeline::graphics::input_assembly::{InputAssemblyState, PrimitiveTopology};
+use vulkano::pipeline::graphics::rasterization::{
+    CullMode, DepthBias, DepthBiasState, FrontFace, PolygonMode, RasterizationState,
+};
+use vulkano::pipeline::graphics::vertex_input::{
+    Vertex as VertexType, VertexBufferDescription, VertexInputRate,
+};
+use vulkano::pipeline::graphics::viewport::{Scissor, Viewport, ViewportState};
+use vulkano::pipeline::cache::PipelineCache;
+use vulkano::pipeline::{GraphicsPipeline, StateMode};
+use vulkano::render_pass::{RenderPass, Subpass};
+use vulkano::shader::{ShaderModule, SpecializationConstants};
+
+use crate::engine::buffer::{InstanceData, Vertex};
+use crate::engine::constants::validate_specialization_constants;
+use crate::engine::reflection::{create_pipeline_layout, vertex_input_state_from_buffers};
+use crate::engine::render_graph::{AttachmentFormat, AttachmentKind, SubpassLayout};
+
+/// Format of the depth attachment; `D16_UNORM` is supported as a depth attachment everywhere
+pub const DEPTH_FORMAT: Format = Format::D16_UNORM;
+
+/// Format of the weighted blended OIT accumulation target
+pub const OIT_ACCUMULATION_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// Format of the weighted blended OIT revealage target
+pub const OIT_REVEALAGE_FORMAT: Format = Format::R8_UNORM;
+
+/// Subpass drawing opaque geometry
+pub const OPAQUE_SUBPASS: u32 = 0;
+
+/// Subpass accumulating order-independent transparent geometry into the OIT targets
+pub const OIT_ACCUMULATION_SUBPASS: u32 = 1;
+
+/// Subpass compositing the OIT targets, then drawing sorted transparent geometry and overlays
+pub const TRANSPARENT_SUBPASS: u32 = 2;
+
+/// Sample counts offered for multisample anti-aliasing
+pub const MSAA_SAMPLE_COUNTS: [SampleCount; 4] = [
+    SampleCount::Sample1,
+    SampleCount::Sample2,
+    SampleCount::Sample4,
+    SampleCount::Sample8,
+];
+
+/// Returns the highest sample count up to `requested` that every attachment supports
+///
+/// Color, depth and the OIT targets all share the sample count, so the device's color and
+/// depth framebuffer limits both apply.
+pub fn supported_sample_count(device: &Device, requested: u32) -> SampleCount {
+    let supported = attachment_sample_counts(device);
+
+    MSAA_SAMPLE_COUNTS
+        .into_iter()
+        .rev()
+        .find(|&count| u32::from(count) <= requested.max(1) && supported.contains_enum(count))
+        .unwrap_or(SampleCount::Sample1)
+}
+
+/// Returns the sample counts both color and depth framebuffer attachments support
+pub fn attachment_sample_counts(device: &Device) -> SampleCounts {
+    let properties = device.physical_device().properties();
+    properties.framebuffer_color_sample_counts & properties.framebuffer_depth_sample_counts
+}
+
+/// Returns the next larger count of `MSAA_SAMPLE_COUNTS` in `supported`, skipping unsupported
+/// ones, or one sample after the largest
+pub fn next_sample_count(current: SampleCount, supported: SampleCounts) -> SampleCount {
+    MSAA_SAMPLE_COUNTS
+        .into_iter()
+        .find(|&count| u32::from(count) > u32::from(current) && supported.contains_enum(count))
+        .unwrap_or(SampleCount::Sample1)
+}
+
+/// Attachments of the scene pass in declaration order: color, OIT accumulation and revealage,
+/// the swapchain image as resolve target when multisampled, then depth
+///
+/// With more than one sample every attachment but the resolve target is multisampled, and the
+/// color attachment is a transient image instead of the swapchain image.
+pub fn scene_attachments(format: Format, samples: SampleCount) -> Vec<AttachmentFormat> {
+    let attachment = |format, kind| AttachmentFormat {
+        format,
+        samples,
+        kind,
+    };
+    let mut attachments = vec![
+        attachment(format, AttachmentKind::Color),
+        attachment(OIT_ACCUMULATION_FORMAT, AttachmentKind::Color),
+        attachment(OIT_REVEALAGE_FORMAT, AttachmentKind::Color),
+    ];
+    if samples != SampleCount::Sample1 {
+        attachments.push(AttachmentFormat {
+            samples: SampleCount::Sample1,
+            ..attachment(format, AttachmentKind::Resolve)
+        });
+    }
+    attachments.push(attachment(DEPTH_FORMAT, AttachmentKind::Depth));
+    attachments
+}
+
+/// Subpasses of the scene pass over the attachments of `scene_attachments`
+///
+/// Opaque geometry is drawn first, then weighted blended OIT geometry into two offscreen
+/// targets, which the last subpass reads as input attachments and composites onto the color
+/// attachment before sorted transparent geometry is drawn. With more than one sample, the
+/// color attachment is resolved into the swapchain image at the end of the last subpass.
+pub fn scene_subpasses(samples: SampleCount) -> Vec<SubpassLayout> {
+    let resolve = if samples == SampleCount::Sample1 {
+        Vec::new()
+    } else {
+        vec![3]
+    };
+    vec![
+        SubpassLayout {
+            color: vec![0],
+            ..Default::default()
+        },
+        SubpassLayout {
+            color: vec![1, 2],
+            ..Default::default()
+        },
+        SubpassLayout {
+            color: vec![0],
+            input: vec![1, 2],
+            resolve,
         },
-        pass: {
-            color: [color],
-            depth_stencil: {}
+    ]
+}
+
+/// A shader module together with the entry point to use from it
+///
+/// Modules compare by identity, so two loads of the same file are different stages.
+#[derive(Clone, Debug)]
+pub struct ShaderStageDesc {
+    pub module: Arc<ShaderModule>,
+    pub entry_point: String,
+}
+
+impl ShaderStageDesc {
+    /// Uses the `main` entry point of a module
+    pub fn main(module: Arc<ShaderModule>) -> Self {
+        Self {
+            module,
+            entry_point: "main".to_string(),
         }
-    )?;
+    }
+}
 
-    info!("Render pass created successfully");
-    Ok(render_pass)
+impl PartialEq for ShaderStageDesc {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.module, &other.module) && self.entry_point == other.entry_point
+    }
 }
 
-/// Creates framebuffers for each swapchain image
-pub fn create_framebuffers(
-    images: &[Arc<vulkano::image::SwapchainImage>],
-    render_pass: Arc<RenderPass>,
-) -> Result<Vec<Arc<Framebuffer>>> {
-    let framebuffers = images
+impl Eq for ShaderStageDesc {}
+
+impl Hash for ShaderStageDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.module).hash(state);
+        self.entry_point.hash(state);
+    }
+}
+
+/// The vertex buffers a pipeline reads, one description per binding
+#[derive(Clone, Debug)]
+pub struct VertexLayout(pub Vec<VertexBufferDescription>);
+
+impl VertexLayout {
+    /// A layout with a single per-vertex buffer of type `V`
+    pub fn of<V: VertexType>() -> Self {
+        Self(vec![V::per_vertex()])
+    }
+
+    /// A layout with a per-vertex buffer of `V` at binding 0 and a per-instance buffer of `I`
+    /// at binding 1
+    pub fn instanced<V: VertexType, I: VertexType>() -> Self {
+        Self(vec![V::per_vertex(), I::per_instance()])
+    }
+
+    /// Returns the members of a binding sorted by name, for comparison and hashing
+    fn sorted_members(description: &VertexBufferDescription) -> Vec<(&String, usize, Format, u32)> {
+        let mut members: Vec<_> = description
+            .members
+            .iter()
+            .map(|(name, info)| (name, info.offset, info.format, info.num_elements))
+            .collect();
+        members.sort_by(|a, b| a.0.cmp(b.0));
+        members
+    }
+}
+
+impl PartialEq for VertexLayout {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self.0.iter().zip(&other.0).all(|(a, b)| {
+                a.stride == b.stride
+                    && a.input_rate == b.input_rate
+                    && Self::sorted_members(a) == Self::sorted_members(b)
+            })
+    }
+}
+
+impl Eq for VertexLayout {}
+
+impl Hash for VertexLayout {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for description in &self.0 {
+            description.stride.hash(state);
+            match description.input_rate {
+                VertexInputRate::Vertex => 0u32.hash(state),
+                VertexInputRate::Instance { divisor } => (1u32, divisor).hash(state),
+            }
+            Self::sorted_members(description).hash(state);
+        }
+    }
+}
+
+/// Constant and slope-scaled depth offset applied to rasterized polygons
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthBiasDesc {
+    pub constant_factor: f32,
+    pub clamp: f32,
+    pub slope_factor: f32,
+}
+
+impl DepthBiasDesc {
+    /// Returns the bit patterns of the factors, for exact comparison and hashing
+    fn bits(&self) -> [u32; 3] {
+        [
+            self.constant_factor.to_bits(),
+            self.clamp.to_bits(),
+            self.slope_factor.to_bits(),
+        ]
+    }
+}
+
+/// Rasterization settings of a pipeline
+#[derive(Clone, Copy, Debug)]
+pub struct RasterDesc {
+    /// Fill, line or point rasterization; anything but fill needs `fill_mode_non_solid`
+    pub polygon_mode: PolygonMode,
+    pub cull_mode: CullMode,
+    pub front_face: FrontFace,
+    /// Depth offset, or `None` to disable depth biasing
+    pub depth_bias: Option<DepthBiasDesc>,
+    /// Width of rasterized lines; anything but 1.0 needs `wide_lines`
+    pub line_width: f32,
+}
+
+impl RasterDesc {
+    /// Line rasterization of every triangle, for wireframe views
+    pub fn wireframe() -> Self {
+        Self {
+            polygon_mode: PolygonMode::Line,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for RasterDesc {
+    fn default() -> Self {
+        Self {
+            polygon_mode: PolygonMode::Fill,
+            cull_mode: CullMode::None,
+            front_face: FrontFace::CounterClockwise,
+            depth_bias: None,
+            line_width: 1.0,
+        }
+    }
+}
+
+impl PartialEq for RasterDesc {
+    fn eq(&self, other: &Self) -> bool {
+        self.polygon_mode == other.polygon_mode
+            && self.cull_mode == other.cull_mode
+            && self.front_face == other.front_face
+            && self.depth_bias.map(|bias| bias.bits()) == other.depth_bias.map(|bias| bias.bits())
+            && self.line_width.to_bits() == other.line_width.to_bits()
+    }
+}
+
+impl Eq for RasterDesc {}
+
+impl Hash for RasterDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.polygon_mode.hash(state);
+        self.cull_mode.hash(state);
+        self.front_face.hash(state);
+        self.depth_bias.map(|bias| bias.bits()).hash(state);
+        self.line_width.to_bits().hash(state);
+    }
+}
+
+/// Named color blending presets applied to every color attachment of a pipeline
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendPreset {
+    /// Blending disabled
+    #[default]
+    Opaque,
+    /// Straight alpha: `src * a + dst * (1 - a)`
+    Alpha,
+    /// Color already multiplied by alpha: `src + dst * (1 - a)`
+    Premultiplied,
+    /// Light-like accumulation: `src * a + dst`
+    Additive,
+    /// Darkening by the source color: `src * dst`
+    Multiply,
+    /// Weighted blended OIT targets: additive accumulation in attachment 0 and the product of
+    /// `1 - a` in the revealage attachment 1; needs `independent_blend`
+    OitAccumulation,
+}
+
+impl BlendPreset {
+    /// Returns the blend state of a color attachment, or `None` when blending is disabled
+    pub fn attachment_blend(self, attachment: u32) -> Option<AttachmentBlend> {
+        let blend = |color_source, color_destination, alpha_source, alpha_destination| {
+            AttachmentBlend {
+                color_op: BlendOp::Add,
+                color_source,
+                color_destination,
+                alpha_op: BlendOp::Add,
+                alpha_source,
+                alpha_destination,
+            }
+        };
+
+        match self {
+            BlendPreset::Opaque => None,
+            BlendPreset::Alpha => Some(AttachmentBlend::alpha()),
+            BlendPreset::Premultiplied => Some(blend(
+                BlendFactor::One,
+                BlendFactor::OneMinusSrcAlpha,
+                BlendFactor::One,
+                BlendFactor::OneMinusSrcAlpha,
+            )),
+            BlendPreset::Additive => Some(blend(
+                BlendFactor::SrcAlpha,
+                BlendFactor::One,
+                BlendFactor::Zero,
+                BlendFactor::One,
+            )),
+            BlendPreset::Multiply => Some(blend(
+                BlendFactor::DstColor,
+                BlendFactor::Zero,
+                BlendFactor::DstAlpha,
+                BlendFactor::Zero,
+            )),
+            BlendPreset::OitAccumulation if attachment == 0 => Some(blend(
+                BlendFactor::One,
+                BlendFactor::One,
+                BlendFactor::One,
+                BlendFactor::One,
+            )),
+            BlendPreset::OitAccumulation => Some(blend(
+                BlendFactor::Zero,
+                BlendFactor::OneMinusSrcColor,
+                BlendFactor::Zero,
+                BlendFactor::OneMinusSrcAlpha,
+            )),
+        }
+    }
+
+    /// Returns whether the preset blends attachments differently, needing `independent_blend`
+    pub fn is_independent(self) -> bool {
+        self == BlendPreset::OitAccumulation
+    }
+
+    /// Returns whether the preset blends with what is already in the attachment
+    pub fn is_transparent(self) -> bool {
+        self != BlendPreset::Opaque
+    }
+}
+
+/// Depth test settings of a pipeline
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DepthDesc {
+    pub test: bool,
+    pub write: bool,
+    pub compare: CompareOp,
+}
+
+impl DepthDesc {
+    /// No depth testing or writing
+    pub fn disabled() -> Self {
+        Self {
+            test: false,
+            write: false,
+            compare: CompareOp::Always,
+        }
+    }
+
+    /// Standard less-than depth test with writes
+    pub fn less() -> Self {
+        Self {
+            test: true,
+            write: true,
+            compare: CompareOp::Less,
+        }
+    }
+}
+
+impl Default for DepthDesc {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+/// Pipeline state that is set while recording instead of being baked into the pipeline
+///
+/// Viewport and scissor are always dynamic so that resizing never requires a new pipeline.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DynamicStates {
+    pub line_width: bool,
+    pub depth_bias: bool,
+}
+
+/// The render pass and subpass a pipeline is used in, compared by identity
+#[derive(Clone, Debug)]
+pub struct RenderPassDesc {
+    pub render_pass: Arc<RenderPass>,
+    pub subpass: u32,
+}
+
+impl RenderPassDesc {
+    /// Returns the subpass object for pipeline creation
+    pub fn subpass(&self) -> Result<Subpass> {
+        Subpass::from(self.render_pass.clone(), self.subpass)
+            .ok_or_else(|| anyhow!("Render pass has no subpass {}", self.subpass))
+    }
+}
+
+impl PartialEq for RenderPassDesc {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.render_pass, &other.render_pass) && self.subpass == other.subpass
+    }
+}
+
+impl Eq for RenderPassDesc {}
+
+impl Hash for RenderPassDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.render_pass).hash(state);
+        self.subpass.hash(state);
+    }
+}
+
+/// Specialization constant values of a pipeline's vertex and fragment stages
+///
+/// Compares and hashes by the value bytes of each constant id, so pipelines that differ only
+/// in their constants get their own cache entries.
+#[derive(Clone)]
+pub struct Specialization {
+    /// Constant id and value bytes of each vertex and fragment stage constant
+    values: [Vec<(u32, Vec<u8>)>; 2],
+    build: Arc<SpecializedBuild>,
+}
+
+type SpecializedBuild = dyn Fn(
+        Arc<Device>,
+        &PipelineDesc,
+        Option<Arc<PipelineCache>>,
+    ) -> Result<Arc<GraphicsPipeline>>
+    + Send
+    + Sync;
+
+impl Specialization {
+    /// Captures typed constant values for the vertex and fragment stages
+    pub fn new<Vss, Fss>(vs_constants: Vss, fs_constants: Fss) -> Self
+    where
+        Vss: SpecializationConstants + Clone + Send + Sync + 'static,
+        Fss: SpecializationConstants + Clone + Send + Sync + 'static,
+    {
+        let values = [constant_values(&vs_constants), constant_values(&fs_constants)];
+        Self {
+            values,
+            build: Arc::new(move |device, desc, cache| {
+                let (vs_constants, fs_constants) = (vs_constants.clone(), fs_constants.clone());
+                build_graphics_pipeline_specialized(device, desc, vs_constants, fs_constants, cache)
+            }),
+        }
+    }
+
+    /// Returns the constant ids and value bytes of the vertex stage
+    pub fn vertex_values(&self) -> &[(u32, Vec<u8>)] {
+        &self.values[0]
+    }
+
+    /// Returns the constant ids and value bytes of the fragment stage
+    pub fn fragment_values(&self) -> &[(u32, Vec<u8>)] {
+        &self.values[1]
+    }
+}
+
+/// Returns the id and value bytes of each constant of a specialization constant struct
+fn constant_values<S: SpecializationConstants>(constants: &S) -> Vec<(u32, Vec<u8>)> {
+    // SAFETY: the trait guarantees every entry lies within the struct, which is borrowed for
+    // the whole read
+    let bytes = unsafe {
+        std::slice::from_raw_parts(constants as *const S as *const u8, std::mem::size_of::<S>())
+    };
+    let mut values: Vec<_> = S::descriptors()
         .iter()
-        .map(|image| {
-            let view = vulkano::image::view::ImageView::new_default(image.clone())?;
-            Ok(Framebuffer::new(
-                render_pass.clone(),
-                FramebufferCreateInfo {
-                    attachments: vec![view],
-                    ..Default::default()
-                },
-            )?)
+        .map(|entry| {
+            let start = entry.offset as usize;
+            (entry.constant_id, bytes[start..start + entry.size].to_vec())
         })
-        .collect::<Result<Vec<_>>>()?;
+        .collect();
+    values.sort();
+    values
+}
 
-    info!("Created {} framebuffers", framebuffers.len());
-    Ok(framebuffers)
+impl PartialEq for Specialization {
+    fn eq(&self, other: &Self) -> bool {
+        self.values == other.values
+    }
+}
+
+impl Eq for Specialization {}
+
+impl Hash for Specialization {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.values.hash(state);
+    }
+}
+
+impl std::fmt::Debug for Specialization {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Specialization")
+            .field("vertex", &self.values[0])
+            .field("fragment", &self.values[1])
+            .finish()
+    }
+}
+
+/// Everything needed to build a graphics pipeline, usable as a cache key
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineDesc {
+    pub vertex_shader: ShaderStageDesc,
+    pub fragment_shader: ShaderStageDesc,
+    pub vertex_layout: VertexLayout,
+    pub topology: PrimitiveTopology,
+    pub raster: RasterDesc,
+    pub blend: BlendPreset,
+    pub depth: DepthDesc,
+    pub render_pass: RenderPassDesc,
+    pub dynamic_states: DynamicStates,
+    /// Specialization constant values, or `None` to build with the shaders' defaults
+    pub specialization: Option<Specialization>,
+}
+
+impl PipelineDesc {
+    /// A triangle list pipeline drawing our `Vertex` type into the opaque subpass of a render pass
+    pub fn new(vs: Arc<ShaderModule>, fs: Arc<ShaderModule>, render_pass: Arc<RenderPass>) -> Self {
+        Self {
+            vertex_shader: ShaderStageDesc::main(vs),
+            fragment_shader: ShaderStageDesc::main(fs),
+            vertex_layout: VertexLayout::of::<Vertex>(),
+            topology: PrimitiveTopology::TriangleList,
+            raster: RasterDesc::default(),
+            blend: BlendPreset::default(),
+            depth: DepthDesc::less(),
+            render_pass: RenderPassDesc {
+                render_pass,
+                subpass: OPAQUE_SUBPASS,
+            },
+            dynamic_states: DynamicStates::default(),
+            specialization: None,
+        }
+    }
+}
+
+impl PipelineDesc {
+    /// A pipeline drawing one fullscreen triangle without vertex buffers, e.g. for compositing
+    pub fn fullscreen(
+        vs: Arc<ShaderModule>,
+        fs: Arc<ShaderModule>,
+        render_pass: RenderPassDesc,
+    ) -> Self {
+        Self {
+            vertex_shader: ShaderStageDesc::main(vs),
+            fragment_shader: ShaderStageDesc::main(fs),
+            vertex_layout: VertexLayout(Vec::new()),
+            topology: PrimitiveTopology::TriangleList,
+            raster: RasterDesc::default(),
+            blend: BlendPreset::Opaque,
+            depth: DepthDesc::disabled(),
+            render_pass,
+            dynamic_states: DynamicStates::default(),
+            specialization: None,
+        }
+    }
+
+    /// Returns the same pipeline with specialization constant values for each stage
+    ///
+    /// Derived pipelines keep the values, also when they swap in another shader.
+    pub fn specialized<Vss, Fss>(&self, vs_constants: Vss, fs_constants: Fss) -> Self
+    where
+        Vss: SpecializationConstants + Clone + Send + Sync + 'static,
+        Fss: SpecializationConstants + Clone + Send + Sync + 'static,
+    {
+        Self {
+            specialization: Some(Specialization::new(vs_constants, fs_constants)),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the same pipeline targeting another subpass of its render pass
+    pub fn in_subpass(&self, subpass: u32) -> Self {
+        Self {
+            render_pass: RenderPassDesc {
+                subpass,
+                ..self.render_pass.clone()
+            },
+            ..self.clone()
+        }
+    }
+
+    /// Returns the same pipeline targeting the same subpass of another render pass
+    ///
+    /// Used when the render pass is recreated, e.g. with a different sample count.
+    pub fn with_render_pass(&self, render_pass: Arc<RenderPass>) -> Self {
+        Self {
+            render_pass: RenderPassDesc {
+                render_pass,
+                subpass: self.render_pass.subpass,
+            },
+            ..self.clone()
+        }
+    }
+
+    /// Derives a pipeline for sorted transparent draws with the given blend preset
+    ///
+    /// Transparent surfaces are still depth tested against opaque geometry but do not write
+    /// depth, so overlapping transparent draws all reach the blender.
+    pub fn transparent(&self, blend: BlendPreset) -> Self {
+        Self {
+            blend,
+            depth: DepthDesc {
+                write: false,
+                ..self.depth
+            },
+            ..self.in_subpass(TRANSPARENT_SUBPASS)
+        }
+    }
+
+    /// Derives a pipeline drawing our `Vertex` type once per `InstanceData` element
+    ///
+    /// The vertex shader reads the model matrix and color from the instance-rate binding;
+    /// everything else, including the fragment shader, is kept.
+    pub fn instanced(&self, vertex_shader: Arc<ShaderModule>) -> Self {
+        Self {
+            vertex_shader: ShaderStageDesc::main(vertex_shader),
+            vertex_layout: VertexLayout::instanced::<Vertex, InstanceData>(),
+            ..self.clone()
+        }
+    }
+
+    /// Derives a pipeline reading its per-vertex attributes from the given streams
+    ///
+    /// Per-instance bindings are kept and follow the streams, the order `Mesh` binds its
+    /// buffers in; the shaders and all other state are unchanged.
+    pub fn with_vertex_streams(&self, streams: &VertexLayout) -> Self {
+        let instance_bindings = self
+            .vertex_layout
+            .0
+            .iter()
+            .filter(|binding| matches!(binding.input_rate, VertexInputRate::Instance { .. }));
+        let bindings = streams.0.iter().chain(instance_bindings).cloned().collect();
+
+        Self {
+            vertex_layout: VertexLayout(bindings),
+            ..self.clone()
+        }
+    }
+
+    /// Derives a weighted blended OIT accumulation pipeline with the given fragment shader
+    pub fn oit_accumulation(&self, fragment_shader: Arc<ShaderModule>) -> Self {
+        Self {
+            fragment_shader: ShaderStageDesc::main(fragment_shader),
+            blend: BlendPreset::OitAccumulation,
+            depth: DepthDesc {
+                write: false,
+                ..self.depth
+            },
+            ..self.in_subpass(OIT_ACCUMULATION_SUBPASS)
+        }
+    }
+
+    /// Derives a line-mode overlay of this pipeline that draws on top of the filled geometry
+    ///
+    /// The overlay keeps the vertex stage and layout, swaps in a flat-color fragment shader and
+    /// pulls lines slightly towards the camera so they win the depth test against the fill.
+    pub fn wireframe_overlay(&self, fragment_shader: Arc<ShaderModule>) -> Self {
+        let depth = if self.depth.test {
+            DepthDesc {
+                test: true,
+                write: false,
+                compare: CompareOp::LessOrEqual,
+            }
+        } else {
+            self.depth
+        };
+
+        Self {
+            fragment_shader: ShaderStageDesc::main(fragment_shader),
+            raster: RasterDesc {
+                cull_mode: CullMode::None,
+                depth_bias: depth.test.then_some(DepthBiasDesc {
+                    constant_factor: -1.0,
+                    clamp: 0.0,
+                    slope_factor: -1.0,
+                }),
+                ..RasterDesc::wireframe()
+            },
+            blend: BlendPreset::Opaque,
+            depth,
+            ..self.in_subpass(TRANSPARENT_SUBPASS)
+        }
+    }
 }
 
 /// Creates a graphics pipeline for rendering
@@ -66,35 +738,281 @@ pub fn create_graphics_pipeline(
     vs: Arc<ShaderModule>,
     fs: Arc<ShaderModule>,
     render_pass: Arc<RenderPass>,
-    viewport: Viewport,
 ) -> Result<Arc<GraphicsPipeline>> {
-    // Create a vertex input state
-    let vertex_input_state = VertexInputState::new();
-
-    // Create a viewport state
-    let viewport_state = ViewportState::viewport_fixed_scissor_irrelevant([viewport]);
-
-    // Create a pipeline layout
-    let pipeline_layout = PipelineLayout::new(
-        device.clone(),
-        vulkano::pipeline::layout::PipelineLayoutCreateInfo {
-            push_constant_ranges: vec![],
-            set_layouts: vec![],
+    build_graphics_pipeline(device, &PipelineDesc::new(vs, fs, render_pass), None)
+}
+
+/// Builds a graphics pipeline from a description, with its specialization constants if any
+pub fn build_graphics_pipeline(
+    device: Arc<Device>,
+    desc: &PipelineDesc,
+    cache: Option<Arc<PipelineCache>>,
+) -> Result<Arc<GraphicsPipeline>> {
+    match &desc.specialization {
+        Some(specialization) => (specialization.build)(device, desc, cache),
+        None => build_graphics_pipeline_specialized(device, desc, (), (), cache),
+    }
+}
+
+/// Builds a graphics pipeline with specialization constant values for each stage
+///
+/// The description's own `specialization` is ignored. The values are checked against the constants each shader declares before the pipeline is built.
+pub fn build_graphics_pipeline_specialized<Vss, Fss>(
+    device: Arc<Device>,
+    desc: &PipelineDesc,
+    vs_constants: Vss,
+    fs_constants: Fss,
+    cache: Option<Arc<PipelineCache>>,
+) -> Result<Arc<GraphicsPipeline>>
+where
+    Vss: SpecializationConstants,
+    Fss: SpecializationConstants,
+{
+    let vs_entry = desc
+        .vertex_shader
+        .module
+        .entry_point(&desc.vertex_shader.entry_point)
+        .ok_or_else(|| {
+            anyhow!("Vertex shader has no `{}` entry point", desc.vertex_shader.entry_point)
+        })?;
+    let fs_entry = desc
+        .fragment_shader
+        .module
+        .entry_point(&desc.fragment_shader.entry_point)
+        .ok_or_else(|| {
+            anyhow!("Fragment shader has no `{}` entry point", desc.fragment_shader.entry_point)
+        })?;
+
+    // Check the supplied specialization constants against the shaders
+    validate_specialization_constants::<Vss>(&vs_entry)?;
+    validate_specialization_constants::<Fss>(&fs_entry)?;
+
+    // Create a vertex input state, checking the shader inputs against the vertex layout
+    let vertex_input_state = vertex_input_state_from_buffers(&desc.vertex_layout.0, &vs_entry)?;
+
+    // Create a pipeline layout from the shaders' descriptor and push constant requirements
+    let pipeline_layout = create_pipeline_layout(device.clone(), &[&vs_entry, &fs_entry])?;
+
+    let subpass = desc.render_pass.subpass()?;
+
+    let rasterization_state = rasterization_state(&device, &desc.raster, &desc.dynamic_states)?;
+
+    if desc.blend.is_independent() && !device.enabled_features().independent_blend {
+        return Err(anyhow!(
+            "Blend preset {:?} requires the `independent_blend` device feature",
+            desc.blend
+        ));
+    }
+    let mut color_blend_state = ColorBlendState::new(subpass.num_color_attachments());
+    for (index, attachment) in color_blend_state.attachments.iter_mut().enumerate() {
+        attachment.blend = desc.blend.attachment_blend(index as u32);
+    }
+
+    let depth_stencil_state = if desc.depth.test {
+        DepthStencilState {
+            depth: Some(DepthState {
+                enable_dynamic: false,
+                write_enable: StateMode::Fixed(desc.depth.write),
+                compare_op: StateMode::Fixed(desc.depth.compare),
+            }),
             ..Default::default()
-        },
-    )?;
+        }
+    } else {
+        DepthStencilState::disabled()
+    };
+
+    // Match the sample count of the attachments the subpass renders to
+    let multisample_state = MultisampleState {
+        rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
+        ..Default::default()
+    };
 
     // Create the graphics pipeline
-    let pipeline = GraphicsPipeline::start()
+    let mut builder = GraphicsPipeline::start()
         .vertex_input_state(vertex_input_state)
-        .vertex_shader(vs.entry_point("main").unwrap(), ())
-        .input_assembly_state(InputAssemblyState::default())
-        .viewport_state(viewport_state)
-        .fragment_shader(fs.entry_point("main").unwrap(), ())
-        .color_blend_state(ColorBlendState::new(1).blend_alpha())
-        .render_pass(Subpass::from(render_pass, 0).unwrap())
-        .with_pipeline_layout(device.clone(), pipeline_layout)?;
+        .vertex_shader(vs_entry, vs_constants)
+        .input_assembly_state(InputAssemblyState::new().topology(desc.topology))
+        .viewport_state(ViewportState::viewport_dynamic_scissor_dynamic(1))
+        .fragment_shader(fs_entry, fs_constants)
+        .rasterization_state(rasterization_state)
+        .color_blend_state(color_blend_state)
+        .depth_stencil_state(depth_stencil_state)
+        .multisample_state(multisample_state)
+        .render_pass(subpass);
+    if let Some(cache) = cache {
+        builder = builder.build_with_cache(cache);
+    }
+    let pipeline = builder.with_pipeline_layout(device.clone(), pipeline_layout)?;
 
     info!("Graphics pipeline created successfully");
     Ok(pipeline)
 }
+
+/// Translates a raster description into vulkano state, checking the device features it needs
+fn rasterization_state(
+    device: &Device,
+    raster: &RasterDesc,
+    dynamic_states: &DynamicStates,
+) -> Result<RasterizationState> {
+    let features = device.enabled_features();
+    let properties = device.physical_device().properties();
+
+    if raster.polygon_mode != PolygonMode::Fill && !features.fill_mode_non_solid {
+        return Err(anyhow!(
+            "Polygon mode {:?} requires the `fill_mode_non_solid` device feature",
+            raster.polygon_mode
+        ));
+    }
+
+    if !dynamic_states.line_width {
+        if raster.line_width != 1.0 && !features.wide_lines {
+            return Err(anyhow!(
+                "Line width {} requires the `wide_lines` device feature",
+                raster.line_width
+            ));
+        }
+        let [min_width, max_width] = properties.line_width_range;
+        if raster.line_width < min_width || raster.line_width > max_width {
+            return Err(anyhow!(
+                "Line width {} is outside the supported range {}..={}",
+                raster.line_width,
+                min_width,
+                max_width
+            ));
+        }
+    }
+
+    let depth_bias = if dynamic_states.depth_bias {
+        Some(DepthBiasState {
+            enable_dynamic: false,
+            bias: StateMode::Dynamic,
+        })
+    } else {
+        match raster.depth_bias {
+            Some(bias) => {
+                if bias.clamp != 0.0 && !features.depth_bias_clamp {
+                    return Err(anyhow!(
+                        "Depth bias clamp {} requires the `depth_bias_clamp` device feature",
+                        bias.clamp
+                    ));
+                }
+                Some(DepthBiasState {
+                    enable_dynamic: false,
+                    bias: StateMode::Fixed(DepthBias {
+                        constant_factor: bias.constant_factor,
+                        clamp: bias.clamp,
+                        slope_factor: bias.slope_factor,
+                    }),
+                })
+            }
+            None => None,
+        }
+    };
+
+    Ok(RasterizationState {
+        polygon_mode: raster.polygon_mode,
+        cull_mode: StateMode::Fixed(raster.cull_mode),
+        front_face: StateMode::Fixed(raster.front_face),
+        depth_bias,
+        line_width: if dynamic_states.line_width {
+            StateMode::Dynamic
+        } else {
+            StateMode::Fixed(raster.line_width)
+        },
+        ..Default::default()
+    })
+}
+
+/// Returns a viewport and matching scissor covering the given extent
+///
+/// Pipelines use dynamic viewport and scissor state, so these are set while recording.
+pub fn full_viewport(extent: [u32; 2]) -> (Viewport, Scissor) {
+    let viewport = Viewport {
+        origin: [0.0, 0.0],
+        dimensions: [extent[0] as f32, extent[1] as f32],
+        depth_range: 0.0..1.0,
+    };
+    let scissor = Scissor {
+        origin: [0, 0],
+        dimensions: extent,
+    };
+    (viewport, scissor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vulkano::shader::SpecializationMapEntry;
+
+    #[test]
+    fn sample_count_cycle_skips_unsupported_counts() {
+        let supported = SampleCounts::SAMPLE_1 | SampleCounts::SAMPLE_4 | SampleCounts::SAMPLE_8;
+        let mut count = SampleCount::Sample1;
+        let mut visited = Vec::new();
+        for _ in 0..4 {
+            count = next_sample_count(count, supported);
+            visited.push(u32::from(count));
+        }
+        assert_eq!(visited, [4, 8, 1, 4]);
+    }
+
+    #[derive(Clone, Copy)]
+    #[repr(C)]
+    struct Constants {
+        count: u32,
+        scale: f32,
+    }
+
+    unsafe impl SpecializationConstants for Constants {
+        fn descriptors() -> &'static [SpecializationMapEntry] {
+            static DESCRIPTORS: [SpecializationMapEntry; 2] = [
+                SpecializationMapEntry {
+                    constant_id: 1,
+                    offset: 4,
+                    size: 4,
+                },
+                SpecializationMapEntry {
+                    constant_id: 0,
+                    offset: 0,
+                    size: 4,
+                },
+            ];
+            &DESCRIPTORS
+        }
+    }
+
+    fn hash_of(specialization: &Specialization) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        specialization.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn specialization_compares_constant_values() {
+        let constants = Constants {
+            count: 3,
+            scale: 0.5,
+        };
+        let specialization = Specialization::new(constants, ());
+        assert_eq!(
+            specialization.vertex_values(),
+            [(0, 3u32.to_ne_bytes().to_vec()), (1, 0.5f32.to_ne_bytes().to_vec())]
+        );
+        assert!(specialization.fragment_values().is_empty());
+
+        let same = Specialization::new(constants, ());
+        assert_eq!(specialization, same);
+        assert_eq!(hash_of(&specialization), hash_of(&same));
+
+        let other = Specialization::new(Constants { count: 4, ..constants }, ());
+        assert_ne!(specialization, other);
+        let swapped = Specialization::new((), constants);
+        assert_ne!(specialization, swapped);
+    }
+
+    #[test]
+    fn sample_count_cycle_stays_at_one_without_multisampling() {
+        let next = next_sample_count(SampleCount::Sample1, SampleCounts::SAMPLE_1);
+        assert_eq!(next, SampleCount::Sample1);
+    }
+}
@@ -0,0 +1,215 @@
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use vulkano::device::Device;
+use vulkano::pipeline::cache::PipelineCache;
+use vulkano::pipeline::GraphicsPipeline;
+
+use crate::engine::buffer::Vertex;
+use crate::engine::mesh::Mesh;
+use crate::engine::pipeline::{build_graphics_pipeline, PipelineDesc, VertexLayout};
+
+/// Default directory for the on-disk pipeline cache, relative to the working directory
+pub const DEFAULT_CACHE_DIR: &str = ".cache/pipelines";
+
+/// Size of the Vulkan pipeline cache header (version one)
+const CACHE_HEADER_SIZE: usize = 32;
+
+/// A driver-level pipeline cache that is loaded from and saved to disk
+///
+/// The file name includes the device UUID and driver version, so switching GPUs or updating the
+/// driver starts a fresh cache instead of feeding the driver incompatible data.
+pub struct PersistentPipelineCache {
+    cache: Arc<PipelineCache>,
+    path: PathBuf,
+}
+
+impl PersistentPipelineCache {
+    /// Opens the cache file for this device in `dir`, or starts an empty cache
+    pub fn load(device: Arc<Device>, dir: impl AsRef<Path>) -> Result<Self> {
+        let path = dir.as_ref().join(Self::file_name(&device));
+
+        let cache = match fs::read(&path) {
+            Ok(data) if Self::header_matches(&device, &data) => {
+                info!("Loaded {} bytes of pipeline cache from {}", data.len(), path.display());
+                // The header was checked against this device above
+                unsafe { PipelineCache::with_data(device, &data)? }
+            }
+            Ok(_) => {
+                warn!("Ignoring stale pipeline cache at {}", path.display());
+                PipelineCache::empty(device)?
+            }
+            Err(_) => PipelineCache::empty(device)?,
+        };
+
+        Ok(Self { cache, path })
+    }
+
+    /// Returns the cache to pass into pipeline builds
+    pub fn cache(&self) -> &Arc<PipelineCache> {
+        &self.cache
+    }
+
+    /// Writes the current cache contents back to disk
+    pub fn save(&self) -> Result<()> {
+        let data = self.cache.get_data()?;
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        // Write to a temporary file first so a crash never leaves a truncated cache behind
+        let temp_path = self.path.with_extension("tmp");
+        fs::write(&temp_path, &data)?;
+        fs::rename(&temp_path, &self.path)?;
+
+        info!("Saved {} bytes of pipeline cache to {}", data.len(), self.path.display());
+        Ok(())
+    }
+
+    /// Builds the cache file name from the device UUID and driver version
+    fn file_name(device: &Device) -> String {
+        let properties = device.physical_device().properties();
+        let uuid = properties
+            .device_uuid
+            .unwrap_or(properties.pipeline_cache_uuid);
+        let uuid: String = uuid.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+        format!("{}-{:08x}.bin", uuid, properties.driver_version)
+    }
+
+    /// Checks the vendor, device and cache UUID stored in a cache blob's header
+    fn header_matches(device: &Device, data: &[u8]) -> bool {
+        if data.len() < CACHE_HEADER_SIZE {
+            return false;
+        }
+
+        let read_u32 = |offset: usize| {
+            u32::from_ne_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]])
+        };
+        let properties = device.physical_device().properties();
+
+        read_u32(0) as usize >= CACHE_HEADER_SIZE
+            && read_u32(4) == 1
+            && read_u32(8) == properties.vendor_id
+            && read_u32(12) == properties.device_id
+            && data[16..32] == properties.pipeline_cache_uuid
+    }
+}
+
+/// In-memory cache of graphics pipelines keyed by their description
+///
+/// Requesting a description that was built before returns the existing pipeline instead of
+/// compiling a new one.
+pub struct GraphicsPipelineCache {
+    device: Arc<Device>,
+    driver_cache: Option<Arc<PipelineCache>>,
+    pipelines: HashMap<PipelineDesc, Arc<GraphicsPipeline>>,
+}
+
+impl GraphicsPipelineCache {
+    /// Creates an empty cache, optionally backed by a driver-level pipeline cache
+    pub fn new(device: Arc<Device>, driver_cache: Option<Arc<PipelineCache>>) -> Self {
+        Self {
+            device,
+            driver_cache,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    /// Returns the pipeline for a description, building it on first use
+    pub fn get_or_create(&mut self, desc: &PipelineDesc) -> Result<Arc<GraphicsPipeline>> {
+        if let Some(pipeline) = self.pipelines.get(desc) {
+            return Ok(pipeline.clone());
+        }
+
+        let pipeline =
+            build_graphics_pipeline(self.device.clone(), desc, self.driver_cache.clone())?;
+        self.pipelines.insert(desc.clone(), pipeline.clone());
+
+        info!("Pipeline cache now holds {} pipeline(s)", self.pipelines.len());
+        Ok(pipeline)
+    }
+
+    /// Returns the number of cached pipelines
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    /// Returns whether the cache is empty
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+
+    /// Drops every pipeline built for the given render pass, e.g. after it was recreated
+    pub fn evict_render_pass(&mut self, render_pass: &Arc<vulkano::render_pass::RenderPass>) {
+        self.pipelines
+            .retain(|desc, _| !Arc::ptr_eq(&desc.render_pass.render_pass, render_pass));
+    }
+
+    /// Drops every cached pipeline
+    pub fn clear(&mut self) {
+        self.pipelines.clear();
+    }
+}
+
+/// A pipeline with variants for meshes whose vertex streams differ from the single `Vertex`
+///
+/// Variants are derived with `PipelineDesc::with_vertex_streams` and built through the cache
+/// before recording, so that drawing a mesh only has to look its pipeline up.
+pub struct MeshPipelines {
+    desc: PipelineDesc,
+    standard: Arc<GraphicsPipeline>,
+    variants: HashMap<VertexLayout, Arc<GraphicsPipeline>>,
+}
+
+impl MeshPipelines {
+    /// Builds the pipeline for meshes with the standard layout
+    pub fn new(cache: &mut GraphicsPipelineCache, desc: PipelineDesc) -> Result<Self> {
+        let standard = cache.get_or_create(&desc)?;
+        let variants = HashMap::from([(VertexLayout::of::<Vertex>(), standard.clone())]);
+        Ok(Self {
+            desc,
+            standard,
+            variants,
+        })
+    }
+
+    /// Returns the pipeline for meshes with the standard layout
+    pub fn standard(&self) -> &Arc<GraphicsPipeline> {
+        &self.standard
+    }
+
+    /// Builds the variants for vertex layouts that have none yet
+    pub fn add_variants<'a>(
+        &mut self,
+        cache: &mut GraphicsPipelineCache,
+        layouts: impl IntoIterator<Item = &'a VertexLayout>,
+    ) -> Result<()> {
+        for layout in layouts {
+            if !self.variants.contains_key(layout) {
+                let pipeline = cache.get_or_create(&self.desc.with_vertex_streams(layout))?;
+                self.variants.insert(layout.clone(), pipeline);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the variant reading the given vertex layout
+    pub fn get(&self, layout: &VertexLayout) -> Result<&Arc<GraphicsPipeline>> {
+        self.variants
+            .get(layout)
+            .ok_or_else(|| anyhow!("No pipeline variant was built for vertex layout {:?}", layout))
+    }
+
+    /// Returns the variant matching a mesh's vertex streams
+    pub fn for_mesh(&self, mesh: &Mesh) -> Result<&Arc<GraphicsPipeline>> {
+        if mesh.has_standard_layout() {
+            Ok(&self.standard)
+        } else {
+            self.get(mesh.vertex_layout())
+        }
+    }
+}
@@ -0,0 +1,575 @@
+use nalgebra::{Point3, Vector3};
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+
+use crate::engine::buffer::Vertex;
+
+/// Builds a vertex from nalgebra types
+fn vertex(position: Point3<f32>, normal: Vector3<f32>, tex_coords: [f32; 2]) -> Vertex {
+    Vertex {
+        position: position.into(),
+        normal: normal.into(),
+        tex_coords,
+    }
+}
+
+/// Appends two triangles for every cell of a grid of `rows + 1` by `columns + 1` vertices
+///
+/// Vertices are stored row by row starting at `first`. The triangles face the side from which
+/// the next column lies to the right and the next row above.
+fn grid_indices(indices: &mut Vec<u32>, first: u32, rows: u32, columns: u32) {
+    let stride = columns + 1;
+    for row in 0..rows {
+        for column in 0..columns {
+            let top_left = first + row * stride + column;
+            let bottom_left = top_left + stride;
+            indices.extend([top_left, top_left + 1, bottom_left]);
+            indices.extend([top_left + 1, bottom_left + 1, bottom_left]);
+        }
+    }
+}
+
+/// Appends a flat disc facing up or down at height `y`, split into triangles around its center
+fn disc(
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>,
+    radius: f32,
+    y: f32,
+    segments: u32,
+    up: bool,
+) {
+    let normal = if up { Vector3::y() } else { -Vector3::y() };
+    let center = vertices.len() as u32;
+    vertices.push(vertex(Point3::new(0.0, y, 0.0), normal, [0.5, 0.5]));
+
+    for segment in 0..=segments {
+        let angle = TAU * segment as f32 / segments as f32;
+        let (sin, cos) = angle.sin_cos();
+        vertices.push(vertex(
+            Point3::new(radius * cos, y, radius * sin),
+            normal,
+            [0.5 + 0.5 * cos, 0.5 + 0.5 * sin],
+        ));
+    }
+
+    for segment in 0..segments {
+        let rim = center + 1 + segment;
+        if up {
+            indices.extend([center, rim + 1, rim]);
+        } else {
+            indices.extend([center, rim, rim + 1]);
+        }
+    }
+}
+
+/// Creates a sphere from rings of latitude and segments of longitude, centered on the origin
+///
+/// The poles are on the Y axis. U runs once around the equator and V from the north pole to
+/// the south pole; the seam and the poles have duplicated vertices so UVs do not wrap.
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let (segments, rings) = (segments.max(3), rings.max(2));
+    let mut vertices = Vec::with_capacity(((rings + 1) * (segments + 1)) as usize);
+    for ring in 0..=rings {
+        let v = ring as f32 / rings as f32;
+        let (sin_theta, cos_theta) = (PI * v).sin_cos();
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let (sin_phi, cos_phi) = (TAU * u).sin_cos();
+            let normal = Vector3::new(sin_theta * cos_phi, cos_theta, sin_theta * sin_phi);
+            vertices.push(vertex(Point3::from(normal * radius), normal, [u, v]));
+        }
+    }
+
+    let mut indices = Vec::new();
+    grid_indices(&mut indices, 0, rings, segments);
+    remove_degenerate(&vertices, &mut indices);
+    (vertices, indices)
+}
+
+/// Creates a sphere by subdividing an icosahedron, centered on the origin
+///
+/// Every subdivision splits each triangle into four, giving `20 * 4^subdivisions` triangles of
+/// nearly equal size. UVs are spherical like `uv_sphere`, with vertices duplicated along the
+/// seam and at the poles.
+pub fn icosphere(radius: f32, subdivisions: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let mut directions: Vec<Vector3<f32>> = [
+        [-1.0, t, 0.0], [1.0, t, 0.0], [-1.0, -t, 0.0], [1.0, -t, 0.0],
+        [0.0, -1.0, t], [0.0, 1.0, t], [0.0, -1.0, -t], [0.0, 1.0, -t],
+        [t, 0.0, -1.0], [t, 0.0, 1.0], [-t, 0.0, -1.0], [-t, 0.0, 1.0],
+    ]
+    .iter()
+    .map(|&direction| Vector3::from(direction).normalize())
+    .collect();
+
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32, directions: &mut Vec<Vector3<f32>>| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                directions.push((directions[a as usize] + directions[b as usize]).normalize());
+                directions.len() as u32 - 1
+            })
+        };
+
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let ab = midpoint(a, b, &mut directions);
+                let bc = midpoint(b, c, &mut directions);
+                let ca = midpoint(c, a, &mut directions);
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let spherical_uv = |direction: &Vector3<f32>| {
+        let u = direction.z.atan2(direction.x).rem_euclid(TAU) / TAU;
+        let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+        [u, v]
+    };
+
+    let mut vertices: Vec<Vertex> = directions
+        .iter()
+        .map(|direction| {
+            vertex(Point3::from(direction * radius), *direction, spherical_uv(direction))
+        })
+        .collect();
+
+    // Give triangles across the seam or touching a pole their own copies with fixed U
+    let mut indices = Vec::with_capacity(triangles.len() * 3);
+    for triangle in triangles {
+        let mut corners = triangle.map(|index| vertices[index as usize]);
+        let wraps = corners.iter().any(|corner| corner.tex_coords[0] > 0.75)
+            && corners.iter().any(|corner| corner.tex_coords[0] < 0.25);
+        if wraps {
+            for corner in &mut corners {
+                if corner.tex_coords[0] < 0.5 {
+                    corner.tex_coords[0] += 1.0;
+                }
+            }
+        }
+
+        let is_pole = |corner: &Vertex| corner.normal[1].abs() > 1.0 - 1e-6;
+        for pole in 0..3 {
+            if is_pole(&corners[pole]) {
+                let others = [corners[(pole + 1) % 3], corners[(pole + 2) % 3]];
+                corners[pole].tex_coords[0] =
+                    (others[0].tex_coords[0] + others[1].tex_coords[0]) / 2.0;
+            }
+        }
+
+        for (corner, original) in corners.iter().zip(triangle) {
+            if corner.tex_coords == vertices[original as usize].tex_coords {
+                indices.push(original);
+            } else {
+                indices.push(vertices.len() as u32);
+                vertices.push(*corner);
+            }
+        }
+    }
+
+    (vertices, indices)
+}
+
+/// Creates a flat grid in the XZ plane facing +Y, centered on the origin
+///
+/// Each side is split into the given number of cells, which is useful for terrain or for
+/// surfaces displaced or lit per vertex. U runs along +X and V along -Z, so seen from above
+/// with -Z ahead the texture is upright.
+pub fn plane(
+    width: f32,
+    depth: f32,
+    subdivisions_x: u32,
+    subdivisions_z: u32,
+) -> (Vec<Vertex>, Vec<u32>) {
+    let (columns, rows) = (subdivisions_x.max(1), subdivisions_z.max(1));
+    let mut vertices = Vec::with_capacity(((rows + 1) * (columns + 1)) as usize);
+    for row in 0..=rows {
+        let v = row as f32 / rows as f32;
+        for column in 0..=columns {
+            let u = column as f32 / columns as f32;
+            let position = Point3::new((u - 0.5) * width, 0.0, (0.5 - v) * depth);
+            vertices.push(vertex(position, Vector3::y(), [u, v]));
+        }
+    }
+
+    let mut indices = Vec::new();
+    grid_indices(&mut indices, 0, rows, columns);
+    (vertices, indices)
+}
+
+/// Creates a capped cylinder along the Y axis, centered on the origin
+///
+/// The side has U running around and V from top to bottom; each cap is a disc with its own
+/// vertices so the edges stay sharp.
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let segments = segments.max(3);
+    let half = height / 2.0;
+    let mut vertices = Vec::new();
+    for (v, y) in [(0.0, half), (1.0, -half)] {
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let (sin, cos) = (TAU * u).sin_cos();
+            let normal = Vector3::new(cos, 0.0, sin);
+            vertices.push(vertex(Point3::new(radius * cos, y, radius * sin), normal, [u, v]));
+        }
+    }
+
+    let mut indices = Vec::new();
+    grid_indices(&mut indices, 0, 1, segments);
+    disc(&mut vertices, &mut indices, radius, half, segments, true);
+    disc(&mut vertices, &mut indices, radius, -half, segments, false);
+    (vertices, indices)
+}
+
+/// Creates a cone along the Y axis with its apex up, centered on the origin
+///
+/// The apex has one vertex per segment so every side triangle gets a smooth slanted normal
+/// and its own U coordinate; the base is a disc facing down.
+pub fn cone(radius: f32, height: f32, segments: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let segments = segments.max(3);
+    let half = height / 2.0;
+    let slope = Vector3::new(height, radius, 0.0).normalize();
+    let side_vertex = |u: f32, ring_radius: f32, y: f32, v: f32| {
+        let (sin, cos) = (TAU * u).sin_cos();
+        let normal = Vector3::new(slope.x * cos, slope.y, slope.x * sin);
+        vertex(Point3::new(ring_radius * cos, y, ring_radius * sin), normal, [u, v])
+    };
+
+    // Apex vertices sit halfway between the base vertices they connect
+    let mut vertices: Vec<Vertex> = (0..segments)
+        .map(|segment| side_vertex((segment as f32 + 0.5) / segments as f32, 0.0, half, 0.0))
+        .collect();
+    vertices.extend((0..=segments).map(|segment| {
+        side_vertex(segment as f32 / segments as f32, radius, -half, 1.0)
+    }));
+
+    let mut indices = Vec::new();
+    for segment in 0..segments {
+        let base = segments + segment;
+        indices.extend([segment, base + 1, base]);
+    }
+    disc(&mut vertices, &mut indices, radius, -half, segments, false);
+    (vertices, indices)
+}
+
+/// Creates a capsule along the Y axis, centered on the origin
+///
+/// `height` is the length of the straight middle part, so the capsule is
+/// `height + 2 * radius` tall. `rings` is the number of rings in each hemisphere. V follows
+/// the arc length from the top to the bottom pole so textures are not stretched.
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let (segments, rings) = (segments.max(3), rings.max(1));
+    let half = height / 2.0;
+    let total_length = PI * radius + height;
+
+    // Latitudes of the rows from the top pole down with their offset along Y and the length
+    // of the straight part above them; the equator appears once per hemisphere
+    let mut rows = Vec::new();
+    for ring in 0..=rings {
+        rows.push((PI / 2.0 * ring as f32 / rings as f32, half, 0.0));
+    }
+    for ring in 0..=rings {
+        rows.push((PI / 2.0 * (1.0 + ring as f32 / rings as f32), -half, height));
+    }
+
+    let mut vertices = Vec::with_capacity(rows.len() * (segments + 1) as usize);
+    for &(theta, offset, straight) in &rows {
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        let v = if total_length > 0.0 {
+            (theta * radius + straight) / total_length
+        } else {
+            0.0
+        };
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let (sin_phi, cos_phi) = (TAU * u).sin_cos();
+            let normal = Vector3::new(sin_theta * cos_phi, cos_theta, sin_theta * sin_phi);
+            let position = Point3::from(normal * radius) + Vector3::y() * offset;
+            vertices.push(vertex(position, normal, [u, v]));
+        }
+    }
+
+    let mut indices = Vec::new();
+    grid_indices(&mut indices, 0, rows.len() as u32 - 1, segments);
+    remove_degenerate(&vertices, &mut indices);
+    (vertices, indices)
+}
+
+/// Creates a torus around the Y axis, centered on the origin
+///
+/// `major_radius` is the distance from the center to the middle of the tube and
+/// `minor_radius` the radius of the tube. U runs around the Y axis and V around the tube.
+pub fn torus(
+    major_radius: f32,
+    minor_radius: f32,
+    major_segments: u32,
+    minor_segments: u32,
+) -> (Vec<Vertex>, Vec<u32>) {
+    let (major_segments, minor_segments) = (major_segments.max(3), minor_segments.max(3));
+    let mut vertices = Vec::with_capacity(((major_segments + 1) * (minor_segments + 1)) as usize);
+    for minor in 0..=minor_segments {
+        let v = minor as f32 / minor_segments as f32;
+        // Start at the top of the tube and go outwards, so rows run down the outer side
+        let (sin_tube, cos_tube) = (TAU * v).sin_cos();
+        for major in 0..=major_segments {
+            let u = major as f32 / major_segments as f32;
+            let (sin_ring, cos_ring) = (TAU * u).sin_cos();
+            let outward = Vector3::new(cos_ring, 0.0, sin_ring);
+            let normal = outward * sin_tube + Vector3::y() * cos_tube;
+            let position = Point3::from(outward * major_radius + normal * minor_radius);
+            vertices.push(vertex(position, normal, [u, v]));
+        }
+    }
+
+    let mut indices = Vec::new();
+    grid_indices(&mut indices, 0, minor_segments, major_segments);
+    (vertices, indices)
+}
+
+/// Drops triangles without area, e.g. the ones collapsed into the poles of a sphere
+fn remove_degenerate(vertices: &[Vertex], indices: &mut Vec<u32>) {
+    let triangles: Vec<u32> = indices
+        .chunks_exact(3)
+        .filter(|triangle| {
+            let [a, b, c] =
+                [0, 1, 2].map(|corner| Vector3::from(vertices[triangle[corner] as usize].position));
+            let longest = [b - a, c - b, a - c]
+                .iter()
+                .map(|edge| edge.norm_squared())
+                .fold(0.0, f32::max);
+            (b - a).cross(&(c - a)).norm() > longest * 1e-4
+        })
+        .flatten()
+        .copied()
+        .collect();
+    *indices = triangles;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the positions of a mesh welded by value, so seams and poles count as connected
+    fn welded(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vector3<f32>>, Vec<[usize; 3]>) {
+        let mut positions = Vec::new();
+        let mut by_position = HashMap::new();
+        let ids: Vec<usize> = vertices
+            .iter()
+            .map(|vertex| {
+                let key = vertex.position.map(|axis| (axis * 1e4).round() as i64);
+                *by_position.entry(key).or_insert_with(|| {
+                    positions.push(Vector3::from(vertex.position));
+                    positions.len() - 1
+                })
+            })
+            .collect();
+        let triangles = indices
+            .chunks_exact(3)
+            .map(|triangle| [0, 1, 2].map(|corner| ids[triangle[corner] as usize]))
+            .collect();
+        (positions, triangles)
+    }
+
+    /// Returns the corner positions of a triangle
+    fn corners(vertices: &[Vertex], triangle: &[u32]) -> [Vector3<f32>; 3] {
+        [0, 1, 2].map(|corner| Vector3::from(vertices[triangle[corner] as usize].position))
+    }
+
+    /// Counts how often each directed edge is used
+    fn directed_edges(triangles: &[[usize; 3]]) -> HashMap<(usize, usize), usize> {
+        let mut edges = HashMap::new();
+        for triangle in triangles {
+            for corner in 0..3 {
+                *edges
+                    .entry((triangle[corner], triangle[(corner + 1) % 3]))
+                    .or_default() += 1;
+            }
+        }
+        edges
+    }
+
+    /// Checks that every UV lies within the texture
+    fn assert_uvs_in_unit_square(vertices: &[Vertex]) {
+        for vertex in vertices {
+            assert!(
+                vertex.tex_coords.iter().all(|coord| (-1e-6..=1.0 + 1e-6).contains(coord)),
+                "UV {:?} outside the texture",
+                vertex.tex_coords
+            );
+        }
+    }
+
+    /// Checks indices, triangle areas, unit normals and finite UVs
+    fn assert_well_formed(vertices: &[Vertex], indices: &[u32]) {
+        assert!(!indices.is_empty());
+        assert_eq!(indices.len() % 3, 0);
+        assert!(indices.iter().all(|&index| (index as usize) < vertices.len()));
+
+        for vertex in vertices {
+            let length = Vector3::from(vertex.normal).norm();
+            assert!((length - 1.0).abs() < 1e-4, "normal of length {}", length);
+            assert!(vertex.tex_coords.iter().all(|coord| coord.is_finite()));
+        }
+
+        let (positions, triangles) = welded(vertices, indices);
+        for [a, b, c] in &triangles {
+            let area = (positions[*b] - positions[*a]).cross(&(positions[*c] - positions[*a]));
+            assert!(area.norm() > 1e-8, "degenerate triangle");
+        }
+    }
+
+    /// Checks that every edge is shared by exactly two triangles wound in opposite directions
+    fn assert_closed_manifold(vertices: &[Vertex], indices: &[u32]) {
+        let (_, triangles) = welded(vertices, indices);
+        let edges = directed_edges(&triangles);
+        for (&(a, b), &count) in &edges {
+            assert_eq!(count, 1, "edge {:?} used {} times in one direction", (a, b), count);
+            assert_eq!(edges.get(&(b, a)), Some(&1), "edge {:?} has no opposite", (a, b));
+        }
+    }
+
+    /// Checks that interior edges are shared by two opposite triangles and borders by one
+    fn assert_open_manifold(vertices: &[Vertex], indices: &[u32]) {
+        let (_, triangles) = welded(vertices, indices);
+        let edges = directed_edges(&triangles);
+        assert!(edges.values().all(|&count| count == 1));
+        assert!(edges.keys().any(|&(a, b)| !edges.contains_key(&(b, a))));
+    }
+
+    /// Checks that the vertex normals agree with the winding of every triangle
+    fn assert_normals_match_winding(vertices: &[Vertex], indices: &[u32]) {
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|corner| &vertices[triangle[corner] as usize]);
+            let [pa, pb, pc] = [a, b, c].map(|vertex| Vector3::from(vertex.position));
+            let face = (pb - pa).cross(&(pc - pa));
+            for vertex in [a, b, c] {
+                assert!(
+                    face.dot(&Vector3::from(vertex.normal)) > 0.0,
+                    "normal {:?} faces away from its triangle",
+                    vertex.normal
+                );
+            }
+        }
+    }
+
+    /// Returns the signed volume enclosed by a closed mesh, positive if it faces outwards
+    fn signed_volume(vertices: &[Vertex], indices: &[u32]) -> f32 {
+        indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let [a, b, c] = corners(vertices, triangle);
+                a.dot(&b.cross(&c)) / 6.0
+            })
+            .sum()
+    }
+
+    /// Runs every check for a closed primitive and compares its volume
+    fn assert_closed_primitive(mesh: (Vec<Vertex>, Vec<u32>), expected_volume: f32) {
+        let (vertices, indices) = mesh;
+        assert_well_formed(&vertices, &indices);
+        assert_uvs_in_unit_square(&vertices);
+        assert_closed_manifold(&vertices, &indices);
+        assert_normals_match_winding(&vertices, &indices);
+        let volume = signed_volume(&vertices, &indices);
+        assert!(
+            (volume - expected_volume).abs() < expected_volume * 0.05,
+            "volume {} instead of about {}",
+            volume,
+            expected_volume
+        );
+    }
+
+    #[test]
+    fn uv_sphere_is_closed_and_faces_outwards() {
+        let sphere = uv_sphere(1.0, 64, 32);
+        for vertex in &sphere.0 {
+            let position = Vector3::from(vertex.position);
+            assert!((position.norm() - 1.0).abs() < 1e-5);
+            assert!((Vector3::from(vertex.normal) - position).norm() < 1e-5);
+        }
+        assert_closed_primitive(sphere, 4.0 / 3.0 * PI);
+    }
+
+    #[test]
+    fn icosphere_is_closed_and_faces_outwards() {
+        for subdivisions in 0..4 {
+            let sphere = icosphere(1.0, subdivisions);
+            assert_eq!(sphere.1.len() / 3, 20 * 4usize.pow(subdivisions));
+            assert_well_formed(&sphere.0, &sphere.1);
+            assert_closed_manifold(&sphere.0, &sphere.1);
+            assert_normals_match_winding(&sphere.0, &sphere.1);
+            assert!(signed_volume(&sphere.0, &sphere.1) > 0.0);
+        }
+        let (vertices, indices) = icosphere(1.0, 4);
+        let volume = signed_volume(&vertices, &indices);
+        assert!((volume - 4.0 / 3.0 * PI).abs() < 0.05 * 4.0 / 3.0 * PI);
+    }
+
+    #[test]
+    fn icosphere_uvs_do_not_wrap_across_the_seam() {
+        let (vertices, indices) = icosphere(1.0, 3);
+        for triangle in indices.chunks_exact(3) {
+            let us = [0, 1, 2].map(|corner| vertices[triangle[corner] as usize].tex_coords[0]);
+            let span = us.iter().fold(f32::MIN, |a, &b| a.max(b))
+                - us.iter().fold(f32::MAX, |a, &b| a.min(b));
+            assert!(span < 0.5, "triangle spans {} of the texture", span);
+        }
+    }
+
+    #[test]
+    fn plane_is_an_open_grid_facing_up() {
+        let (vertices, indices) = plane(2.0, 3.0, 4, 6);
+        assert_eq!(vertices.len(), 5 * 7);
+        assert_eq!(indices.len(), 4 * 6 * 6);
+        assert_well_formed(&vertices, &indices);
+        assert_uvs_in_unit_square(&vertices);
+        assert_open_manifold(&vertices, &indices);
+        assert_normals_match_winding(&vertices, &indices);
+
+        let area: f32 = indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let [a, b, c] = corners(&vertices, triangle);
+                (b - a).cross(&(c - a)).y / 2.0
+            })
+            .sum();
+        assert!((area - 6.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn cylinder_is_closed_and_faces_outwards() {
+        assert_closed_primitive(cylinder(0.5, 2.0, 64), PI * 0.25 * 2.0);
+    }
+
+    #[test]
+    fn cone_is_closed_and_faces_outwards() {
+        assert_closed_primitive(cone(0.5, 2.0, 64), PI * 0.25 * 2.0 / 3.0);
+    }
+
+    #[test]
+    fn capsule_is_closed_and_faces_outwards() {
+        let expected = PI * 0.25 * 1.0 + 4.0 / 3.0 * PI * 0.125;
+        assert_closed_primitive(capsule(0.5, 1.0, 32, 8), expected);
+    }
+
+    #[test]
+    fn torus_is_closed_and_faces_outwards() {
+        let expected = 2.0 * PI * PI * 1.0 * 0.25 * 0.25;
+        assert_closed_primitive(torus(1.0, 0.25, 64, 32), expected);
+    }
+
+    #[test]
+    fn segment_counts_are_clamped_to_valid_shapes() {
+        assert_closed_manifold(&uv_sphere(1.0, 0, 0).0, &uv_sphere(1.0, 0, 0).1);
+        assert_closed_manifold(&cylinder(1.0, 1.0, 1).0, &cylinder(1.0, 1.0, 1).1);
+        assert_closed_manifold(&torus(1.0, 0.5, 0, 0).0, &torus(1.0, 0.5, 0, 0).1);
+    }
+}
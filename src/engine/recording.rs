@@ -0,0 +1,263 @@
+use anyhow::{anyhow, Result};
+use log::{info, warn};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use crate::engine::capture::CapturedImage;
+
+/// An encoder process that reads raw RGBA8 frames from its standard input
+///
+/// `{width}`, `{height}` and `{fps}` in the arguments are replaced when the process is started
+/// with the first frame, since the frame size is not known before then.
+#[derive(Clone, Debug)]
+pub struct EncoderCommand {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl EncoderCommand {
+    /// Encodes to a video file with a local `ffmpeg`
+    pub fn ffmpeg(output: impl Into<PathBuf>) -> Self {
+        let output = output.into();
+        let args = [
+            "-y", "-f", "rawvideo", "-pixel_format", "rgba", "-video_size", "{width}x{height}",
+            "-framerate", "{fps}", "-i", "-", "-pix_fmt", "yuv420p",
+        ];
+
+        Self {
+            program: "ffmpeg".to_string(),
+            args: args
+                .iter()
+                .map(|arg| arg.to_string())
+                .chain([output.display().to_string()])
+                .collect(),
+        }
+    }
+
+    /// Starts the process with the placeholders filled in
+    fn spawn(&self, extent: [u32; 2], fps: f64) -> Result<Child> {
+        let args = self.args.iter().map(|arg| {
+            arg.replace("{width}", &extent[0].to_string())
+                .replace("{height}", &extent[1].to_string())
+                .replace("{fps}", &fps.to_string())
+        });
+
+        let child = Command::new(&self.program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow!("Failed to start encoder `{}`: {}", self.program, e))?;
+        Ok(child)
+    }
+}
+
+/// Where recorded frames are written
+#[derive(Clone, Debug)]
+pub enum FrameSink {
+    /// Numbered PNG files in a directory
+    ImageSequence(PathBuf),
+    /// Raw frames piped to an encoder process
+    Encoder(EncoderCommand),
+}
+
+/// Records every rendered frame at a fixed simulated timestep
+///
+/// Simulation time only advances when a frame has been written, so a recording never drops
+/// frames and plays back at its nominal rate however long each frame took to render.
+pub struct Recorder {
+    sink: FrameSink,
+    fps: f64,
+    frame_index: u64,
+    extent: Option<[u32; 2]>,
+    encoder: Option<Child>,
+}
+
+impl Recorder {
+    /// Creates a recorder writing to `sink` at `fps` frames per simulated second
+    pub fn new(sink: FrameSink, fps: f64) -> Result<Self> {
+        if !(fps.is_finite() && fps > 0.0) {
+            return Err(anyhow!("Recording frame rate must be positive, got {}", fps));
+        }
+        if let FrameSink::ImageSequence(dir) = &sink {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        Ok(Self {
+            sink,
+            fps,
+            frame_index: 0,
+            extent: None,
+            encoder: None,
+        })
+    }
+
+    /// Returns the simulated time of the next frame
+    pub fn time(&self) -> Duration {
+        Duration::from_secs_f64(self.frame_index as f64 / self.fps)
+    }
+
+    /// Returns the fixed time between frames
+    pub fn timestep(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.fps)
+    }
+
+    /// Returns the number of frames written so far
+    pub fn frame_count(&self) -> u64 {
+        self.frame_index
+    }
+
+    /// Writes a frame and advances simulated time by one timestep
+    pub fn write_frame(&mut self, image: &CapturedImage) -> Result<()> {
+        let extent = image.extent();
+        match self.extent {
+            None => self.extent = Some(extent),
+            // A raw stream has no way to describe a size change
+            Some(previous) if previous != extent && matches!(self.sink, FrameSink::Encoder(_)) => {
+                return Err(anyhow!(
+                    "Frame size changed from {}x{} to {}x{} while recording",
+                    previous[0],
+                    previous[1],
+                    extent[0],
+                    extent[1]
+                ));
+            }
+            Some(_) => {}
+        }
+
+        match &self.sink {
+            FrameSink::ImageSequence(dir) => {
+                image.save(dir.join(format!("frame-{:06}.png", self.frame_index)))?;
+            }
+            FrameSink::Encoder(command) => {
+                if self.encoder.is_none() {
+                    self.encoder = Some(command.spawn(extent, self.fps)?);
+                }
+                let stdin = self
+                    .encoder
+                    .as_mut()
+                    .and_then(|encoder| encoder.stdin.as_mut())
+                    .ok_or_else(|| anyhow!("Encoder closed its input"))?;
+                stdin.write_all(image.to_rgba8()?.as_raw())?;
+            }
+        }
+
+        self.frame_index += 1;
+        Ok(())
+    }
+
+    /// Closes the encoder input and waits for it to finish; returns the number of frames
+    pub fn finish(mut self) -> Result<u64> {
+        if let Some(mut encoder) = self.encoder.take() {
+            drop(encoder.stdin.take());
+            let status = encoder.wait()?;
+            if !status.success() {
+                return Err(anyhow!("Encoder exited with {}", status));
+            }
+        }
+
+        info!("Recording finished with {} frames", self.frame_index);
+        Ok(self.frame_index)
+    }
+}
+
+/// Saves a frame read back from the GPU and passes it to the recorder, never failing the frame
+///
+/// A failed readback or save is logged and dropped. A failed recording, e.g. a full disk or a
+/// closed encoder pipe, is logged and stops the recorder, so later frames render without it.
+pub fn write_captured_frame(
+    image: Result<CapturedImage>,
+    capture_path: Option<PathBuf>,
+    recorder: &mut Option<Recorder>,
+) {
+    let image = match image {
+        Ok(image) => image,
+        Err(e) => {
+            warn!("Failed to read back the captured frame: {}", e);
+            if recorder.take().is_some() {
+                warn!("Recording stopped");
+            }
+            return;
+        }
+    };
+
+    if let Some(path) = capture_path {
+        if let Err(e) = image.save(&path) {
+            warn!("Failed to save capture to {}: {}", path.display(), e);
+        }
+    }
+    if let Some(active) = recorder {
+        if let Err(e) = active.write_frame(&image) {
+            warn!("Recording stopped after {} frames: {}", active.frame_count(), e);
+            *recorder = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vulkano::format::Format;
+
+    fn frame() -> CapturedImage {
+        CapturedImage::from_raw(Format::R8G8B8A8_UNORM, [2, 2], vec![255; 16]).unwrap()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("recording-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn failing_recorder() -> Recorder {
+        let command = EncoderCommand {
+            program: "missing-encoder-for-recording-tests".to_string(),
+            args: Vec::new(),
+        };
+        Recorder::new(FrameSink::Encoder(command), 30.0).unwrap()
+    }
+
+    #[test]
+    fn failing_sink_stops_recording_without_failing_the_frame() {
+        let mut recorder = Some(failing_recorder());
+        write_captured_frame(Ok(frame()), None, &mut recorder);
+        assert!(recorder.is_none());
+
+        // The next frame is still captured normally
+        let dir = temp_dir("next-frame");
+        let path = dir.join("next.png");
+        write_captured_frame(Ok(frame()), Some(path.clone()), &mut recorder);
+        assert!(path.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_readback_stops_recording() {
+        let dir = temp_dir("readback");
+        let mut recorder = Some(Recorder::new(FrameSink::ImageSequence(dir.clone()), 30.0).unwrap());
+        write_captured_frame(Err(anyhow!("device lost")), None, &mut recorder);
+        assert!(recorder.is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn working_sink_keeps_recording() {
+        let dir = temp_dir("sequence");
+        let mut recorder = Some(Recorder::new(FrameSink::ImageSequence(dir.clone()), 30.0).unwrap());
+        write_captured_frame(Ok(frame()), None, &mut recorder);
+        write_captured_frame(Ok(frame()), None, &mut recorder);
+
+        assert_eq!(recorder.as_ref().map(Recorder::frame_count), Some(2));
+        assert!(dir.join("frame-000001.png").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_save_is_not_fatal() {
+        let mut recorder = None;
+        let path = temp_dir("unsupported").join("capture.bmp");
+        write_captured_frame(Ok(frame()), Some(path.clone()), &mut recorder);
+        assert!(!path.exists());
+    }
+}
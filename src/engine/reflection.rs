@@ -0,0 +1,170 @@
+use anyhow::{anyhow, Result};
+use log::info;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Arc;
+use vulkano::descriptor_set::layout::{DescriptorSetLayout, DescriptorSetLayoutCreateInfo};
+use vulkano::device::Device;
+use vulkano::pipeline::graphics::vertex_input::{
+    Vertex, VertexBufferDescription, VertexDefinition, VertexInputState,
+};
+use vulkano::pipeline::layout::{PipelineLayoutCreateInfo, PushConstantRange};
+use vulkano::pipeline::PipelineLayout;
+use vulkano::shader::{DescriptorBindingRequirements, EntryPoint, ShaderExecution};
+
+/// Creates a pipeline layout from the descriptor and push constant requirements of the given shader stages
+pub fn create_pipeline_layout(
+    device: Arc<Device>,
+    stages: &[&EntryPoint],
+) -> Result<Arc<PipelineLayout>> {
+    // Merge the descriptor requirements of every stage, binding by binding
+    let mut descriptor_requirements: HashMap<(u32, u32), DescriptorBindingRequirements> =
+        HashMap::new();
+
+    for stage in stages {
+        for ((set, binding), requirements) in stage.descriptor_binding_requirements() {
+            match descriptor_requirements.entry((set, binding)) {
+                Entry::Occupied(entry) => {
+                    entry.into_mut().merge(requirements).map_err(|e| {
+                        anyhow!(
+                            "Incompatible descriptor requirements for set {} binding {}: {}",
+                            set,
+                            binding,
+                            e
+                        )
+                    })?;
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(requirements.clone());
+                }
+            }
+        }
+    }
+
+    let set_layouts = DescriptorSetLayoutCreateInfo::from_requirements(
+        descriptor_requirements
+            .iter()
+            .map(|(&location, requirements)| (location, requirements)),
+    )
+    .into_iter()
+    .map(|create_info| DescriptorSetLayout::new(device.clone(), create_info))
+    .collect::<Result<Vec<_>, _>>()?;
+
+    let push_constant_ranges = merge_push_constant_ranges(
+        stages
+            .iter()
+            .filter_map(|stage| stage.push_constant_requirements()),
+    );
+
+    info!(
+        "Reflected pipeline layout with {} descriptor set(s) and {} push constant range(s)",
+        set_layouts.len(),
+        push_constant_ranges.len()
+    );
+
+    let pipeline_layout = PipelineLayout::new(
+        device,
+        PipelineLayoutCreateInfo {
+            set_layouts,
+            push_constant_ranges,
+            ..Default::default()
+        },
+    )?;
+
+    Ok(pipeline_layout)
+}
+
+/// Unions push constant ranges that cover the same bytes so that every stage appears in only one range
+fn merge_push_constant_ranges<'a>(
+    ranges: impl IntoIterator<Item = &'a PushConstantRange>,
+) -> Vec<PushConstantRange> {
+    let mut merged: Vec<PushConstantRange> = Vec::new();
+
+    for range in ranges {
+        match merged
+            .iter_mut()
+            .find(|existing| existing.offset == range.offset && existing.size == range.size)
+        {
+            Some(existing) => existing.stages |= range.stages,
+            None => merged.push(*range),
+        }
+    }
+
+    merged.sort_by_key(|range| range.offset);
+    merged
+}
+
+/// Validates a vertex shader's inputs against a single per-vertex buffer of type `V`
+pub fn vertex_input_state<V: Vertex>(
+    vertex_shader: &EntryPoint,
+) -> Result<VertexInputState> {
+    vertex_input_state_from_buffers(&[V::per_vertex()], vertex_shader)
+}
+
+/// Validates a vertex shader's inputs against a set of vertex buffer descriptions
+///
+/// Every shader input must be provided by one of the buffers with a matching component and
+/// location count. Mismatches are reported by name so they surface at pipeline creation.
+pub fn vertex_input_state_from_buffers(
+    buffers: &[VertexBufferDescription],
+    vertex_shader: &EntryPoint,
+) -> Result<VertexInputState> {
+    if !matches!(vertex_shader.execution(), ShaderExecution::Vertex) {
+        return Err(anyhow!("Vertex input validation requires a vertex shader entry point"));
+    }
+
+    let mut errors = Vec::new();
+
+    for element in vertex_shader.input_interface().elements() {
+        let name = match &element.name {
+            Some(name) => name.as_ref(),
+            None => {
+                errors.push(format!("input at location {} has no name", element.location));
+                continue;
+            }
+        };
+
+        match buffers.iter().find_map(|buffer| buffer.members.get(name)) {
+            Some(member) => {
+                if member.num_components() != element.ty.num_components
+                    || member.num_elements != element.ty.num_elements
+                {
+                    errors.push(format!(
+                        "input `{}` (location {}) expects {} component(s) x {} element(s) of {:?}, \
+                         but the vertex type provides {:?} x {}",
+                        name,
+                        element.location,
+                        element.ty.num_components,
+                        element.ty.num_elements,
+                        element.ty.base_type,
+                        member.format,
+                        member.num_elements
+                    ));
+                }
+            }
+            None => {
+                let mut available: Vec<_> = buffers
+                    .iter()
+                    .flat_map(|buffer| buffer.members.keys().map(String::as_str))
+                    .collect();
+                available.sort_unstable();
+                errors.push(format!(
+                    "input `{}` (location {}) is not provided by the vertex type (available: {})",
+                    name,
+                    element.location,
+                    available.join(", ")
+                ));
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(anyhow!(
+            "Vertex input mismatch between shader and vertex type:\n  {}",
+            errors.join("\n  ")
+        ));
+    }
+
+    let state = buffers.definition(vertex_shader.input_interface())?;
+    Ok(state)
+}
@@ -0,0 +1,1451 @@
+use anyhow::{anyhow, Result};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use vulkano::buffer::Subbuffer;
+use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
+use vulkano::command_buffer::{
+    AutoCommandBufferBuilder, PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassContents,
+};
+use vulkano::device::{Device, DeviceOwned};
+use vulkano::format::{ClearValue, Format};
+use vulkano::image::view::{ImageView, ImageViewAbstract};
+use vulkano::image::{AttachmentImage, ImageAccess, ImageLayout, ImageUsage, SampleCount};
+use vulkano::memory::allocator::StandardMemoryAllocator;
+use vulkano::render_pass::{
+    AttachmentDescription, AttachmentReference, Framebuffer, FramebufferCreateInfo, LoadOp,
+    RenderPass, RenderPassCreateInfo, StoreOp, Subpass, SubpassDependency, SubpassDescription,
+};
+use vulkano::sync::{AccessFlags, DependencyFlags, PipelineStages};
+
+/// The command buffer builder the renderer records frames into
+pub type FrameCommandBuilder =
+    AutoCommandBufferBuilder<PrimaryAutoCommandBuffer, Arc<StandardCommandBufferAllocator>>;
+
+/// Handle to an image or buffer declared in a render graph
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ResourceId(usize);
+
+/// Size of an image, either fixed or following the swapchain
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageSize {
+    /// A fixed size in pixels
+    Absolute([u32; 2]),
+    /// A fraction of the swapchain extent, e.g. 0.5 for half resolution
+    SwapchainRelative(f32),
+}
+
+impl ImageSize {
+    /// Returns the size in pixels for the given swapchain extent, at least one pixel each way
+    pub fn resolve(self, swapchain_extent: [u32; 2]) -> [u32; 2] {
+        match self {
+            ImageSize::Absolute(extent) => [extent[0].max(1), extent[1].max(1)],
+            ImageSize::SwapchainRelative(scale) => [
+                ((swapchain_extent[0] as f32 * scale).round() as u32).max(1),
+                ((swapchain_extent[1] as f32 * scale).round() as u32).max(1),
+            ],
+        }
+    }
+}
+
+/// Description of a transient image allocated by the graph
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageDesc {
+    pub format: Format,
+    pub size: ImageSize,
+    pub samples: SampleCount,
+}
+
+impl ImageDesc {
+    /// A single-sampled image of the given format and size
+    pub fn new(format: Format, size: ImageSize) -> Self {
+        Self {
+            format,
+            size,
+            samples: SampleCount::Sample1,
+        }
+    }
+}
+
+/// How a pass uses a resource
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Access {
+    ColorAttachment,
+    DepthAttachment,
+    InputAttachment,
+    Sampled,
+    StorageImage,
+    StorageBuffer,
+    UniformBuffer,
+    VertexBuffer,
+    IndexBuffer,
+    IndirectBuffer,
+    TransferSource,
+    TransferDestination,
+}
+
+impl Access {
+    /// Returns the image layout the access needs, or `None` for buffer accesses
+    pub fn layout(self) -> Option<ImageLayout> {
+        match self {
+            Access::ColorAttachment => Some(ImageLayout::ColorAttachmentOptimal),
+            Access::DepthAttachment => Some(ImageLayout::DepthStencilAttachmentOptimal),
+            Access::InputAttachment | Access::Sampled => Some(ImageLayout::ShaderReadOnlyOptimal),
+            Access::StorageImage => Some(ImageLayout::General),
+            Access::TransferSource => Some(ImageLayout::TransferSrcOptimal),
+            Access::TransferDestination => Some(ImageLayout::TransferDstOptimal),
+            Access::StorageBuffer
+            | Access::UniformBuffer
+            | Access::VertexBuffer
+            | Access::IndexBuffer
+            | Access::IndirectBuffer => None,
+        }
+    }
+
+    /// Returns the image usage a transient image needs for this access
+    fn image_usage(self) -> ImageUsage {
+        match self {
+            Access::ColorAttachment => ImageUsage::COLOR_ATTACHMENT,
+            Access::DepthAttachment => ImageUsage::DEPTH_STENCIL_ATTACHMENT,
+            Access::InputAttachment => ImageUsage::INPUT_ATTACHMENT,
+            Access::Sampled => ImageUsage::SAMPLED,
+            Access::StorageImage => ImageUsage::STORAGE,
+            Access::TransferSource => ImageUsage::TRANSFER_SRC,
+            Access::TransferDestination => ImageUsage::TRANSFER_DST,
+            _ => ImageUsage::empty(),
+        }
+    }
+}
+
+/// Where a resource's memory comes from
+enum ResourceKind {
+    /// Allocated by the graph for the frame and reused across frames
+    Transient(ImageDesc),
+    /// An image owned outside the graph, such as the swapchain image
+    ImportedImage(Arc<dyn ImageViewAbstract>),
+    /// A buffer owned outside the graph
+    ImportedBuffer(Subbuffer<[u8]>),
+}
+
+/// A resource or pass name; names known at compile time are kept without allocating
+pub type GraphName = Cow<'static, str>;
+
+struct ResourceEntry {
+    name: GraphName,
+    kind: ResourceKind,
+}
+
+/// One use of a resource by a pass
+#[derive(Clone, Copy, Debug)]
+struct ResourceUse {
+    resource: ResourceId,
+    access: Access,
+    write: bool,
+    /// Whether a write keeps the previous contents, making the previous writer a dependency
+    preserves: bool,
+}
+
+/// What a raster pass does with one of its attachments
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AttachmentKind {
+    Color,
+    /// Single-sampled target a multisampled color attachment is resolved into
+    Resolve,
+    /// Depth, shared by every subpass of the pass
+    Depth,
+}
+
+/// Format and role of a raster pass attachment, for creating pipelines before the graph
+/// compiles
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AttachmentFormat {
+    pub format: Format,
+    pub samples: SampleCount,
+    pub kind: AttachmentKind,
+}
+
+/// The attachments one subpass of a raster pass uses
+///
+/// Indices refer to the pass's color and resolve attachments in declaration order; the depth
+/// attachment is used by every subpass.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SubpassLayout {
+    pub color: Vec<usize>,
+    /// Attachments written by an earlier subpass and read here as input attachments
+    pub input: Vec<usize>,
+    /// Resolve target of each color attachment at the end of the subpass, or empty for none
+    pub resolve: Vec<usize>,
+}
+
+impl SubpassLayout {
+    /// One subpass drawing to every color attachment and resolving into every resolve one
+    fn single(kinds: impl IntoIterator<Item = AttachmentKind>) -> Self {
+        let mut layout = Self::default();
+        for (index, kind) in kinds.into_iter().enumerate() {
+            match kind {
+                AttachmentKind::Color => layout.color.push(index),
+                AttachmentKind::Resolve => layout.resolve.push(index),
+                AttachmentKind::Depth => {}
+            }
+        }
+        layout
+    }
+}
+
+/// A color, resolve or depth attachment of a raster pass
+#[derive(Clone, Copy)]
+struct AttachmentUse {
+    resource: ResourceId,
+    clear: Option<ClearValue>,
+    kind: AttachmentKind,
+}
+
+type ExecuteFn<'a> = Box<dyn for<'c> FnOnce(&mut PassContext<'c>) -> Result<()> + 'a>;
+
+struct PassNode<'a> {
+    name: GraphName,
+    uses: Vec<ResourceUse>,
+    /// Color and resolve attachments in declaration order
+    color_attachments: Vec<AttachmentUse>,
+    depth_attachment: Option<AttachmentUse>,
+    /// Subpasses of the render pass, or empty for a single one
+    subpasses: Vec<SubpassLayout>,
+    side_effects: bool,
+    execute: ExecuteFn<'a>,
+}
+
+impl PassNode<'_> {
+    fn is_raster(&self) -> bool {
+        !self.color_attachments.is_empty() || self.depth_attachment.is_some()
+    }
+}
+
+/// A frame's passes and the resources they read and write
+///
+/// Passes are declared with the resources they use and compiled into an execution order.
+/// Passes whose results never reach an output resource are culled, transient images are
+/// allocated for the remaining ones, and passes with attachments get a render pass and
+/// framebuffer built for them.
+pub struct RenderGraph<'a> {
+    resources: Vec<ResourceEntry>,
+    passes: Vec<PassNode<'a>>,
+    outputs: HashSet<ResourceId>,
+}
+
+impl<'a> RenderGraph<'a> {
+    /// Creates an empty graph
+    pub fn new() -> Self {
+        Self {
+            resources: Vec::new(),
+            passes: Vec::new(),
+            outputs: HashSet::new(),
+        }
+    }
+
+    /// Declares a transient image that the graph allocates when a live pass uses it
+    pub fn create_image(&mut self, name: impl Into<GraphName>, desc: ImageDesc) -> ResourceId {
+        self.add_resource(name.into(), ResourceKind::Transient(desc))
+    }
+
+    /// Declares an image owned outside the graph
+    pub fn import_image(
+        &mut self,
+        name: impl Into<GraphName>,
+        view: Arc<dyn ImageViewAbstract>,
+    ) -> ResourceId {
+        self.add_resource(name.into(), ResourceKind::ImportedImage(view))
+    }
+
+    /// Declares a buffer owned outside the graph
+    pub fn import_buffer<T: ?Sized>(
+        &mut self,
+        name: impl Into<GraphName>,
+        buffer: Subbuffer<T>,
+    ) -> ResourceId {
+        self.add_resource(name.into(), ResourceKind::ImportedBuffer(buffer.into_bytes()))
+    }
+
+    /// Marks a resource as a result of the frame, keeping the passes that produce it alive
+    pub fn mark_output(&mut self, resource: ResourceId) {
+        self.outputs.insert(resource);
+    }
+
+    /// Starts declaring a pass; it is added to the graph by `PassBuilder::execute`
+    pub fn add_pass(&mut self, name: impl Into<GraphName>) -> PassBuilder<'_, 'a> {
+        PassBuilder {
+            graph: self,
+            name: name.into(),
+            uses: Vec::new(),
+            color_attachments: Vec::new(),
+            depth_attachment: None,
+            subpasses: Vec::new(),
+            side_effects: false,
+        }
+    }
+
+    fn add_resource(&mut self, name: GraphName, kind: ResourceKind) -> ResourceId {
+        self.resources.push(ResourceEntry { name, kind });
+        ResourceId(self.resources.len() - 1)
+    }
+
+    /// Orders and culls the passes and creates the resources they need
+    pub fn compile(
+        self,
+        allocator: &StandardMemoryAllocator,
+        cache: &mut RenderGraphCache,
+        swapchain_extent: [u32; 2],
+    ) -> Result<CompiledGraph<'a>> {
+        let (order_deps, live_deps) = self.dependencies();
+        let live = self.live_passes(&live_deps);
+        let order = self.execution_order(&order_deps, &live)?;
+
+        // Resolve every resource used by a live pass
+        let mut usages: HashMap<ResourceId, ImageUsage> = HashMap::new();
+        for &index in &order {
+            for usage in &self.passes[index].uses {
+                *usages.entry(usage.resource).or_insert_with(ImageUsage::empty) |=
+                    usage.access.image_usage();
+            }
+        }
+
+        let mut images: HashMap<ResourceId, Arc<dyn ImageViewAbstract>> = HashMap::new();
+        let mut buffers: HashMap<ResourceId, Subbuffer<[u8]>> = HashMap::new();
+        let mut transient_count: HashMap<TransientImageKey, usize> = HashMap::new();
+        let mut used_keys = HashSet::new();
+        let mut resource_ids: Vec<_> = usages.keys().copied().collect();
+        resource_ids.sort();
+
+        for id in resource_ids {
+            match &self.resources[id.0].kind {
+                ResourceKind::Transient(desc) => {
+                    let mut key = TransientImageKey {
+                        format: desc.format,
+                        extent: desc.size.resolve(swapchain_extent),
+                        samples: desc.samples,
+                        usage: usages[&id],
+                        slot: 0,
+                    };
+                    // Several transients with the same description each get their own image
+                    let slot = transient_count.entry(key).or_insert(0);
+                    key.slot = *slot;
+                    *slot += 1;
+
+                    images.insert(id, cache.transient_image(allocator, key)?);
+                    used_keys.insert(key);
+                }
+                ResourceKind::ImportedImage(view) => {
+                    images.insert(id, view.clone());
+                }
+                ResourceKind::ImportedBuffer(buffer) => {
+                    buffers.insert(id, buffer.clone());
+                }
+            }
+        }
+        cache.retain_images(&used_keys);
+
+        let pass_barriers = self.barriers(&order);
+
+        // Build the render pass and framebuffer of each raster pass
+        let device = allocator.device().clone();
+        let mut targets = Vec::with_capacity(order.len());
+        for (&index, barriers) in order.iter().zip(&pass_barriers) {
+            let pass = &self.passes[index];
+            targets.push(if pass.is_raster() {
+                Some(self.raster_target(&device, cache, pass, barriers, &images)?)
+            } else {
+                None
+            });
+        }
+
+        let mut transients: Vec<(ResourceId, [u32; 2], Format)> = images
+            .iter()
+            .filter(|(id, _)| matches!(self.resources[id.0].kind, ResourceKind::Transient(_)))
+            .filter_map(|(id, view)| {
+                let extent = view.image().dimensions().width_height();
+                Some((*id, extent, view.format()?))
+            })
+            .collect();
+        transients.sort_by_key(|(id, _, _)| *id);
+        let names: Vec<GraphName> = self.resources.into_iter().map(|entry| entry.name).collect();
+
+        let order_set: HashSet<usize> = order.iter().copied().collect();
+        let mut passes: Vec<Option<PassNode<'a>>> = self.passes.into_iter().map(Some).collect();
+        let culled = (0..passes.len())
+            .filter(|index| !order_set.contains(index))
+            .filter_map(|index| passes[index].take().map(|pass| pass.name))
+            .collect();
+
+        let compiled_passes = order
+            .iter()
+            .zip(pass_barriers)
+            .zip(targets)
+            .map(|((&index, barriers), target)| {
+                let node = passes[index].take().expect("pass is scheduled once");
+                CompiledPass {
+                    name: node.name,
+                    uses: node.uses,
+                    barriers,
+                    target,
+                    execute: node.execute,
+                }
+            })
+            .collect();
+
+        Ok(CompiledGraph {
+            passes: compiled_passes,
+            culled,
+            names,
+            transients,
+            images,
+            buffers,
+        })
+    }
+
+    /// Returns, per pass, the passes it must run after and the passes that keep it alive
+    ///
+    /// A read depends on the last earlier write of the resource, or on the first write when
+    /// it was declared before any. A write runs after earlier reads and writes, and only
+    /// keeps the previous writer alive when it preserves the existing contents.
+    fn dependencies(&self) -> (Vec<HashSet<usize>>, Vec<HashSet<usize>>) {
+        let mut order_deps: Vec<HashSet<usize>> = vec![HashSet::new(); self.passes.len()];
+        let mut live_deps: Vec<HashSet<usize>> = vec![HashSet::new(); self.passes.len()];
+
+        for resource in 0..self.resources.len() {
+            let mut last_writer: Option<usize> = None;
+            let mut readers: Vec<usize> = Vec::new();
+            let mut early_readers: Vec<usize> = Vec::new();
+
+            for (index, pass) in self.passes.iter().enumerate() {
+                for usage in pass.uses.iter().filter(|usage| usage.resource.0 == resource) {
+                    if usage.write {
+                        match last_writer {
+                            Some(writer) if writer != index => {
+                                order_deps[index].insert(writer);
+                                if usage.preserves {
+                                    live_deps[index].insert(writer);
+                                }
+                            }
+                            Some(_) => {}
+                            None => {
+                                for reader in early_readers.drain(..) {
+                                    if reader != index {
+                                        order_deps[reader].insert(index);
+                                        live_deps[reader].insert(index);
+                                    }
+                                }
+                            }
+                        }
+                        for reader in readers.drain(..) {
+                            if reader != index {
+                                order_deps[index].insert(reader);
+                            }
+                        }
+                        last_writer = Some(index);
+                    } else {
+                        match last_writer {
+                            Some(writer) if writer != index => {
+                                order_deps[index].insert(writer);
+                                live_deps[index].insert(writer);
+                                readers.push(index);
+                            }
+                            Some(_) => {}
+                            None => early_readers.push(index),
+                        }
+                    }
+                }
+            }
+        }
+
+        (order_deps, live_deps)
+    }
+
+    /// Returns the passes that contribute to an output or have side effects
+    fn live_passes(&self, live_deps: &[HashSet<usize>]) -> HashSet<usize> {
+        let mut stack: Vec<usize> = self
+            .passes
+            .iter()
+            .enumerate()
+            .filter(|(_, pass)| pass.side_effects)
+            .map(|(index, _)| index)
+            .collect();
+
+        // The last writer of each output produces the final contents
+        for output in &self.outputs {
+            let last_writer = self.passes.iter().rposition(|pass| {
+                pass.uses
+                    .iter()
+                    .any(|usage| usage.resource == *output && usage.write)
+            });
+            stack.extend(last_writer);
+        }
+
+        let mut live = HashSet::new();
+        while let Some(index) = stack.pop() {
+            if live.insert(index) {
+                stack.extend(live_deps[index].iter().copied());
+            }
+        }
+        live
+    }
+
+    /// Sorts the live passes so every pass runs after its dependencies, keeping declaration
+    /// order where the dependencies allow it
+    fn execution_order(
+        &self,
+        order_deps: &[HashSet<usize>],
+        live: &HashSet<usize>,
+    ) -> Result<Vec<usize>> {
+        let mut remaining: HashMap<usize, usize> = live
+            .iter()
+            .map(|&index| {
+                let count = order_deps[index].iter().filter(|dep| live.contains(dep)).count();
+                (index, count)
+            })
+            .collect();
+
+        let mut ready: BinaryHeap<Reverse<usize>> = remaining
+            .iter()
+            .filter(|(_, &count)| count == 0)
+            .map(|(&index, _)| Reverse(index))
+            .collect();
+
+        let mut order = Vec::with_capacity(live.len());
+        while let Some(Reverse(index)) = ready.pop() {
+            order.push(index);
+            for (&other, count) in remaining.iter_mut() {
+                if *count > 0 && order_deps[other].contains(&index) {
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push(Reverse(other));
+                    }
+                }
+            }
+        }
+
+        if order.len() != live.len() {
+            let stuck: Vec<&str> = live
+                .iter()
+                .filter(|index| !order.contains(index))
+                .map(|&index| self.passes[index].name.as_ref())
+                .collect();
+            return Err(anyhow!("Render graph has a dependency cycle between passes {:?}", stuck));
+        }
+
+        Ok(order)
+    }
+
+    /// Returns the barriers and layout transitions each pass in `order` needs
+    ///
+    /// A pass needs a barrier on a resource when it or the previous pass using the resource
+    /// writes it (a hazard), or when the image layout changes. Uses within one pass are
+    /// ordered by its own render pass or commands, so each resource gets one barrier for
+    /// the pass's first access to it.
+    fn barriers(&self, order: &[usize]) -> Vec<Vec<Barrier>> {
+        let mut last_use: HashMap<ResourceId, ResourceUse> = HashMap::new();
+        let mut pass_barriers = Vec::with_capacity(order.len());
+        for &index in order {
+            let uses = &self.passes[index].uses;
+            let mut barriers: Vec<Barrier> = Vec::new();
+            for usage in uses {
+                if barriers.iter().any(|barrier| barrier.resource == usage.resource) {
+                    continue;
+                }
+                let writes = uses
+                    .iter()
+                    .any(|other| other.resource == usage.resource && other.write);
+                let previous = last_use.get(&usage.resource).copied();
+                let old_layout = previous.and_then(|previous| previous.access.layout());
+                let new_layout = usage.access.layout();
+                let hazard = previous.is_some_and(|previous| previous.write || writes);
+                let transition = new_layout.is_some() && old_layout != new_layout;
+
+                if hazard || transition {
+                    barriers.push(Barrier {
+                        resource: usage.resource,
+                        src: previous.map(|previous| previous.access),
+                        dst: usage.access,
+                        old_layout: old_layout.or(new_layout.map(|_| ImageLayout::Undefined)),
+                        new_layout,
+                    });
+                }
+            }
+
+            // Later passes synchronize against this pass's last access and any write in it
+            for usage in uses {
+                let write = uses
+                    .iter()
+                    .any(|other| other.resource == usage.resource && other.write);
+                last_use.insert(usage.resource, ResourceUse { write, ..*usage });
+            }
+            pass_barriers.push(barriers);
+        }
+        pass_barriers
+    }
+
+    /// Creates the render pass, framebuffer and clear values for a pass with attachments
+    ///
+    /// Cleared attachments that are first used this frame start from the `Undefined` layout
+    /// given by their barrier, so their old contents are discarded rather than transitioned.
+    /// Resolve attachments are always fully overwritten.
+    fn raster_target(
+        &self,
+        device: &Arc<Device>,
+        cache: &mut RenderGraphCache,
+        pass: &PassNode<'a>,
+        barriers: &[Barrier],
+        images: &HashMap<ResourceId, Arc<dyn ImageViewAbstract>>,
+    ) -> Result<RasterTarget> {
+        let attachments: Vec<AttachmentUse> = pass
+            .color_attachments
+            .iter()
+            .copied()
+            .chain(pass.depth_attachment)
+            .collect();
+
+        let mut key = RenderPassKey {
+            attachments: Vec::with_capacity(attachments.len()),
+            subpasses: if pass.subpasses.is_empty() {
+                vec![SubpassLayout::single(attachments.iter().map(|a| a.kind))]
+            } else {
+                pass.subpasses.clone()
+            },
+        };
+        let mut views = Vec::with_capacity(attachments.len());
+        let mut clear_values = Vec::with_capacity(attachments.len());
+
+        for attachment in attachments {
+            let view = images.get(&attachment.resource).ok_or_else(|| {
+                anyhow!(
+                    "Pass `{}` uses attachment `{}` that was not resolved",
+                    pass.name,
+                    self.resources[attachment.resource.0].name
+                )
+            })?;
+            let format = view.format().ok_or_else(|| {
+                anyhow!("Attachment `{}` has no format", self.resources[attachment.resource.0].name)
+            })?;
+
+            let first_use = barriers.iter().any(|barrier| {
+                barrier.resource == attachment.resource
+                    && barrier.old_layout == Some(ImageLayout::Undefined)
+            });
+            let clear = attachment.clear.is_some();
+            key.attachments.push(AttachmentKey {
+                format,
+                samples: view.image().samples(),
+                kind: attachment.kind,
+                clear,
+                discard: (clear && first_use) || attachment.kind == AttachmentKind::Resolve,
+            });
+            views.push(view.clone());
+            clear_values.push(attachment.clear);
+        }
+
+        let render_pass = cache.render_pass(device, key)?;
+        let framebuffer = Framebuffer::new(
+            render_pass,
+            FramebufferCreateInfo {
+                attachments: views,
+                ..Default::default()
+            },
+        )?;
+
+        Ok(RasterTarget {
+            framebuffer,
+            clear_values,
+        })
+    }
+}
+
+impl Default for RenderGraph<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Declares the resources a pass uses before adding it to the graph
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    name: GraphName,
+    uses: Vec<ResourceUse>,
+    color_attachments: Vec<AttachmentUse>,
+    depth_attachment: Option<AttachmentUse>,
+    subpasses: Vec<SubpassLayout>,
+    side_effects: bool,
+}
+
+impl<'g, 'a> PassBuilder<'g, 'a> {
+    /// Declares that the pass reads a resource
+    pub fn read(mut self, resource: ResourceId, access: Access) -> Self {
+        self.uses.push(ResourceUse {
+            resource,
+            access,
+            write: false,
+            preserves: true,
+        });
+        self
+    }
+
+    /// Declares that the pass writes a resource, possibly keeping parts of its old contents
+    pub fn write(mut self, resource: ResourceId, access: Access) -> Self {
+        self.uses.push(ResourceUse {
+            resource,
+            access,
+            write: true,
+            preserves: true,
+        });
+        self
+    }
+
+    /// Renders into a color attachment, cleared first when `clear` is given
+    ///
+    /// Passes with attachments get a render pass begun around their callback, with a single
+    /// subpass unless `subpasses` describes more.
+    pub fn color_attachment(mut self, resource: ResourceId, clear: Option<ClearValue>) -> Self {
+        self.uses.push(ResourceUse {
+            resource,
+            access: Access::ColorAttachment,
+            write: true,
+            preserves: clear.is_none(),
+        });
+        self.color_attachments.push(AttachmentUse {
+            resource,
+            clear,
+            kind: AttachmentKind::Color,
+        });
+        self
+    }
+
+    /// Resolves a multisampled color attachment into a single-sampled image
+    pub fn resolve_attachment(mut self, resource: ResourceId) -> Self {
+        self.uses.push(ResourceUse {
+            resource,
+            access: Access::ColorAttachment,
+            write: true,
+            preserves: false,
+        });
+        self.color_attachments.push(AttachmentUse {
+            resource,
+            clear: None,
+            kind: AttachmentKind::Resolve,
+        });
+        self
+    }
+
+    /// Renders with a depth attachment, cleared first when `clear` is given
+    pub fn depth_attachment(mut self, resource: ResourceId, clear: Option<ClearValue>) -> Self {
+        self.uses.push(ResourceUse {
+            resource,
+            access: Access::DepthAttachment,
+            write: true,
+            preserves: clear.is_none(),
+        });
+        self.depth_attachment = Some(AttachmentUse {
+            resource,
+            clear,
+            kind: AttachmentKind::Depth,
+        });
+        self
+    }
+
+    /// Splits the render pass into subpasses over the attachments declared so far
+    ///
+    /// The callback moves between them with `next_subpass`. Attachments read as input
+    /// attachments are declared as reads of the pass.
+    pub fn subpasses(mut self, subpasses: Vec<SubpassLayout>) -> Self {
+        for &index in subpasses.iter().flat_map(|subpass| &subpass.input) {
+            if let Some(attachment) = self.color_attachments.get(index) {
+                self.uses.push(ResourceUse {
+                    resource: attachment.resource,
+                    access: Access::InputAttachment,
+                    write: false,
+                    preserves: true,
+                });
+            }
+        }
+        self.subpasses = subpasses;
+        self
+    }
+
+    /// Keeps the pass even if nothing reads its results, e.g. for readbacks or compute work
+    pub fn side_effects(mut self) -> Self {
+        self.side_effects = true;
+        self
+    }
+
+    /// Adds the pass to the graph with the callback that records it
+    pub fn execute<F>(self, execute: F)
+    where
+        F: for<'c> FnOnce(&mut PassContext<'c>) -> Result<()> + 'a,
+    {
+        self.graph.passes.push(PassNode {
+            name: self.name,
+            uses: self.uses,
+            color_attachments: self.color_attachments,
+            depth_attachment: self.depth_attachment,
+            subpasses: self.subpasses,
+            side_effects: self.side_effects,
+            execute: Box::new(execute),
+        });
+    }
+}
+
+/// What a pass callback can access while recording
+pub struct PassContext<'c> {
+    pub builder: &'c mut FrameCommandBuilder,
+    images: &'c HashMap<ResourceId, Arc<dyn ImageViewAbstract>>,
+    buffers: &'c HashMap<ResourceId, Subbuffer<[u8]>>,
+    framebuffer: Option<&'c Arc<Framebuffer>>,
+}
+
+impl PassContext<'_> {
+    /// Returns the view of an image the pass declared
+    pub fn image(&self, resource: ResourceId) -> Result<Arc<dyn ImageViewAbstract>> {
+        self.images
+            .get(&resource)
+            .cloned()
+            .ok_or_else(|| anyhow!("Image {:?} is not available to this pass", resource))
+    }
+
+    /// Returns a buffer the pass declared
+    pub fn buffer(&self, resource: ResourceId) -> Result<Subbuffer<[u8]>> {
+        self.buffers
+            .get(&resource)
+            .cloned()
+            .ok_or_else(|| anyhow!("Buffer {:?} is not available to this pass", resource))
+    }
+
+    /// Returns the first subpass a raster pass records into, for building pipelines
+    pub fn subpass(&self) -> Result<Subpass> {
+        let framebuffer = self
+            .framebuffer
+            .ok_or_else(|| anyhow!("Only passes with attachments have a subpass"))?;
+        Subpass::from(framebuffer.render_pass().clone(), 0)
+            .ok_or_else(|| anyhow!("Render graph pass has no subpass"))
+    }
+
+    /// Returns the size of a raster pass's attachments
+    pub fn extent(&self) -> Option<[u32; 2]> {
+        self.framebuffer.map(|framebuffer| framebuffer.extent())
+    }
+}
+
+/// A synchronization point before a pass, from the previous use of a resource to this one
+///
+/// Vulkano's `AutoCommandBufferBuilder` records pipeline barriers and layout transitions from
+/// the resources each command uses and has no way to record them by hand. The graph makes
+/// that correct by recording passes in dependency order with every shared resource declared,
+/// feeds first-use transitions into its render passes, and lists all barriers in the dump.
+#[derive(Clone, Copy, Debug)]
+pub struct Barrier {
+    pub resource: ResourceId,
+    /// The previous access in this frame, or `None` for the first use
+    pub src: Option<Access>,
+    pub dst: Access,
+    pub old_layout: Option<ImageLayout>,
+    pub new_layout: Option<ImageLayout>,
+}
+
+struct RasterTarget {
+    framebuffer: Arc<Framebuffer>,
+    clear_values: Vec<Option<ClearValue>>,
+}
+
+struct CompiledPass<'a> {
+    name: GraphName,
+    uses: Vec<ResourceUse>,
+    barriers: Vec<Barrier>,
+    target: Option<RasterTarget>,
+    execute: ExecuteFn<'a>,
+}
+
+/// A render graph ready to record, with passes in execution order
+pub struct CompiledGraph<'a> {
+    passes: Vec<CompiledPass<'a>>,
+    culled: Vec<GraphName>,
+    names: Vec<GraphName>,
+    transients: Vec<(ResourceId, [u32; 2], Format)>,
+    images: HashMap<ResourceId, Arc<dyn ImageViewAbstract>>,
+    buffers: HashMap<ResourceId, Subbuffer<[u8]>>,
+}
+
+impl CompiledGraph<'_> {
+    /// Returns the names of the passes that will run, in order
+    pub fn pass_names(&self) -> Vec<&str> {
+        self.passes.iter().map(|pass| pass.name.as_ref()).collect()
+    }
+
+    /// Returns the names of the passes that were culled
+    pub fn culled(&self) -> &[GraphName] {
+        &self.culled
+    }
+
+    /// Returns the barriers computed before each pass, in execution order
+    pub fn barriers(&self) -> Vec<(&str, &[Barrier])> {
+        self.passes
+            .iter()
+            .map(|pass| (pass.name.as_ref(), pass.barriers.as_slice()))
+            .collect()
+    }
+
+    /// Records every pass into the command buffer
+    pub fn execute(self, builder: &mut FrameCommandBuilder) -> Result<()> {
+        let CompiledGraph {
+            passes,
+            images,
+            buffers,
+            ..
+        } = self;
+
+        for pass in passes {
+            if let Some(target) = &pass.target {
+                builder.begin_render_pass(
+                    RenderPassBeginInfo {
+                        clear_values: target.clear_values.clone(),
+                        ..RenderPassBeginInfo::framebuffer(target.framebuffer.clone())
+                    },
+                    SubpassContents::Inline,
+                )?;
+            }
+
+            let mut context = PassContext {
+                builder: &mut *builder,
+                images: &images,
+                buffers: &buffers,
+                framebuffer: pass.target.as_ref().map(|target| &target.framebuffer),
+            };
+            (pass.execute)(&mut context)
+                .map_err(|e| anyhow!("Render graph pass `{}` failed: {}", pass.name, e))?;
+
+            if pass.target.is_some() {
+                builder.end_render_pass()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for CompiledGraph<'_> {
+    /// Dumps the compiled graph: pass order, resource uses, barriers and culled passes
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |id: ResourceId| self.names[id.0].as_ref();
+
+        writeln!(
+            f,
+            "Render graph: {} pass(es), {} culled",
+            self.passes.len(),
+            self.culled.len()
+        )?;
+        for (index, pass) in self.passes.iter().enumerate() {
+            let kind = if pass.target.is_some() { "raster" } else { "custom" };
+            writeln!(f, "  [{}] {} ({})", index, pass.name, kind)?;
+            for barrier in &pass.barriers {
+                write!(f, "      barrier {}: ", name(barrier.resource))?;
+                match barrier.src {
+                    Some(access) => write!(f, "{:?}", access)?,
+                    None => write!(f, "start of frame")?,
+                }
+                write!(f, " -> {:?}", barrier.dst)?;
+                if let (Some(old), Some(new)) = (barrier.old_layout, barrier.new_layout) {
+                    if old != new {
+                        write!(f, " ({:?} -> {:?})", old, new)?;
+                    }
+                }
+                writeln!(f)?;
+            }
+            for usage in &pass.uses {
+                let verb = if usage.write { "writes" } else { "reads" };
+                writeln!(f, "      {} {} as {:?}", verb, name(usage.resource), usage.access)?;
+            }
+        }
+        for (id, extent, format) in &self.transients {
+            writeln!(
+                f,
+                "  transient {}: {}x{} {:?}",
+                name(*id),
+                extent[0],
+                extent[1],
+                format
+            )?;
+        }
+        for culled in &self.culled {
+            writeln!(f, "  culled {}", culled)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct TransientImageKey {
+    format: Format,
+    extent: [u32; 2],
+    samples: SampleCount,
+    usage: ImageUsage,
+    slot: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct AttachmentKey {
+    format: Format,
+    samples: SampleCount,
+    kind: AttachmentKind,
+    clear: bool,
+    /// Whether the render pass starts from `Undefined`, discarding the old contents
+    discard: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct RenderPassKey {
+    attachments: Vec<AttachmentKey>,
+    subpasses: Vec<SubpassLayout>,
+}
+
+/// Images and render passes kept between frames so compiling a graph each frame is cheap
+///
+/// Render passes are reused for the same attachment layout, which keeps pipelines built
+/// against them valid in the pipeline cache.
+#[derive(Default)]
+pub struct RenderGraphCache {
+    images: HashMap<TransientImageKey, Arc<ImageView<AttachmentImage>>>,
+    render_passes: HashMap<RenderPassKey, Arc<RenderPass>>,
+}
+
+impl RenderGraphCache {
+    /// Creates an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drops every cached image and render pass
+    pub fn clear(&mut self) {
+        self.images.clear();
+        self.render_passes.clear();
+    }
+
+    fn transient_image(
+        &mut self,
+        allocator: &StandardMemoryAllocator,
+        key: TransientImageKey,
+    ) -> Result<Arc<dyn ImageViewAbstract>> {
+        if let Some(view) = self.images.get(&key) {
+            return Ok(view.clone());
+        }
+
+        let image = AttachmentImage::multisampled_with_usage(
+            allocator,
+            key.extent,
+            key.samples,
+            key.format,
+            key.usage,
+        )?;
+        let view = ImageView::new_default(image)?;
+        self.images.insert(key, view.clone());
+        Ok(view)
+    }
+
+    /// Drops images no pass used this frame, e.g. after a resize
+    fn retain_images(&mut self, used: &HashSet<TransientImageKey>) {
+        self.images.retain(|key, _| used.contains(key));
+    }
+
+    /// Returns a render pass compatible with the one a raster pass with these attachments and
+    /// subpasses records into, for creating its pipelines before the graph compiles
+    ///
+    /// `attachments` lists the color and resolve attachments in declaration order, then the
+    /// depth attachment; no `subpasses` means a single one. Load and store operations do not
+    /// affect compatibility, so the render pass assumes cleared attachments first used there.
+    pub fn render_pass_for(
+        &mut self,
+        device: &Arc<Device>,
+        attachments: &[AttachmentFormat],
+        subpasses: &[SubpassLayout],
+    ) -> Result<Arc<RenderPass>> {
+        let key = RenderPassKey {
+            attachments: attachments
+                .iter()
+                .map(|attachment| AttachmentKey {
+                    format: attachment.format,
+                    samples: attachment.samples,
+                    kind: attachment.kind,
+                    clear: attachment.kind != AttachmentKind::Resolve,
+                    discard: true,
+                })
+                .collect(),
+            subpasses: if subpasses.is_empty() {
+                vec![SubpassLayout::single(attachments.iter().map(|a| a.kind))]
+            } else {
+                subpasses.to_vec()
+            },
+        };
+        self.render_pass(device, key)
+    }
+
+    /// Builds the render pass for a key, with each attachment starting in the layout of its
+    /// first subpass and ending in the layout of its last
+    fn render_pass(&mut self, device: &Arc<Device>, key: RenderPassKey) -> Result<Arc<RenderPass>> {
+        if let Some(render_pass) = self.render_passes.get(&key) {
+            return Ok(render_pass.clone());
+        }
+
+        let depth = key
+            .attachments
+            .iter()
+            .position(|attachment| attachment.kind == AttachmentKind::Depth);
+        let color_count = key
+            .attachments
+            .iter()
+            .filter(|attachment| attachment.kind != AttachmentKind::Depth)
+            .count();
+        let mut layouts: Vec<Option<(ImageLayout, ImageLayout)>> =
+            vec![None; key.attachments.len()];
+        let mut reference = |index: usize, layout: ImageLayout| -> Result<AttachmentReference> {
+            let entry = layouts
+                .get_mut(index)
+                .filter(|_| index < color_count || Some(index) == depth)
+                .ok_or_else(|| anyhow!("Subpass uses missing attachment {}", index))?;
+            *entry = Some((entry.map_or(layout, |(first, _)| first), layout));
+            Ok(AttachmentReference {
+                attachment: index as u32,
+                layout,
+                ..Default::default()
+            })
+        };
+
+        let mut subpasses = Vec::with_capacity(key.subpasses.len());
+        for subpass in &key.subpasses {
+            let mut description = SubpassDescription::default();
+            for &index in &subpass.color {
+                let color = reference(index, ImageLayout::ColorAttachmentOptimal)?;
+                description.color_attachments.push(Some(color));
+            }
+            for &index in &subpass.input {
+                let input = reference(index, ImageLayout::ShaderReadOnlyOptimal)?;
+                description.input_attachments.push(Some(input));
+            }
+            for &index in &subpass.resolve {
+                let resolve = reference(index, ImageLayout::ColorAttachmentOptimal)?;
+                description.resolve_attachments.push(Some(resolve));
+            }
+            if let Some(depth) = depth {
+                let layout = ImageLayout::DepthStencilAttachmentOptimal;
+                description.depth_stencil_attachment = Some(reference(depth, layout)?);
+            }
+
+            // Attachments a subpass skips keep their contents for the later ones
+            description.preserve_attachments = (0..key.attachments.len() as u32)
+                .filter(|&index| {
+                    Some(index as usize) != depth
+                        && ![&subpass.color, &subpass.input, &subpass.resolve]
+                            .iter()
+                            .any(|indices| indices.contains(&(index as usize)))
+                })
+                .collect();
+            subpasses.push(description);
+        }
+
+        let mut attachments = Vec::with_capacity(key.attachments.len());
+        for (attachment, layout) in key.attachments.iter().zip(layouts) {
+            let (initial_layout, final_layout) = layout.unwrap_or(match attachment.kind {
+                AttachmentKind::Depth => (
+                    ImageLayout::DepthStencilAttachmentOptimal,
+                    ImageLayout::DepthStencilAttachmentOptimal,
+                ),
+                _ => (
+                    ImageLayout::ColorAttachmentOptimal,
+                    ImageLayout::ColorAttachmentOptimal,
+                ),
+            });
+            let load_op = if attachment.clear {
+                LoadOp::Clear
+            } else if attachment.kind == AttachmentKind::Resolve {
+                LoadOp::DontCare
+            } else {
+                LoadOp::Load
+            };
+
+            attachments.push(AttachmentDescription {
+                format: Some(attachment.format),
+                samples: attachment.samples,
+                load_op,
+                store_op: StoreOp::Store,
+                stencil_load_op: load_op,
+                stencil_store_op: StoreOp::Store,
+                initial_layout: if attachment.discard {
+                    ImageLayout::Undefined
+                } else {
+                    initial_layout
+                },
+                final_layout,
+                ..Default::default()
+            });
+        }
+
+        // Each subpass waits for everything the previous one did, region by region
+        let access = AccessFlags::MEMORY_READ | AccessFlags::MEMORY_WRITE;
+        let dependencies = (1..subpasses.len() as u32)
+            .map(|subpass| SubpassDependency {
+                src_subpass: Some(subpass - 1),
+                dst_subpass: Some(subpass),
+                src_stages: PipelineStages::ALL_GRAPHICS,
+                dst_stages: PipelineStages::ALL_GRAPHICS,
+                src_access: access,
+                dst_access: access,
+                dependency_flags: DependencyFlags::BY_REGION,
+                ..Default::default()
+            })
+            .collect();
+
+        let render_pass = RenderPass::new(
+            device.clone(),
+            RenderPassCreateInfo {
+                attachments,
+                subpasses,
+                dependencies,
+                ..Default::default()
+            },
+        )?;
+
+        self.render_passes.insert(key, render_pass.clone());
+        Ok(render_pass)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(graph: &mut RenderGraph, name: &'static str) -> ResourceId {
+        graph.create_image(
+            name,
+            ImageDesc::new(Format::R8G8B8A8_UNORM, ImageSize::Absolute([4, 4])),
+        )
+    }
+
+    fn clear() -> Option<ClearValue> {
+        Some(ClearValue::Float([0.0; 4]))
+    }
+
+    /// Returns the scheduled pass indices and their barriers
+    fn schedule(graph: &RenderGraph) -> Result<(Vec<usize>, Vec<Vec<Barrier>>)> {
+        let (order_deps, live_deps) = graph.dependencies();
+        let live = graph.live_passes(&live_deps);
+        let order = graph.execution_order(&order_deps, &live)?;
+        let barriers = graph.barriers(&order);
+        Ok((order, barriers))
+    }
+
+    #[test]
+    fn reader_declared_before_its_writer_runs_after_it() {
+        let mut graph = RenderGraph::new();
+        let color = image(&mut graph, "color");
+        graph
+            .add_pass("read")
+            .read(color, Access::Sampled)
+            .side_effects()
+            .execute(|_| Ok(()));
+        graph.add_pass("write").color_attachment(color, clear()).execute(|_| Ok(()));
+
+        let (order, _) = schedule(&graph).unwrap();
+        assert_eq!(order, [1, 0]);
+    }
+
+    #[test]
+    fn independent_passes_keep_declaration_order() {
+        let mut graph = RenderGraph::new();
+        let output = image(&mut graph, "output");
+        let shadow = image(&mut graph, "shadow");
+        graph.mark_output(output);
+        graph.add_pass("compute").side_effects().execute(|_| Ok(()));
+        graph.add_pass("shadow").write(shadow, Access::DepthAttachment).execute(|_| Ok(()));
+        graph.add_pass("clear").color_attachment(output, clear()).execute(|_| Ok(()));
+        graph
+            .add_pass("lighting")
+            .read(shadow, Access::Sampled)
+            .write(output, Access::ColorAttachment)
+            .execute(|_| Ok(()));
+
+        let (order, _) = schedule(&graph).unwrap();
+        assert_eq!(order, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn dependency_cycle_is_an_error() {
+        let mut graph = RenderGraph::new();
+        let a = image(&mut graph, "a");
+        let b = image(&mut graph, "b");
+        graph
+            .add_pass("first")
+            .read(a, Access::Sampled)
+            .write(b, Access::StorageImage)
+            .side_effects()
+            .execute(|_| Ok(()));
+        graph
+            .add_pass("second")
+            .read(b, Access::Sampled)
+            .write(a, Access::StorageImage)
+            .side_effects()
+            .execute(|_| Ok(()));
+
+        assert!(schedule(&graph).is_err());
+    }
+
+    #[test]
+    fn passes_without_outputs_or_side_effects_are_culled() {
+        let mut graph = RenderGraph::new();
+        let output = image(&mut graph, "output");
+        let unused = image(&mut graph, "unused");
+        let readback = image(&mut graph, "readback");
+        graph.mark_output(output);
+        graph.add_pass("unused").color_attachment(unused, clear()).execute(|_| Ok(()));
+        graph.add_pass("scene").color_attachment(output, clear()).execute(|_| Ok(()));
+        graph
+            .add_pass("readback")
+            .read(readback, Access::TransferSource)
+            .side_effects()
+            .execute(|_| Ok(()));
+
+        let (order, _) = schedule(&graph).unwrap();
+        assert_eq!(order, [1, 2]);
+    }
+
+    #[test]
+    fn cleared_attachment_does_not_keep_the_previous_writer_alive() {
+        let mut graph = RenderGraph::new();
+        let output = image(&mut graph, "output");
+        graph.mark_output(output);
+        graph.add_pass("overwritten").color_attachment(output, clear()).execute(|_| Ok(()));
+        graph.add_pass("cleared").color_attachment(output, clear()).execute(|_| Ok(()));
+        let (order, _) = schedule(&graph).unwrap();
+        assert_eq!(order, [1]);
+
+        // Loading the old contents keeps the earlier pass
+        let mut graph = RenderGraph::new();
+        let output = image(&mut graph, "output");
+        graph.mark_output(output);
+        graph.add_pass("background").color_attachment(output, clear()).execute(|_| Ok(()));
+        graph.add_pass("overlay").color_attachment(output, None).execute(|_| Ok(()));
+        let (order, _) = schedule(&graph).unwrap();
+        assert_eq!(order, [0, 1]);
+    }
+
+    #[test]
+    fn write_then_sample_needs_a_layout_transition() {
+        let mut graph = RenderGraph::new();
+        let color = image(&mut graph, "color");
+        let output = image(&mut graph, "output");
+        graph.mark_output(output);
+        graph.add_pass("draw").color_attachment(color, clear()).execute(|_| Ok(()));
+        graph
+            .add_pass("post")
+            .read(color, Access::Sampled)
+            .color_attachment(output, clear())
+            .execute(|_| Ok(()));
+
+        let (order, barriers) = schedule(&graph).unwrap();
+        assert_eq!(order, [0, 1]);
+
+        // First use starts from an undefined layout
+        assert_eq!(barriers[0].len(), 1);
+        let first = barriers[0][0];
+        assert_eq!(first.resource, color);
+        assert!(first.src.is_none());
+        assert_eq!(first.old_layout, Some(ImageLayout::Undefined));
+        assert_eq!(first.new_layout, Some(ImageLayout::ColorAttachmentOptimal));
+
+        let sampled = barriers[1].iter().find(|barrier| barrier.resource == color).unwrap();
+        assert_eq!(sampled.src, Some(Access::ColorAttachment));
+        assert_eq!(sampled.dst, Access::Sampled);
+        assert_eq!(sampled.old_layout, Some(ImageLayout::ColorAttachmentOptimal));
+        assert_eq!(sampled.new_layout, Some(ImageLayout::ShaderReadOnlyOptimal));
+    }
+
+    #[test]
+    fn consecutive_reads_need_no_barrier() {
+        let mut graph = RenderGraph::new();
+        let color = image(&mut graph, "color");
+        graph.add_pass("draw").color_attachment(color, clear()).execute(|_| Ok(()));
+        for name in ["blur", "bloom"] {
+            graph
+                .add_pass(name)
+                .read(color, Access::Sampled)
+                .side_effects()
+                .execute(|_| Ok(()));
+        }
+
+        let (order, barriers) = schedule(&graph).unwrap();
+        assert_eq!(order, [0, 1, 2]);
+        assert_eq!(barriers[1].len(), 1);
+        assert!(barriers[2].is_empty());
+    }
+
+    #[test]
+    fn subpass_inputs_are_read_within_the_pass() {
+        let mut graph = RenderGraph::new();
+        let output = image(&mut graph, "output");
+        let color = image(&mut graph, "color");
+        let accumulation = image(&mut graph, "accumulation");
+        graph.mark_output(output);
+        graph
+            .add_pass("scene")
+            .color_attachment(color, clear())
+            .color_attachment(accumulation, clear())
+            .resolve_attachment(output)
+            .subpasses(vec![
+                SubpassLayout {
+                    color: vec![1],
+                    ..Default::default()
+                },
+                SubpassLayout {
+                    color: vec![0],
+                    input: vec![1],
+                    resolve: vec![2],
+                },
+            ])
+            .execute(|_| Ok(()));
+
+        let pass = &graph.passes[0];
+        let input = pass.uses.last().unwrap();
+        assert_eq!(input.resource, accumulation);
+        assert_eq!(input.access, Access::InputAttachment);
+        assert!(!input.write);
+
+        // One first-use barrier per attachment, none for the read after the write
+        let (order, barriers) = schedule(&graph).unwrap();
+        assert_eq!(order, [0]);
+        assert_eq!(barriers[0].len(), 3);
+        assert!(barriers[0]
+            .iter()
+            .all(|barrier| barrier.old_layout == Some(ImageLayout::Undefined)));
+    }
+
+    #[test]
+    fn read_then_write_in_one_pass_is_a_hazard() {
+        let mut graph = RenderGraph::new();
+        let data = image(&mut graph, "data");
+        graph
+            .add_pass("produce")
+            .write(data, Access::StorageImage)
+            .execute(|_| Ok(()));
+        graph
+            .add_pass("inspect")
+            .read(data, Access::StorageImage)
+            .side_effects()
+            .execute(|_| Ok(()));
+        graph
+            .add_pass("update")
+            .read(data, Access::StorageImage)
+            .write(data, Access::StorageImage)
+            .side_effects()
+            .execute(|_| Ok(()));
+
+        let (order, barriers) = schedule(&graph).unwrap();
+        assert_eq!(order, [0, 1, 2]);
+        // The update keeps the layout, but overwrites what the inspection reads
+        assert_eq!(barriers[1].len(), 1);
+        assert_eq!(barriers[2].len(), 1);
+        assert_eq!(barriers[2][0].src, Some(Access::StorageImage));
+    }
+}
@@ -0,0 +1,441 @@
+use nalgebra::{Matrix4, Point3, Vector3};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
+use crate::engine::bounds::{CullStats, Frustum};
+use crate::engine::buffer::InstanceData;
+use crate::engine::camera::Camera;
+use crate::engine::gpu_culling::GpuScene;
+use crate::engine::mesh::Mesh;
+use crate::engine::pipeline::{BlendPreset, VertexLayout};
+
+/// How transparent draws of a material are composited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransparencyMode {
+    /// Sorted back-to-front per draw and blended with the material's preset
+    #[default]
+    Sorted,
+    /// Weighted blended order-independent transparency, correct for intersecting and
+    /// self-overlapping meshes at the cost of approximate ordering; always alpha blends
+    WeightedOit,
+}
+
+/// Surface settings that decide which pipeline and queue a draw goes through
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Material {
+    /// Linear RGBA base color; alpha is only used by blending presets
+    pub color: [f32; 4],
+    pub blend: BlendPreset,
+    /// Compositing of transparent draws; ignored for opaque materials
+    pub transparency: TransparencyMode,
+}
+
+impl Material {
+    /// An opaque material of the given color
+    pub fn opaque(color: [f32; 3]) -> Self {
+        Self {
+            color: [color[0], color[1], color[2], 1.0],
+            blend: BlendPreset::Opaque,
+            transparency: TransparencyMode::Sorted,
+        }
+    }
+
+    /// A sorted transparent material blended with the given preset
+    pub fn blended(color: [f32; 4], blend: BlendPreset) -> Self {
+        Self {
+            color,
+            blend,
+            transparency: TransparencyMode::Sorted,
+        }
+    }
+
+    /// An alpha-blended material drawn with order-independent transparency
+    pub fn order_independent(color: [f32; 4]) -> Self {
+        Self {
+            color,
+            blend: BlendPreset::Alpha,
+            transparency: TransparencyMode::WeightedOit,
+        }
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self::opaque([0.7, 0.2, 0.2])
+    }
+}
+
+impl Material {
+    /// Returns whether draws with this material belong in the transparent queue
+    pub fn is_transparent(&self) -> bool {
+        self.blend.is_transparent()
+    }
+
+    /// Returns whether draws with this material go through the OIT path
+    pub fn is_order_independent(&self) -> bool {
+        self.is_transparent() && self.transparency == TransparencyMode::WeightedOit
+    }
+}
+
+/// Most directional lights the shaders read; further lights in a queue are ignored
+pub const MAX_LIGHTS: usize = 4;
+
+/// A directional light, like the sun, shining on the whole scene
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    /// Direction the light travels in world space
+    pub direction: Vector3<f32>,
+    /// Linear RGB color
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            direction: Vector3::new(-1.0, -1.0, -1.0).normalize(),
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+        }
+    }
+}
+
+/// A mesh drawn with a model matrix and material
+#[derive(Clone)]
+pub struct DrawItem {
+    pub mesh: Arc<Mesh>,
+    pub model: Matrix4<f32>,
+    pub material: Material,
+}
+
+/// Copies of one mesh drawn with one material in a single instanced draw
+#[derive(Clone)]
+pub struct InstanceBatch {
+    pub mesh: Arc<Mesh>,
+    pub material: Material,
+    pub instances: Vec<InstanceData>,
+}
+
+/// Fewest opaque draws sharing a mesh and material that `batch_instances` merges
+pub const MIN_INSTANCE_BATCH: usize = 2;
+
+/// What opaque draws have to share to be merged into one instanced batch
+type BatchKey = (*const Mesh, [u32; 4], BlendPreset);
+
+fn batch_key(mesh: *const Mesh, material: &Material) -> BatchKey {
+    (mesh, material.color.map(f32::to_bits), material.blend)
+}
+
+/// Sorts items from farthest to nearest to `eye`, keeping the order of equally distant items
+fn sort_back_to_front<T>(items: &mut [T], eye: Point3<f32>, center: impl Fn(&T) -> Point3<f32>) {
+    let distance = |item: &T| (center(item) - eye).norm_squared();
+    items.sort_by(|a, b| {
+        distance(b)
+            .partial_cmp(&distance(a))
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+/// Groups items with equal keys, keeping groups and their items in first-seen order
+fn group_in_order<T, K: Eq + Hash>(
+    items: impl IntoIterator<Item = T>,
+    key: impl Fn(&T) -> K,
+) -> Vec<Vec<T>> {
+    let mut groups: Vec<Vec<T>> = Vec::new();
+    let mut index_of: HashMap<K, usize> = HashMap::new();
+    for item in items {
+        let index = *index_of.entry(key(&item)).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[index].push(item);
+    }
+    groups
+}
+
+/// The draws for one frame, split into opaque, order-independent and sorted transparent queues
+///
+/// Opaque draws are recorded first with depth writes. Order-independent draws are accumulated
+/// and composited next, then sorted transparent draws follow back-to-front by distance from
+/// the camera. Neither kind of transparent draw writes depth. Instanced batches are drawn
+/// with the opaque draws, followed by GPU scenes culled by compute shaders.
+pub struct RenderQueue {
+    pub camera: Camera,
+    lights: Vec<Light>,
+    opaque: Vec<DrawItem>,
+    instanced: Vec<InstanceBatch>,
+    gpu_scenes: Vec<Arc<GpuScene>>,
+    order_independent: Vec<DrawItem>,
+    transparent: Vec<DrawItem>,
+}
+
+impl RenderQueue {
+    /// Creates an empty queue viewed through `camera`
+    pub fn new(camera: Camera) -> Self {
+        Self {
+            camera,
+            lights: Vec::new(),
+            opaque: Vec::new(),
+            instanced: Vec::new(),
+            gpu_scenes: Vec::new(),
+            order_independent: Vec::new(),
+            transparent: Vec::new(),
+        }
+    }
+
+    /// Adds a draw to the queue matching its material
+    pub fn push(&mut self, item: DrawItem) {
+        if item.material.is_order_independent() {
+            self.order_independent.push(item);
+        } else if item.material.is_transparent() {
+            self.transparent.push(item);
+        } else {
+            self.opaque.push(item);
+        }
+    }
+
+    /// Adds copies of a mesh with per-instance transforms and colors
+    ///
+    /// Opaque batches become one instanced draw. Transparent ones are split into single draws,
+    /// since each copy has to be sorted or composited on its own.
+    pub fn push_instanced(&mut self, batch: InstanceBatch) {
+        if batch.material.is_transparent() {
+            for instance in &batch.instances {
+                let tint = instance.instance_color;
+                let mut material = batch.material;
+                for (channel, factor) in material.color.iter_mut().zip(tint) {
+                    *channel *= factor;
+                }
+                self.push(DrawItem {
+                    mesh: batch.mesh.clone(),
+                    model: instance.instance_model.into(),
+                    material,
+                });
+            }
+        } else if !batch.instances.is_empty() {
+            self.instanced.push(batch);
+        }
+    }
+
+    /// Adds a scene culled and drawn by the GPU, in the main view only
+    ///
+    /// CPU culling and batching leave GPU scenes alone; they are culled by a compute pass and
+    /// drawn with indirect commands. Since their objects live on the GPU, they stay in the
+    /// queue across frames until `clear_gpu_scenes`.
+    pub fn push_gpu_scene(&mut self, scene: Arc<GpuScene>) {
+        self.gpu_scenes.push(scene);
+    }
+
+    /// Merges opaque draws that share a mesh and material into instanced batches
+    ///
+    /// Draws are grouped by mesh identity and exact material; groups smaller than
+    /// `MIN_INSTANCE_BATCH` stay single draws. Groups keep the order their first draw was
+    /// queued in, so the recorded draws do not change between frames. Called by the
+    /// renderer before every frame.
+    pub fn batch_instances(&mut self) {
+        let groups = group_in_order(self.opaque.drain(..), |item| {
+            batch_key(Arc::as_ptr(&item.mesh), &item.material)
+        });
+
+        for items in groups {
+            if items.len() < MIN_INSTANCE_BATCH {
+                self.opaque.extend(items);
+                continue;
+            }
+            self.instanced.push(InstanceBatch {
+                mesh: items[0].mesh.clone(),
+                material: items[0].material,
+                instances: items.iter().map(|item| InstanceData::new(item.model)).collect(),
+            });
+        }
+    }
+
+    /// Removes draws and instances whose world-space bounds are outside the frustum
+    ///
+    /// Each draw is tested with its mesh's bounding sphere first and its box only if the
+    /// sphere passes. Transparent draws are culled too, before they are sorted.
+    pub fn cull(&mut self, frustum: &Frustum) -> CullStats {
+        let mut stats = CullStats::default();
+        let mut is_visible = |mesh: &Mesh, model: &Matrix4<f32>| {
+            stats.tested += 1;
+            let visible = frustum.intersects_sphere(&mesh.bounding_sphere().transform(model))
+                && frustum.intersects_aabb(&mesh.aabb().transform(model));
+            if !visible {
+                stats.culled += 1;
+            }
+            visible
+        };
+
+        for items in [&mut self.opaque, &mut self.order_independent, &mut self.transparent] {
+            items.retain(|item| is_visible(&item.mesh, &item.model));
+        }
+        for batch in &mut self.instanced {
+            let mesh = &batch.mesh;
+            batch
+                .instances
+                .retain(|instance| is_visible(mesh, &instance.instance_model.into()));
+        }
+        self.instanced.retain(|batch| !batch.instances.is_empty());
+
+        stats
+    }
+
+    /// Adds a light to the frame
+    pub fn push_light(&mut self, light: Light) {
+        self.lights.push(light);
+    }
+
+    /// Returns the lights; a default light is used when there are none
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    /// Returns the opaque draws
+    pub fn opaque(&self) -> &[DrawItem] {
+        &self.opaque
+    }
+
+    /// Returns the instanced batches
+    pub fn instanced(&self) -> &[InstanceBatch] {
+        &self.instanced
+    }
+
+    /// Returns the scenes culled and drawn by the GPU
+    pub fn gpu_scenes(&self) -> &[Arc<GpuScene>] {
+        &self.gpu_scenes
+    }
+
+    /// Returns the distinct vertex layouts of queued meshes that need pipeline variants
+    ///
+    /// Meshes with the standard layout are skipped; GPU scenes are always included.
+    pub fn vertex_layouts(&self) -> Vec<VertexLayout> {
+        let meshes = self
+            .opaque
+            .iter()
+            .chain(&self.order_independent)
+            .chain(&self.transparent)
+            .map(|item| &item.mesh)
+            .chain(self.instanced.iter().map(|batch| &batch.mesh))
+            .filter(|mesh| !mesh.has_standard_layout())
+            .map(|mesh| mesh.vertex_layout());
+        let scenes = self.gpu_scenes.iter().map(|scene| scene.vertex_layout());
+
+        let mut layouts: Vec<VertexLayout> = Vec::new();
+        for layout in meshes.chain(scenes) {
+            if !layouts.contains(layout) {
+                layouts.push(layout.clone());
+            }
+        }
+        layouts
+    }
+
+    /// Returns the draws using order-independent transparency
+    pub fn order_independent(&self) -> &[DrawItem] {
+        &self.order_independent
+    }
+
+    /// Moves order-independent draws into the sorted queue, for devices without OIT support
+    pub fn sort_order_independent(&mut self) {
+        self.transparent.append(&mut self.order_independent);
+    }
+
+    /// Returns the sorted transparent draws, in back-to-front order after `sort_transparent`
+    pub fn transparent(&self) -> &[DrawItem] {
+        &self.transparent
+    }
+
+    /// Sorts transparent draws from farthest to nearest to the camera
+    pub fn sort_transparent(&mut self) {
+        sort_back_to_front(&mut self.transparent, self.camera.position(), |item| {
+            item.model.transform_point(&item.mesh.center())
+        });
+    }
+
+    /// Returns the total number of draws, counting each instanced batch and GPU scene once
+    pub fn len(&self) -> usize {
+        self.opaque.len()
+            + self.instanced.len()
+            + self.gpu_scenes.len()
+            + self.order_independent.len()
+            + self.transparent.len()
+    }
+
+    /// Returns whether the queue has no draws
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes every draw and light, keeping the camera and GPU scenes
+    pub fn clear(&mut self) {
+        self.lights.clear();
+        self.clear_draws();
+    }
+
+    /// Removes every GPU scene
+    pub fn clear_gpu_scenes(&mut self) {
+        self.gpu_scenes.clear();
+    }
+
+    /// Removes every draw, keeping the camera and lights
+    pub fn clear_draws(&mut self) {
+        self.opaque.clear();
+        self.instanced.clear();
+        self.order_independent.clear();
+        self.transparent.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batching_merges_only_identical_mesh_and_material_pairs() {
+        // Stand-ins for two meshes; only their identity matters
+        let cube = std::ptr::null::<Mesh>();
+        let sphere = std::ptr::dangling::<Mesh>();
+        let red = Material::opaque([1.0, 0.0, 0.0]);
+        let blue = Material::opaque([0.0, 0.0, 1.0]);
+        let red_additive = Material {
+            blend: BlendPreset::Additive,
+            ..red
+        };
+        let draws = [
+            (0, cube, red),
+            (1, sphere, red),
+            (2, cube, blue),
+            (3, cube, red),
+            (4, cube, red_additive),
+            (5, sphere, red),
+            (6, cube, red),
+        ];
+
+        let groups = group_in_order(draws, |(_, mesh, material)| batch_key(*mesh, material));
+        let groups: Vec<Vec<usize>> = groups
+            .iter()
+            .map(|group| group.iter().map(|(draw, _, _)| *draw).collect())
+            .collect();
+        assert_eq!(groups, [vec![0, 3, 6], vec![1, 5], vec![2], vec![4]]);
+    }
+
+    #[test]
+    fn transparent_draws_sort_back_to_front() {
+        let eye = Point3::new(0.0, 0.0, 5.0);
+        let mut draws = vec![
+            (0, Point3::new(0.0, 0.0, 4.0)),
+            (1, Point3::new(0.0, 0.0, -10.0)),
+            (2, Point3::new(3.0, 0.0, 5.0)),
+            (3, Point3::new(0.0, 0.0, 0.0)),
+            (4, Point3::new(0.0, 3.0, 5.0)),
+        ];
+
+        sort_back_to_front(&mut draws, eye, |(_, center)| *center);
+        let order: Vec<usize> = draws.iter().map(|(draw, _)| *draw).collect();
+        // 2 and 4 are equally far away, so they keep their submission order
+        assert_eq!(order, [1, 3, 2, 4, 0]);
+    }
+}
@@ -0,0 +1,311 @@
+use anyhow::{anyhow, Result};
+use log::info;
+use std::sync::Arc;
+use vulkano::descriptor_set::WriteDescriptorSet;
+use vulkano::device::{Device, DeviceOwned};
+use vulkano::format::{ClearValue, Format};
+use vulkano::image::view::{ImageView, ImageViewAbstract};
+use vulkano::image::{AttachmentImage, ImageLayout, ImageUsage};
+use vulkano::memory::allocator::StandardMemoryAllocator;
+use vulkano::pipeline::graphics::viewport::{Scissor, Viewport};
+use vulkano::render_pass::{
+    AttachmentDescription, AttachmentReference, Framebuffer, FramebufferCreateInfo, LoadOp,
+    RenderPass, RenderPassCreateInfo, StoreOp, Subpass, SubpassDescription,
+};
+use vulkano::sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo};
+
+use crate::engine::pipeline::full_viewport;
+use crate::engine::render_graph::ImageSize;
+
+/// Handle to a render target owned by the renderer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct RenderTargetId(pub(crate) usize);
+
+/// Description of an offscreen render target
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderTargetDesc {
+    /// Fixed size, or a fraction of the swapchain that follows window resizes
+    pub size: ImageSize,
+    /// Format of the color image, or `None` for a depth-only target
+    pub color_format: Option<Format>,
+    /// Format of the depth image, or `None` to render without depth
+    pub depth_format: Option<Format>,
+    pub clear_color: [f32; 4],
+}
+
+impl RenderTargetDesc {
+    /// A color target with a depth buffer, e.g. for mirrors and in-world screens
+    pub fn color_depth(size: ImageSize, color_format: Format) -> Self {
+        Self {
+            size,
+            color_format: Some(color_format),
+            depth_format: Some(Format::D16_UNORM),
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+
+    /// A depth-only target, e.g. for shadow maps
+    pub fn depth_only(size: ImageSize, depth_format: Format) -> Self {
+        Self {
+            size,
+            color_format: None,
+            depth_format: Some(depth_format),
+            clear_color: [0.0; 4],
+        }
+    }
+}
+
+/// Offscreen color and depth images that can be rendered into and sampled afterwards
+///
+/// The render pass is created once and kept across resizes, so pipelines built for it stay
+/// valid; only the images and framebuffer are recreated.
+pub struct RenderTarget {
+    desc: RenderTargetDesc,
+    render_pass: Arc<RenderPass>,
+    color: Option<Arc<ImageView<AttachmentImage>>>,
+    depth: Option<Arc<ImageView<AttachmentImage>>>,
+    framebuffer: Arc<Framebuffer>,
+    sampler: Arc<Sampler>,
+    extent: [u32; 2],
+}
+
+impl RenderTarget {
+    /// Creates a target, resolving relative sizes against the swapchain extent
+    pub fn new(
+        allocator: &StandardMemoryAllocator,
+        desc: RenderTargetDesc,
+        swapchain_extent: [u32; 2],
+    ) -> Result<Self> {
+        if desc.color_format.is_none() && desc.depth_format.is_none() {
+            return Err(anyhow!("Render target needs a color or depth format"));
+        }
+
+        let device = allocator.device().clone();
+        let render_pass = create_target_render_pass(device.clone(), &desc)?;
+        let sampler = Sampler::new(
+            device,
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )?;
+
+        let extent = desc.size.resolve(swapchain_extent);
+        let (color, depth, framebuffer) = create_images(allocator, &desc, &render_pass, extent)?;
+
+        info!("Render target created at {}x{}", extent[0], extent[1]);
+        Ok(Self {
+            desc,
+            render_pass,
+            color,
+            depth,
+            framebuffer,
+            sampler,
+            extent,
+        })
+    }
+
+    /// Recreates the images if the size they resolve to changed; returns whether it did
+    pub fn resize(
+        &mut self,
+        allocator: &StandardMemoryAllocator,
+        swapchain_extent: [u32; 2],
+    ) -> Result<bool> {
+        let extent = self.desc.size.resolve(swapchain_extent);
+        if extent == self.extent {
+            return Ok(false);
+        }
+
+        let (color, depth, framebuffer) =
+            create_images(allocator, &self.desc, &self.render_pass, extent)?;
+        self.color = color;
+        self.depth = depth;
+        self.framebuffer = framebuffer;
+        self.extent = extent;
+
+        info!("Render target resized to {}x{}", extent[0], extent[1]);
+        Ok(true)
+    }
+
+    /// Returns the description the target was created with
+    pub fn desc(&self) -> &RenderTargetDesc {
+        &self.desc
+    }
+
+    /// Returns the current size in pixels
+    pub fn extent(&self) -> [u32; 2] {
+        self.extent
+    }
+
+    /// Returns the render pass to build pipelines against
+    pub fn render_pass(&self) -> &Arc<RenderPass> {
+        &self.render_pass
+    }
+
+    /// Returns the single subpass of the render pass
+    pub fn subpass(&self) -> Subpass {
+        Subpass::from(self.render_pass.clone(), 0).expect("render target has one subpass")
+    }
+
+    /// Returns the framebuffer to begin the render pass with
+    pub fn framebuffer(&self) -> &Arc<Framebuffer> {
+        &self.framebuffer
+    }
+
+    /// Returns the color image view, if the target has one
+    pub fn color(&self) -> Option<&Arc<ImageView<AttachmentImage>>> {
+        self.color.as_ref()
+    }
+
+    /// Returns the depth image view, if the target has one
+    pub fn depth(&self) -> Option<&Arc<ImageView<AttachmentImage>>> {
+        self.depth.as_ref()
+    }
+
+    /// Returns the clamp-to-edge linear sampler used when the target is sampled
+    pub fn sampler(&self) -> &Arc<Sampler> {
+        &self.sampler
+    }
+
+    /// Returns a descriptor write sampling the color image, or the depth image for
+    /// depth-only targets
+    pub fn sampled_descriptor(&self, binding: u32) -> WriteDescriptorSet {
+        let view = self
+            .color
+            .clone()
+            .or_else(|| self.depth.clone())
+            .expect("render target has a color or depth image");
+        WriteDescriptorSet::image_view_sampler(binding, view, self.sampler.clone())
+    }
+
+    /// Returns clear values for every attachment, in attachment order
+    pub fn clear_values(&self) -> Vec<Option<ClearValue>> {
+        let mut values = Vec::with_capacity(2);
+        if self.color.is_some() {
+            values.push(Some(self.desc.clear_color.into()));
+        }
+        if self.depth.is_some() {
+            values.push(Some(1.0.into()));
+        }
+        values
+    }
+
+    /// Returns a viewport and scissor covering the whole target
+    pub fn viewport(&self) -> (Viewport, Scissor) {
+        full_viewport(self.extent)
+    }
+}
+
+/// Creates a single-subpass render pass clearing and storing the target's attachments
+fn create_target_render_pass(
+    device: Arc<Device>,
+    desc: &RenderTargetDesc,
+) -> Result<Arc<RenderPass>> {
+    let mut attachments = Vec::with_capacity(2);
+    let mut color_attachments = Vec::new();
+    let mut depth_stencil_attachment = None;
+
+    if let Some(format) = desc.color_format {
+        color_attachments.push(Some(AttachmentReference {
+            attachment: attachments.len() as u32,
+            layout: ImageLayout::ColorAttachmentOptimal,
+            ..Default::default()
+        }));
+        attachments.push(AttachmentDescription {
+            format: Some(format),
+            load_op: LoadOp::Clear,
+            store_op: StoreOp::Store,
+            initial_layout: ImageLayout::ColorAttachmentOptimal,
+            final_layout: ImageLayout::ColorAttachmentOptimal,
+            ..Default::default()
+        });
+    }
+
+    if let Some(format) = desc.depth_format {
+        depth_stencil_attachment = Some(AttachmentReference {
+            attachment: attachments.len() as u32,
+            layout: ImageLayout::DepthStencilAttachmentOptimal,
+            ..Default::default()
+        });
+        attachments.push(AttachmentDescription {
+            format: Some(format),
+            load_op: LoadOp::Clear,
+            store_op: StoreOp::Store,
+            stencil_load_op: LoadOp::Clear,
+            stencil_store_op: StoreOp::Store,
+            initial_layout: ImageLayout::DepthStencilAttachmentOptimal,
+            final_layout: ImageLayout::DepthStencilAttachmentOptimal,
+            ..Default::default()
+        });
+    }
+
+    let render_pass = RenderPass::new(
+        device,
+        RenderPassCreateInfo {
+            attachments,
+            subpasses: vec![SubpassDescription {
+                color_attachments,
+                depth_stencil_attachment,
+                ..Default::default()
+            }],
+            ..Default::default()
+        },
+    )?;
+
+    Ok(render_pass)
+}
+
+type TargetImages = (
+    Option<Arc<ImageView<AttachmentImage>>>,
+    Option<Arc<ImageView<AttachmentImage>>>,
+    Arc<Framebuffer>,
+);
+
+/// Allocates sampleable images of the given extent and a framebuffer around them
+fn create_images(
+    allocator: &StandardMemoryAllocator,
+    desc: &RenderTargetDesc,
+    render_pass: &Arc<RenderPass>,
+    extent: [u32; 2],
+) -> Result<TargetImages> {
+    let color = desc
+        .color_format
+        .map(|format| {
+            let usage = ImageUsage::COLOR_ATTACHMENT | ImageUsage::SAMPLED | ImageUsage::TRANSFER_SRC;
+            Ok::<_, anyhow::Error>(ImageView::new_default(AttachmentImage::with_usage(
+                allocator, extent, format, usage,
+            )?)?)
+        })
+        .transpose()?;
+    let depth = desc
+        .depth_format
+        .map(|format| {
+            let usage = ImageUsage::DEPTH_STENCIL_ATTACHMENT
+                | ImageUsage::SAMPLED
+                | ImageUsage::TRANSFER_SRC;
+            Ok::<_, anyhow::Error>(ImageView::new_default(AttachmentImage::with_usage(
+                allocator, extent, format, usage,
+            )?)?)
+        })
+        .transpose()?;
+
+    let mut views: Vec<Arc<dyn ImageViewAbstract>> = Vec::with_capacity(2);
+    if let Some(color) = &color {
+        views.push(color.clone());
+    }
+    if let Some(depth) = &depth {
+        views.push(depth.clone());
+    }
+
+    let framebuffer = Framebuffer::new(
+        render_pass.clone(),
+        FramebufferCreateInfo {
+            attachments: views,
+            ..Default::default()
+        },
+    )?;
+
+    Ok((color, depth, framebuffer))
+}
@@ -1,33 +1,84 @@
 use anyhow::{anyhow, Result};
-use log::info;
+use log::{debug, info, log_enabled, warn, Level};
+use nalgebra::Matrix4;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
+use vulkano::buffer::allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo};
+use vulkano::buffer::BufferUsage;
 use vulkano::command_buffer::{
     AutoCommandBufferBuilder, CommandBufferUsage, RenderPassBeginInfo, SubpassContents,
 };
-use vulkano::device::{Device, Queue};
+use vulkano::device::{Device, DeviceOwned, Queue};
 use vulkano::format::Format;
-use vulkano::image::view::ImageView;
-use vulkano::image::SwapchainImage;
+use vulkano::image::view::{ImageView, ImageViewAbstract};
+use vulkano::image::{ImageAccess, ImageUsage, SampleCount, SwapchainImage};
 use vulkano::instance::Instance;
-use vulkano::memory::allocator::StandardMemoryAllocator;
-use vulkano::command_buffer::allocator::StandardCommandBufferAllocator;
-use vulkano::pipeline::graphics::viewport::Viewport;
-use vulkano::pipeline::{GraphicsPipeline, PipelineLayout};
-use vulkano::pipeline::layout::PipelineLayoutCreateInfo;
-use vulkano::pipeline::graphics::vertex_input::VertexInputState;
-use vulkano::pipeline::graphics::input_assembly::InputAssemblyState;
-use vulkano::pipeline::graphics::viewport::ViewportState;
-use vulkano::render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass};
+use vulkano::memory::allocator::{MemoryUsage, StandardMemoryAllocator};
+use vulkano::command_buffer::allocator::{CommandBufferAllocator, StandardCommandBufferAllocator};
+use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
+use vulkano::descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet};
+use vulkano::pipeline::graphics::viewport::{Scissor, Viewport};
+use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint};
+use vulkano::render_pass::RenderPass;
+use vulkano::shader::ShaderModule;
 use vulkano::swapchain::{
     acquire_next_image, AcquireError, Surface, Swapchain, SwapchainPresentInfo,
 };
 use vulkano::sync::{self, FlushError, GpuFuture};
-use vulkano::buffer::Subbuffer;
-use nalgebra::Matrix4;
 
-use crate::engine::buffer::{create_uniform_buffer, UniformBufferObject};
+use crate::engine::bounds::CullStats;
+use crate::engine::buffer::{InstanceData, LightBlock, PushConstants, UniformBufferObject};
+use crate::engine::capture::CaptureBuffer;
+use crate::engine::components::extract_render_list;
+use crate::engine::compute::ComputeDispatch;
+use crate::engine::constants::push_constants;
+use crate::engine::ecs::World;
+use crate::engine::gpu_culling::{CulledScene, GpuCuller, GpuScene, HiZPyramid};
+use crate::engine::pipeline::{
+    attachment_sample_counts, full_viewport, next_sample_count, scene_attachments,
+    scene_subpasses, supported_sample_count, BlendPreset, DepthDesc, PipelineDesc,
+    RenderPassDesc, VertexLayout, DEPTH_FORMAT, OIT_ACCUMULATION_FORMAT, OIT_REVEALAGE_FORMAT,
+    TRANSPARENT_SUBPASS,
+};
+use crate::engine::pipeline_cache::{
+    GraphicsPipelineCache, MeshPipelines, PersistentPipelineCache, DEFAULT_CACHE_DIR,
+};
 use crate::engine::instance::{create_logical_device, select_physical_device};
+use crate::engine::render_graph::{Access, ImageDesc, ImageSize, RenderGraph, RenderGraphCache};
+use crate::engine::render_queue::{DrawItem, InstanceBatch, Light, RenderQueue, MAX_LIGHTS};
+use crate::engine::recording::{write_captured_frame, Recorder};
+use crate::engine::scene::SceneGraph;
+use crate::engine::render_target::{RenderTarget, RenderTargetDesc, RenderTargetId};
 use crate::engine::swapchain::{create_swapchain, recreate_swapchain};
+use crate::shaders::oit_composite_ms::fs::CompositeConstants;
+
+/// Sample count requested for MSAA at startup, lowered to what the device supports
+pub const DEFAULT_MSAA_SAMPLES: u32 = 4;
+
+/// Pipelines and bindings of the weighted blended OIT path
+struct OitPipelines {
+    accumulation: MeshPipelines,
+    composite: Arc<GraphicsPipeline>,
+}
+
+/// Descriptor sets shared by every draw of one view
+struct FrameSets {
+    /// Set 0: camera matrices
+    camera: Arc<PersistentDescriptorSet>,
+    /// Set 1: directional lights
+    lights: Arc<PersistentDescriptorSet>,
+}
+
+/// An offscreen view queued for the next frame, with its pipelines resolved
+struct TargetRender {
+    target: RenderTargetId,
+    queue: RenderQueue,
+    frame_sets: FrameSets,
+    opaque: MeshPipelines,
+    instanced: MeshPipelines,
+    transparent: Vec<Arc<GraphicsPipeline>>,
+}
 
 /// Renderer handles all drawing operations
 pub struct Renderer {
@@ -35,15 +86,37 @@ pub struct Renderer {
     queue: Arc<Queue>,
     surface: Arc<Surface>,
     swapchain: Arc<Swapchain>,
-    render_pass: Arc<RenderPass>,
-    pipeline: Arc<GraphicsPipeline>,
-    framebuffers: Vec<Arc<Framebuffer>>,
+    pipeline_cache: GraphicsPipelineCache,
+    persistent_cache: PersistentPipelineCache,
+    pipeline_desc: PipelineDesc,
+    pipeline: MeshPipelines,
+    instanced_shader: Arc<ShaderModule>,
+    instanced_pipeline: MeshPipelines,
+    wireframe_pipeline: Option<MeshPipelines>,
+    instanced_wireframe_pipeline: Option<MeshPipelines>,
+    wireframe_overlay: bool,
+    oit: Option<OitPipelines>,
+    sample_count: SampleCount,
     swapchain_images: Vec<Arc<SwapchainImage>>,
     memory_allocator: Arc<StandardMemoryAllocator>,
     command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
     viewport: Viewport,
+    scissor: Scissor,
     previous_frame_end: Option<Box<dyn GpuFuture>>,
-    uniform_buffer: Subbuffer<UniformBufferObject>,
+    uniform_buffer_allocator: SubbufferAllocator,
+    instance_buffer_allocator: SubbufferAllocator,
+    pending_compute: Vec<ComputeDispatch>,
+    render_graph_cache: RenderGraphCache,
+    render_graph_dump: String,
+    render_targets: Vec<Option<RenderTarget>>,
+    pending_target_renders: Vec<(RenderTargetId, RenderQueue)>,
+    pending_capture: Option<PathBuf>,
+    recorder: Option<Recorder>,
+    frustum_culling: bool,
+    cull_stats: CullStats,
+    gpu_culler: GpuCuller,
+    occlusion: Option<(RenderTargetId, HiZPyramid)>,
 }
 
 impl Renderer {
@@ -65,8 +138,9 @@ impl Renderer {
             Default::default(),
         ));
 
-        // Create a render pass
-        let render_pass = create_render_pass(device.clone())?;
+        // Create a descriptor set allocator
+        let descriptor_set_allocator =
+            Arc::new(StandardDescriptorSetAllocator::new(device.clone()));
 
         // Create a swapchain, swapchain images, etc.
         let swapchain_bundle = create_swapchain(device.clone(), surface.clone())?;
@@ -74,27 +148,70 @@ impl Renderer {
         // Extract swapchain and images from the bundle
         let swapchain = swapchain_bundle.swapchain;
         let swapchain_images = swapchain_bundle.images;
+
+        // Get the render pass the frame graph draws the scene in, matching the swapchain format
+        // and a supported MSAA sample count
+        let sample_count = supported_sample_count(&device, DEFAULT_MSAA_SAMPLES);
+        let mut render_graph_cache = RenderGraphCache::new();
+        let render_pass = scene_render_pass(
+            &mut render_graph_cache,
+            &device,
+            swapchain.image_format(),
+            sample_count,
+        )?;
         
-        // Create framebuffers from swapchain images
-        let framebuffers = create_framebuffers(&swapchain_images, &render_pass)?;
-        
-        // Create viewport
-        let dimensions = swapchain.image_extent();
-        let viewport = Viewport {
-            origin: [0.0, 0.0],
-            dimensions: [dimensions[0] as f32, dimensions[1] as f32],
-            depth_range: 0.0..1.0,
-        };
+        // Create viewport and scissor, which pipelines take as dynamic state
+        let (viewport, scissor) = full_viewport(swapchain.image_extent());
 
-        // Create a graphics pipeline
-        let pipeline = create_pipeline(
-            device.clone(),
+        // Create the scene pipeline through the cache
+        use crate::shaders::fragment::fs;
+        use crate::shaders::vertex::vs;
+
+        let persistent_cache = PersistentPipelineCache::load(device.clone(), DEFAULT_CACHE_DIR)?;
+        let mut pipeline_cache =
+            GraphicsPipelineCache::new(device.clone(), Some(persistent_cache.cache().clone()));
+        let pipeline_desc = PipelineDesc::new(
+            vs::load(device.clone())?,
+            fs::load(device.clone())?,
             render_pass.clone(),
-            viewport.clone(),
-        )?;
+        );
+        let pipeline = MeshPipelines::new(&mut pipeline_cache, pipeline_desc.clone())?;
+
+        // Create the instanced variant drawing batches of meshes sharing a material
+        let instanced_shader = crate::shaders::instanced::load(device.clone())?;
+        let instanced_desc = pipeline_desc.instanced(instanced_shader.clone());
+        let instanced_pipeline = MeshPipelines::new(&mut pipeline_cache, instanced_desc)?;
+
+        // Create the weighted blended OIT pipelines when the device can blend them
+        let oit = create_oit_pipelines(&mut pipeline_cache, &pipeline_desc, sample_count)?;
 
-        // Create a uniform buffer
-        let uniform_buffer = create_uniform_buffer(&memory_allocator)?;
+        // Create an allocator for the per-frame camera uniforms
+        let uniform_buffer_allocator = SubbufferAllocator::new(
+            memory_allocator.clone(),
+            SubbufferAllocatorCreateInfo {
+                buffer_usage: BufferUsage::UNIFORM_BUFFER,
+                memory_usage: MemoryUsage::Upload,
+                ..Default::default()
+            },
+        );
+
+        // Create an allocator for the per-frame instance data of batched draws
+        let instance_buffer_allocator = SubbufferAllocator::new(
+            memory_allocator.clone(),
+            SubbufferAllocatorCreateInfo {
+                buffer_usage: BufferUsage::VERTEX_BUFFER,
+                memory_usage: MemoryUsage::Upload,
+                ..Default::default()
+            },
+        );
+
+        // Create the compute pipelines culling GPU scenes
+        let gpu_culler = GpuCuller::new(
+            device.clone(),
+            memory_allocator.clone(),
+            descriptor_set_allocator.clone(),
+            Some(persistent_cache.cache().clone()),
+        )?;
 
         // Create a fence for synchronization
         let previous_frame_end = Some(sync::now(device.clone()).boxed());
@@ -106,20 +223,376 @@ impl Renderer {
             queue,
             surface,
             swapchain,
-            render_pass,
+            pipeline_cache,
+            persistent_cache,
+            pipeline_desc,
             pipeline,
-            framebuffers,
+            instanced_shader,
+            instanced_pipeline,
+            wireframe_pipeline: None,
+            instanced_wireframe_pipeline: None,
+            wireframe_overlay: false,
+            oit,
+            sample_count,
             swapchain_images,
             memory_allocator,
             command_buffer_allocator,
+            descriptor_set_allocator,
             viewport,
+            scissor,
             previous_frame_end,
-            uniform_buffer,
+            uniform_buffer_allocator,
+            instance_buffer_allocator,
+            pending_compute: Vec::new(),
+            render_graph_cache,
+            render_graph_dump: String::new(),
+            render_targets: Vec::new(),
+            pending_target_renders: Vec::new(),
+            pending_capture: None,
+            recorder: None,
+            frustum_culling: true,
+            cull_stats: CullStats::default(),
+            gpu_culler,
+            occlusion: None,
         })
     }
 
-    /// Render a frame
-    pub fn render_frame(&mut self) -> Result<()> {
+    /// Returns the logical device
+    pub fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+
+    /// Returns the graphics and compute queue
+    pub fn queue(&self) -> &Arc<Queue> {
+        &self.queue
+    }
+
+    /// Returns the memory allocator
+    pub fn memory_allocator(&self) -> &Arc<StandardMemoryAllocator> {
+        &self.memory_allocator
+    }
+
+    /// Returns the command buffer allocator
+    pub fn command_buffer_allocator(&self) -> &Arc<StandardCommandBufferAllocator> {
+        &self.command_buffer_allocator
+    }
+
+    /// Returns the descriptor set allocator
+    pub fn descriptor_set_allocator(&self) -> &Arc<StandardDescriptorSetAllocator> {
+        &self.descriptor_set_allocator
+    }
+
+    /// Returns the pipeline cache shared by all graphics pipelines
+    pub fn pipeline_cache(&mut self) -> &mut GraphicsPipelineCache {
+        &mut self.pipeline_cache
+    }
+
+    /// Returns the driver-level pipeline cache to pass into pipeline builds
+    pub fn driver_pipeline_cache(&self) -> &Arc<vulkano::pipeline::cache::PipelineCache> {
+        self.persistent_cache.cache()
+    }
+
+    /// Writes the driver-level pipeline cache to disk so the next launch starts faster
+    pub fn save_pipeline_cache(&self) -> Result<()> {
+        self.persistent_cache.save()
+    }
+
+    /// Returns the description of the default scene pipeline
+    pub fn pipeline_desc(&self) -> &PipelineDesc {
+        &self.pipeline_desc
+    }
+
+    /// Returns the description of the instanced scene pipeline
+    pub fn instanced_pipeline_desc(&self) -> PipelineDesc {
+        self.pipeline_desc.instanced(self.instanced_shader.clone())
+    }
+
+    /// Enables or disables drawing the whole scene again as a wireframe on top, for debugging
+    ///
+    /// Requires the `fill_mode_non_solid` device feature; the overlay stays off if it is missing.
+    pub fn set_wireframe_overlay(&mut self, enabled: bool) -> Result<()> {
+        if enabled && self.wireframe_pipeline.is_none() {
+            if !self.device.enabled_features().fill_mode_non_solid {
+                return Err(anyhow!("Wireframe rendering is not supported by this device"));
+            }
+
+            let fs = crate::shaders::wireframe::load(self.device.clone())?;
+            let desc = self.pipeline_desc.wireframe_overlay(fs.clone());
+            self.wireframe_pipeline = Some(MeshPipelines::new(&mut self.pipeline_cache, desc)?);
+            let desc = self.instanced_pipeline_desc().wireframe_overlay(fs);
+            let instanced = MeshPipelines::new(&mut self.pipeline_cache, desc)?;
+            self.instanced_wireframe_pipeline = Some(instanced);
+        }
+
+        self.wireframe_overlay = enabled;
+        info!("Wireframe overlay {}", if enabled { "enabled" } else { "disabled" });
+        Ok(())
+    }
+
+    /// Flips the wireframe overlay on or off
+    pub fn toggle_wireframe_overlay(&mut self) -> Result<()> {
+        self.set_wireframe_overlay(!self.wireframe_overlay)
+    }
+
+    /// Returns whether the wireframe overlay is drawn
+    pub fn wireframe_overlay(&self) -> bool {
+        self.wireframe_overlay
+    }
+
+    /// Enables or disables skipping draws outside the camera frustum
+    pub fn set_frustum_culling(&mut self, enabled: bool) {
+        self.frustum_culling = enabled;
+        info!("Frustum culling {}", if enabled { "enabled" } else { "disabled" });
+    }
+
+    /// Returns whether draws outside the camera frustum are skipped
+    pub fn frustum_culling(&self) -> bool {
+        self.frustum_culling
+    }
+
+    /// Returns the culling counts of the last frame, summed over the scene and offscreen views
+    pub fn cull_stats(&self) -> CullStats {
+        self.cull_stats
+    }
+
+    /// Returns the MSAA sample count of the render pass
+    pub fn sample_count(&self) -> SampleCount {
+        self.sample_count
+    }
+
+    /// Switches MSAA to the highest supported sample count up to `requested`
+    ///
+    /// Rebuilds every pipeline for the scene render pass with the new sample count; the frame
+    /// graph allocates attachments to match. Frames in flight keep the old resources alive
+    /// until they finish.
+    pub fn set_sample_count(&mut self, requested: u32) -> Result<SampleCount> {
+        let sample_count = supported_sample_count(&self.device, requested);
+        if sample_count == self.sample_count {
+            return Ok(sample_count);
+        }
+
+        let render_pass = scene_render_pass(
+            &mut self.render_graph_cache,
+            &self.device,
+            self.swapchain.image_format(),
+            sample_count,
+        )?;
+        let old_render_pass = self.pipeline_desc.render_pass.render_pass.clone();
+        self.pipeline_cache.evict_render_pass(&old_render_pass);
+        self.sample_count = sample_count;
+
+        // Rebuild the scene pipelines against the new render pass
+        self.pipeline_desc = self.pipeline_desc.with_render_pass(render_pass);
+        self.pipeline = MeshPipelines::new(&mut self.pipeline_cache, self.pipeline_desc.clone())?;
+        let instanced_desc = self.instanced_pipeline_desc();
+        self.instanced_pipeline = MeshPipelines::new(&mut self.pipeline_cache, instanced_desc)?;
+        self.oit =
+            create_oit_pipelines(&mut self.pipeline_cache, &self.pipeline_desc, sample_count)?;
+        self.wireframe_pipeline = None;
+        self.instanced_wireframe_pipeline = None;
+        if self.wireframe_overlay {
+            self.set_wireframe_overlay(true)?;
+        }
+
+        info!("MSAA set to {:?}", sample_count);
+        Ok(sample_count)
+    }
+
+    /// Steps to the next supported MSAA sample count, wrapping back to one sample
+    pub fn cycle_sample_count(&mut self) -> Result<SampleCount> {
+        let supported = attachment_sample_counts(&self.device);
+        let next = next_sample_count(self.sample_count, supported);
+        self.set_sample_count(u32::from(next))
+    }
+
+    /// Queues a compute dispatch to be recorded at the start of the next frame
+    ///
+    /// Dispatches run before the render pass in the same command buffer, so their results can
+    /// be read as vertex buffers or textures by that frame's draws.
+    pub fn queue_compute(&mut self, dispatch: ComputeDispatch) {
+        self.pending_compute.push(dispatch);
+    }
+
+    /// Makes the next frame wait for a future, such as a separately submitted compute dispatch
+    pub fn wait_for(&mut self, future: Box<dyn GpuFuture>) {
+        let previous = self
+            .previous_frame_end
+            .take()
+            .unwrap_or_else(|| sync::now(self.device.clone()).boxed());
+        self.previous_frame_end = Some(previous.join(future).boxed());
+    }
+
+    /// Uploads a GPU-culled scene, copying its objects to device-local memory before the
+    /// next frame
+    pub fn create_gpu_scene(&mut self, batches: &[InstanceBatch]) -> Result<Arc<GpuScene>> {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        let scene = GpuScene::new(&self.memory_allocator, batches, &mut builder)?;
+        let command_buffer = builder.build()?;
+
+        let future = self
+            .previous_frame_end
+            .take()
+            .unwrap_or_else(|| sync::now(self.device.clone()).boxed())
+            .then_execute(self.queue.clone(), command_buffer)?;
+        self.previous_frame_end = Some(future.boxed());
+        Ok(scene)
+    }
+
+    /// Creates an offscreen render target, sized relative to the swapchain if requested
+    pub fn create_render_target(&mut self, desc: RenderTargetDesc) -> Result<RenderTargetId> {
+        let target = RenderTarget::new(&self.memory_allocator, desc, self.swapchain.image_extent())?;
+
+        // Reuse the slot of a destroyed target if there is one
+        let slot = self.render_targets.iter().position(Option::is_none);
+        let id = match slot {
+            Some(index) => {
+                self.render_targets[index] = Some(target);
+                RenderTargetId(index)
+            }
+            None => {
+                self.render_targets.push(Some(target));
+                RenderTargetId(self.render_targets.len() - 1)
+            }
+        };
+        Ok(id)
+    }
+
+    /// Returns a render target, e.g. to bind its images in a later pass
+    pub fn render_target(&self, id: RenderTargetId) -> Option<&RenderTarget> {
+        self.render_targets.get(id.0).and_then(Option::as_ref)
+    }
+
+    /// Destroys a render target and the pipelines built for it
+    pub fn destroy_render_target(&mut self, id: RenderTargetId) {
+        if let Some(target) = self.render_targets.get_mut(id.0).and_then(Option::take) {
+            self.pipeline_cache.evict_render_pass(target.render_pass());
+            self.pending_target_renders.retain(|(pending, _)| *pending != id);
+            if self.occlusion.as_ref().is_some_and(|(occluders, _)| *occluders == id) {
+                self.occlusion = None;
+            }
+        }
+    }
+
+    /// Queues a view of the scene to be rendered into a target during the next frame
+    ///
+    /// Targets are drawn before the main scene, so the main scene can sample them in the same
+    /// frame. Order-independent draws are sorted with the other transparent draws.
+    pub fn render_to_target(&mut self, id: RenderTargetId, queue: RenderQueue) {
+        self.pending_target_renders.push((id, queue));
+    }
+
+    /// Occlusion-culls GPU scenes against the depth of a render target, or stops with `None`
+    ///
+    /// The target must have depth rendered from the main camera, typically a prepass of large
+    /// occluders queued with `render_to_target` every frame. A Hi-Z pyramid is built from it
+    /// before GPU scenes are culled.
+    pub fn set_occlusion_target(&mut self, id: Option<RenderTargetId>) -> Result<()> {
+        self.occlusion = None;
+        if let Some(id) = id {
+            let target = self
+                .render_target(id)
+                .ok_or_else(|| anyhow!("Render target {:?} does not exist", id))?;
+            if target.depth().is_none() {
+                return Err(anyhow!("Render target {:?} has no depth to occlude with", id));
+            }
+            let pyramid = self.create_hi_z_pyramid(target.extent())?;
+            self.occlusion = Some((id, pyramid));
+        }
+        Ok(())
+    }
+
+    /// Saves the next presented frame to an image file once it has been rendered
+    ///
+    /// The file type follows the extension: `.png`, `.jpg`/`.jpeg` or `.exr`.
+    pub fn capture_frame(&mut self, path: impl Into<PathBuf>) -> Result<()> {
+        if !self.swapchain.image_usage().intersects(ImageUsage::TRANSFER_SRC) {
+            return Err(anyhow!("Swapchain images cannot be copied on this surface"));
+        }
+        self.pending_capture = Some(path.into());
+        Ok(())
+    }
+
+    /// Starts writing every rendered frame to a recorder, replacing any active recording
+    ///
+    /// Each frame waits for the GPU so it can be read back, which makes recording slower than
+    /// real time but never drops a frame.
+    pub fn start_recording(&mut self, recorder: Recorder) -> Result<()> {
+        if !self.swapchain.image_usage().intersects(ImageUsage::TRANSFER_SRC) {
+            return Err(anyhow!("Swapchain images cannot be copied on this surface"));
+        }
+        self.stop_recording()?;
+        self.recorder = Some(recorder);
+        info!("Recording started");
+        Ok(())
+    }
+
+    /// Finishes the active recording; returns the number of frames it wrote
+    pub fn stop_recording(&mut self) -> Result<Option<u64>> {
+        self.recorder.take().map(Recorder::finish).transpose()
+    }
+
+    /// Returns whether frames are being recorded
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Returns the simulated time of the next recorded frame, if recording
+    ///
+    /// Animation should follow this instead of the wall clock while recording.
+    pub fn recording_time(&self) -> Option<Duration> {
+        self.recorder.as_ref().map(Recorder::time)
+    }
+
+    /// Saves the current contents of a render target to an image file
+    ///
+    /// Captures the color image, or the depth image of depth-only targets. Waits for the GPU
+    /// to finish the previous frame, so this is meant for debugging rather than every frame.
+    pub fn capture_target(&mut self, id: RenderTargetId, path: impl Into<PathBuf>) -> Result<()> {
+        let target = self
+            .render_target(id)
+            .ok_or_else(|| anyhow!("Render target {:?} does not exist", id))?;
+        let image: Arc<dyn ImageAccess> = match (target.color(), target.depth()) {
+            (Some(color), _) => color.image().clone(),
+            (None, Some(depth)) => depth.image().clone(),
+            (None, None) => return Err(anyhow!("Render target {:?} has no images", id)),
+        };
+        let format = image.format();
+        let capture = CaptureBuffer::new(&self.memory_allocator, format, target.extent())?;
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        capture.record_copy(&mut builder, image)?;
+        let command_buffer = builder.build()?;
+
+        // Copy after any pending rendering into the target and wait for the result
+        let future = self
+            .previous_frame_end
+            .take()
+            .unwrap_or_else(|| sync::now(self.device.clone()).boxed())
+            .then_execute(self.queue.clone(), command_buffer)?
+            .then_signal_fence_and_flush()?;
+        future.wait(None)?;
+        self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+
+        capture.read()?.save(path.into())
+    }
+
+    /// Render a frame of the queued draws
+    ///
+    /// Opaque draws are recorded first. Order-independent draws are accumulated in the next
+    /// subpass and composited at the start of the last one, where sorted transparent draws
+    /// follow back-to-front using pipelines derived for their blend preset.
+    ///
+    /// The queue is culled, batched and sorted in place, so it should be refilled every frame
+    /// as `render_scene` and `render_world` do.
+    pub fn render_frame(&mut self, queue: &mut RenderQueue) -> Result<()> {
         // Wait for the previous frame to finish
         self.previous_frame_end.as_mut().unwrap().cleanup_finished();
 
@@ -141,14 +614,104 @@ impl Renderer {
             return Ok(());
         }
 
-        let _ubo = UniformBufferObject {
-            model: Matrix4::identity(),
-            view: Matrix4::identity(),
-            proj: Matrix4::identity(),
+        // Upload this frame's camera matrices and lights
+        let aspect = self.viewport.dimensions[0] / self.viewport.dimensions[1];
+        let frame_sets = self.create_frame_sets(queue, aspect)?;
+
+        // Skip draws outside the camera frustum
+        let mut cull_stats = CullStats::default();
+        if self.frustum_culling {
+            let frustum = queue.camera.frustum(aspect);
+            cull_stats += queue.cull(&frustum);
+        }
+
+        // Resolve the camera and pipelines of each queued offscreen view
+        let mut target_renders = Vec::with_capacity(self.pending_target_renders.len());
+        for (id, mut target_queue) in std::mem::take(&mut self.pending_target_renders) {
+            let Some(target) = self.render_targets.get(id.0).and_then(Option::as_ref) else {
+                warn!("Skipping render into destroyed target {:?}", id);
+                continue;
+            };
+            let extent = target.extent();
+            let base_desc = target_pipeline_desc(&self.pipeline_desc, target);
+
+            let aspect = extent[0] as f32 / extent[1] as f32;
+            let frame_sets = self.create_frame_sets(&target_queue, aspect)?;
+
+            if self.frustum_culling {
+                let frustum = target_queue.camera.frustum(aspect);
+                cull_stats += target_queue.cull(&frustum);
+            }
+            target_queue.batch_instances();
+            target_queue.sort_order_independent();
+            target_queue.sort_transparent();
+            let mut transparent = Vec::with_capacity(target_queue.transparent().len());
+            for item in target_queue.transparent() {
+                let desc = base_desc
+                    .transparent(item.material.blend)
+                    .in_subpass(0)
+                    .with_vertex_streams(item.mesh.vertex_layout());
+                transparent.push(self.pipeline_cache.get_or_create(&desc)?);
+            }
+
+            let layouts = target_queue.vertex_layouts();
+            let instanced_desc = base_desc.instanced(self.instanced_shader.clone());
+            let mut opaque = MeshPipelines::new(&mut self.pipeline_cache, base_desc)?;
+            opaque.add_variants(&mut self.pipeline_cache, &layouts)?;
+            let mut instanced = MeshPipelines::new(&mut self.pipeline_cache, instanced_desc)?;
+            instanced.add_variants(&mut self.pipeline_cache, &layouts)?;
+            target_renders.push(TargetRender {
+                target: id,
+                opaque,
+                instanced,
+                queue: target_queue,
+                frame_sets,
+                transparent,
+            });
+        }
+
+        self.cull_stats = cull_stats;
+
+        // Merge opaque draws of the same mesh and material into instanced batches
+        queue.batch_instances();
+
+        // Build pipeline variants for meshes with their own vertex streams
+        self.add_pipeline_variants(&queue.vertex_layouts())?;
+
+        // Prepare the culling of GPU scenes, behind the occlusion target's depth when there is one
+        let occlusion = self.occlusion.as_ref().and_then(|(id, pyramid)| {
+            let depth = self.render_target(*id)?.depth()?.clone();
+            Some((*id, depth, pyramid))
+        });
+        let hi_z_build = match &occlusion {
+            Some((_, depth, pyramid)) if !queue.gpu_scenes().is_empty() => {
+                self.gpu_culler.build_hi_z(pyramid, depth.clone())?
+            }
+            _ => Vec::new(),
         };
-        
-        // Create a new uniform buffer with the updated values
-        let _new_buffer = create_uniform_buffer(&self.memory_allocator)?;
+        let hi_z = occlusion
+            .as_ref()
+            .filter(|_| !hi_z_build.is_empty())
+            .map(|&(_, _, pyramid)| pyramid);
+        let culled_scenes = queue
+            .gpu_scenes()
+            .iter()
+            .map(|scene| self.gpu_culler.prepare(scene, &queue.camera, aspect, hi_z))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Resolve the pipeline for each transparent draw, in back-to-front order
+        if self.oit.is_none() {
+            queue.sort_order_independent();
+        }
+        queue.sort_transparent();
+        let mut transparent = Vec::with_capacity(queue.transparent().len());
+        for item in queue.transparent() {
+            let desc = self
+                .pipeline_desc
+                .transparent(item.material.blend)
+                .with_vertex_streams(item.mesh.vertex_layout());
+            transparent.push((self.pipeline_cache.get_or_create(&desc)?, item));
+        }
 
         // Create a command buffer builder
         let mut builder = AutoCommandBufferBuilder::primary(
@@ -157,48 +720,615 @@ impl Renderer {
             CommandBufferUsage::OneTimeSubmit,
         )?;
 
-        // Begin the render pass
-        builder
-            .begin_render_pass(
-                RenderPassBeginInfo {
-                    clear_values: vec![Some([0.0, 0.0, 0.0, 1.0].into())],
-                    ..RenderPassBeginInfo::framebuffer(
-                        self.framebuffers[image_index as usize].clone(),
-                    )
+        // Describe the frame as a render graph ending in the swapchain image
+        let dispatches: Vec<ComputeDispatch> = self.pending_compute.drain(..).collect();
+        let swapchain_view =
+            ImageView::new_default(self.swapchain_images[image_index as usize].clone())?;
+
+        // Allocate a readback buffer when this frame is captured or recorded
+        let capture_path = self.pending_capture.take();
+        let capture = if capture_path.is_some() || self.recorder.is_some() {
+            Some(CaptureBuffer::new(
+                &self.memory_allocator,
+                self.swapchain.image_format(),
+                self.swapchain.image_extent(),
+            )?)
+        } else {
+            None
+        };
+
+        let mut graph_cache = std::mem::take(&mut self.render_graph_cache);
+        let this = &*self;
+        let queue = &*queue;
+        let mut graph = RenderGraph::new();
+        let swapchain_image = graph.import_image("swapchain", swapchain_view);
+        graph.mark_output(swapchain_image);
+
+        // Queued compute work runs ahead of the scene
+        if !dispatches.is_empty() {
+            graph.add_pass("compute").side_effects().execute(move |context| {
+                for dispatch in &dispatches {
+                    dispatch.record(context.builder)?;
+                }
+                Ok(())
+            });
+        }
+
+        // Offscreen views are drawn before the scene that may sample them
+        let mut target_depths = Vec::with_capacity(target_renders.len());
+        for render in &target_renders {
+            let Some(target) = this.render_target(render.target) else {
+                continue;
+            };
+            let name = format!("render target {}", render.target.0);
+            let color = target
+                .color()
+                .map(|color| graph.import_image(format!("{} color", name), color.clone()));
+            let depth = target
+                .depth()
+                .map(|depth| graph.import_image(format!("{} depth", name), depth.clone()));
+            if color.is_none() && depth.is_none() {
+                continue;
+            }
+
+            let mut pass = graph.add_pass(name).side_effects();
+            if let Some(color) = color {
+                pass = pass.write(color, Access::ColorAttachment);
+            }
+            if let Some(depth) = depth {
+                pass = pass.write(depth, Access::DepthAttachment);
+                target_depths.push((render.target, depth));
+            }
+            pass.execute(move |context| this.record_target(context.builder, target, render));
+        }
+
+        // Rebuild the Hi-Z pyramid once the occluders are drawn
+        let mut hi_z_image = None;
+        if let Some((id, depth, pyramid)) = occlusion.filter(|_| !hi_z_build.is_empty()) {
+            let depth_image = target_depths
+                .iter()
+                .find(|(target, _)| *target == id)
+                .map(|&(_, image)| image)
+                .unwrap_or_else(|| graph.import_image("occlusion depth", depth));
+            let pyramid_image = graph.import_image("hi-z pyramid", pyramid.view().clone());
+            graph
+                .add_pass("hi-z")
+                .read(depth_image, Access::Sampled)
+                .write(pyramid_image, Access::StorageImage)
+                .execute(move |context| {
+                    for dispatch in &hi_z_build {
+                        dispatch.record(context.builder)?;
+                    }
+                    Ok(())
+                });
+            hi_z_image = Some(pyramid_image);
+        }
+
+        // Cull GPU scenes into the indirect commands and instances the scene pass draws
+        let mut culled_buffers = Vec::with_capacity(culled_scenes.len());
+        for (index, culled) in culled_scenes.iter().enumerate() {
+            let commands = graph.import_buffer(
+                format!("gpu scene {} commands", index),
+                culled.commands().clone(),
+            );
+            let instances = graph.import_buffer(
+                format!("gpu scene {} instances", index),
+                culled.instances().clone(),
+            );
+            let mut pass = graph
+                .add_pass(format!("gpu scene {} culling", index))
+                .write(commands, Access::StorageBuffer)
+                .write(instances, Access::StorageBuffer);
+            if let Some(pyramid_image) = hi_z_image {
+                pass = pass.read(pyramid_image, Access::Sampled);
+            }
+            pass.execute(move |context| culled.record_cull(context.builder));
+            culled_buffers.push((commands, instances));
+        }
+
+        // The scene renders into the swapchain image through transient depth and OIT targets,
+        // and a multisampled color target resolved into the swapchain image when MSAA is on;
+        // the attachments are declared in the order of `scene_attachments`
+        let samples = this.sample_count;
+        let mut scene_image = |name: &'static str, format| {
+            graph.create_image(
+                name,
+                ImageDesc {
+                    format,
+                    size: ImageSize::SwapchainRelative(1.0),
+                    samples,
                 },
-                SubpassContents::Inline,
-            )?
-            .end_render_pass()?;
+            )
+        };
+        let color_image = (samples != SampleCount::Sample1)
+            .then(|| scene_image("msaa color", this.swapchain.image_format()));
+        let accumulation_image = scene_image("oit accumulation", OIT_ACCUMULATION_FORMAT);
+        let revealage_image = scene_image("oit revealage", OIT_REVEALAGE_FORMAT);
+        let depth_image = scene_image("scene depth", DEPTH_FORMAT);
+        let mut scene_pass = graph
+            .add_pass("scene")
+            .color_attachment(
+                color_image.unwrap_or(swapchain_image),
+                Some([0.0, 0.0, 0.0, 1.0].into()),
+            )
+            // Nothing accumulated and everything behind fully revealed
+            .color_attachment(accumulation_image, Some([0.0, 0.0, 0.0, 0.0].into()))
+            .color_attachment(revealage_image, Some([1.0, 0.0, 0.0, 0.0].into()));
+        if color_image.is_some() {
+            scene_pass = scene_pass.resolve_attachment(swapchain_image);
+        }
+        scene_pass = scene_pass
+            .depth_attachment(depth_image, Some(1.0.into()))
+            .subpasses(scene_subpasses(samples));
+        for (commands, instances) in culled_buffers {
+            scene_pass = scene_pass
+                .read(commands, Access::IndirectBuffer)
+                .read(instances, Access::VertexBuffer);
+        }
+        scene_pass.execute(|context| {
+            let oit_targets = [context.image(accumulation_image)?, context.image(revealage_image)?];
+            this.record_scene(
+                context.builder,
+                oit_targets,
+                queue,
+                &frame_sets,
+                &transparent,
+                &culled_scenes,
+            )
+        });
+
+        // Copy the finished image out before it is presented
+        if let Some(buffer) = &capture {
+            let image = this.swapchain_images[image_index as usize].clone();
+            graph
+                .add_pass("capture")
+                .read(swapchain_image, Access::TransferSource)
+                .side_effects()
+                .execute(move |context| buffer.record_copy(context.builder, image));
+        }
+
+        let compiled = graph.compile(
+            &this.memory_allocator,
+            &mut graph_cache,
+            this.swapchain.image_extent(),
+        );
+        let recorded = compiled.and_then(|compiled| {
+            // Only describe the graph when someone reads it
+            let graph_dump = log_enabled!(Level::Debug).then(|| compiled.to_string());
+            compiled.execute(&mut builder).map(|()| graph_dump)
+        });
+
+        // Put the cache back before reporting a failed frame, so its images and render passes
+        // survive for the next one
+        self.render_graph_cache = graph_cache;
+        let graph_dump = recorded?;
+        if let Some(graph_dump) = graph_dump.filter(|dump| *dump != self.render_graph_dump) {
+            debug!("{}", graph_dump);
+            self.render_graph_dump = graph_dump;
+        }
 
         // Build the command buffer
         let command_buffer = builder.build()?;
 
         // Submit the command buffer and advance to the next frame
-        let future = self
+        let executed = self
             .previous_frame_end
             .take()
             .unwrap()
             .join(acquire_future)
-            .then_execute(self.queue.clone(), command_buffer)?
+            .then_execute(self.queue.clone(), command_buffer);
+        let executed = match executed {
+            Ok(executed) => executed,
+            Err(e) => {
+                // Leave a future behind so the next frame can still start
+                self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+                return Err(e.into());
+            }
+        };
+        let future = executed
             .then_swapchain_present(
                 self.queue.clone(),
                 SwapchainPresentInfo::swapchain_image_index(self.swapchain.clone(), image_index),
             )
             .then_signal_fence_and_flush();
 
-        // Handle the result
-        self.previous_frame_end = match future {
-            Ok(future) => Some(future.boxed()),
+        let future = match future {
+            Ok(future) => future,
             Err(FlushError::OutOfDate) => {
+                // The frame was not presented; capture the next one instead
+                self.pending_capture = capture_path;
                 self.recreate_swapchain()?;
-                Some(sync::now(self.device.clone()).boxed())
+                self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+                return Ok(());
             }
             Err(e) => return Err(anyhow!("Failed to flush future: {}", e)),
         };
 
+        // Wait for a captured frame to finish, but keep its future before writing the image,
+        // so a failed capture or recording never leaves the next frame without one
+        let image = capture.map(|buffer| {
+            future
+                .wait(None)
+                .map_err(|e| anyhow!("Failed to wait for the captured frame: {}", e))
+                .and_then(|()| buffer.read())
+        });
+        self.previous_frame_end = Some(future.boxed());
+        if let Some(image) = image {
+            write_captured_frame(image, capture_path, &mut self.recorder);
+        }
+
+        Ok(())
+    }
+
+    /// Render a frame of every mesh node in a scene, seen through the queue's camera
+    ///
+    /// The queue's draws are cleared first and refilled from the scene's updated world
+    /// matrices; its camera and lights are kept.
+    pub fn render_scene(&mut self, scene: &mut SceneGraph, queue: &mut RenderQueue) -> Result<()> {
+        queue.clear_draws();
+        scene.queue_draws(queue);
+        self.render_frame(queue)
+    }
+
+    /// Render a frame of the renderable components of an ECS world
+    ///
+    /// The queue is refilled by `extract_render_list`, so simulation code only touches
+    /// components and never Vulkan resources.
+    pub fn render_world(&mut self, world: &World, queue: &mut RenderQueue) -> Result<()> {
+        extract_render_list(world, queue);
+        self.render_frame(queue)
+    }
+
+    /// Records the subpasses of the scene pass: opaque, OIT and sorted transparent draws, then
+    /// overlays
+    ///
+    /// The render graph begins and ends the render pass around this.
+    fn record_scene<L, A>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+        oit_targets: [Arc<dyn ImageViewAbstract>; 2],
+        queue: &RenderQueue,
+        frame_sets: &FrameSets,
+        transparent: &[(Arc<GraphicsPipeline>, &DrawItem)],
+        culled: &[CulledScene],
+    ) -> Result<()>
+    where
+        A: CommandBufferAllocator,
+    {
+        let viewport = (self.viewport.clone(), self.scissor);
+
+        // Draw opaque geometry first so transparent surfaces blend over it
+        self.record_draws(builder, &self.pipeline, frame_sets, &viewport, queue.opaque())?;
+        let pipelines = &self.instanced_pipeline;
+        self.record_instanced(builder, pipelines, frame_sets, &viewport, queue.instanced())?;
+        self.record_culled(builder, pipelines, frame_sets, &viewport, culled)?;
+        builder.next_subpass(SubpassContents::Inline)?;
+
+        // Accumulate order-independent geometry into the OIT targets
+        let oit = self
+            .oit
+            .as_ref()
+            .filter(|_| !queue.order_independent().is_empty());
+        if let Some(oit) = oit {
+            let items = queue.order_independent();
+            self.record_draws(builder, &oit.accumulation, frame_sets, &viewport, items)?;
+        }
+        builder.next_subpass(SubpassContents::Inline)?;
+
+        // Resolve the OIT targets over the opaque scene
+        if let Some(oit) = oit {
+            let composite_set = create_composite_set(
+                &self.descriptor_set_allocator,
+                &oit.composite,
+                oit_targets,
+            )?;
+            builder
+                .bind_pipeline_graphics(oit.composite.clone())
+                .set_viewport(0, [self.viewport.clone()])
+                .set_scissor(0, [self.scissor])
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    oit.composite.layout().clone(),
+                    0,
+                    composite_set,
+                );
+            if self.sample_count != SampleCount::Sample1 {
+                let constants = CompositeConstants {
+                    sample_count: u32::from(self.sample_count) as i32,
+                };
+                push_constants(builder, oit.composite.layout(), 0, constants)?;
+            }
+            builder.draw(3, 1, 0, 0)?;
+        }
+
+        // Draw transparent geometry back to front
+        self.record_transparent(builder, frame_sets, &viewport, transparent)?;
+
+        // Draw everything again as lines when the wireframe overlay is on
+        if self.wireframe_overlay {
+            if let Some(wireframe_pipeline) = &self.wireframe_pipeline {
+                let items = queue
+                    .opaque()
+                    .iter()
+                    .chain(queue.order_independent())
+                    .chain(queue.transparent());
+                self.record_draws(builder, wireframe_pipeline, frame_sets, &viewport, items)?;
+            }
+            if let Some(wireframe_pipeline) = &self.instanced_wireframe_pipeline {
+                let batches = queue.instanced();
+                self.record_instanced(builder, wireframe_pipeline, frame_sets, &viewport, batches)?;
+                self.record_culled(builder, wireframe_pipeline, frame_sets, &viewport, culled)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records an offscreen view into its render target
+    fn record_target<L, A>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+        target: &RenderTarget,
+        render: &TargetRender,
+    ) -> Result<()>
+    where
+        A: CommandBufferAllocator,
+    {
+        builder.begin_render_pass(
+            RenderPassBeginInfo {
+                clear_values: target.clear_values(),
+                ..RenderPassBeginInfo::framebuffer(target.framebuffer().clone())
+            },
+            SubpassContents::Inline,
+        )?;
+        let viewport = target.viewport();
+
+        let queue = &render.queue;
+        self.record_draws(builder, &render.opaque, &render.frame_sets, &viewport, queue.opaque())?;
+        let batches = queue.instanced();
+        self.record_instanced(builder, &render.instanced, &render.frame_sets, &viewport, batches)?;
+        let transparent: Vec<_> = render
+            .transparent
+            .iter()
+            .cloned()
+            .zip(queue.transparent())
+            .collect();
+        self.record_transparent(builder, &render.frame_sets, &viewport, &transparent)?;
+
+        builder.end_render_pass()?;
         Ok(())
     }
 
+    /// Records sorted transparent draws with the pipelines resolved for each of them
+    fn record_transparent<L, A>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+        frame_sets: &FrameSets,
+        viewport: &(Viewport, Scissor),
+        transparent: &[(Arc<GraphicsPipeline>, &DrawItem)],
+    ) -> Result<()>
+    where
+        A: CommandBufferAllocator,
+    {
+        let draws = transparent.iter().map(|(pipeline, item)| (pipeline, *item));
+        self.record_pipeline_draws(builder, frame_sets, viewport, draws)
+    }
+
+    /// Records draws with the variant matching each mesh's vertex streams
+    fn record_draws<'a, L, A>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+        pipelines: &MeshPipelines,
+        frame_sets: &FrameSets,
+        viewport: &(Viewport, Scissor),
+        items: impl IntoIterator<Item = &'a DrawItem>,
+    ) -> Result<()>
+    where
+        A: CommandBufferAllocator,
+    {
+        let draws = items
+            .into_iter()
+            .map(|item| Ok((pipelines.for_mesh(&item.mesh)?, item)))
+            .collect::<Result<Vec<_>>>()?;
+        self.record_pipeline_draws(builder, frame_sets, viewport, draws)
+    }
+
+    /// Records draws with their own pipelines, binding a pipeline only when it changes
+    fn record_pipeline_draws<'a, L, A>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+        frame_sets: &FrameSets,
+        viewport: &(Viewport, Scissor),
+        draws: impl IntoIterator<Item = (&'a Arc<GraphicsPipeline>, &'a DrawItem)>,
+    ) -> Result<()>
+    where
+        A: CommandBufferAllocator,
+    {
+        let mut bound: Option<&Arc<GraphicsPipeline>> = None;
+        for (pipeline, item) in draws {
+            if !bound.is_some_and(|bound| Arc::ptr_eq(bound, pipeline)) {
+                self.bind_pipeline(builder, pipeline, frame_sets, viewport);
+                bound = Some(pipeline);
+            }
+
+            let draw_constants = PushConstants {
+                model: item.model.into(),
+                color: item.material.color,
+            };
+            push_constants(builder, pipeline.layout(), 0, draw_constants)?;
+            item.mesh.draw(builder)?;
+        }
+
+        Ok(())
+    }
+
+    /// Records one instanced draw per batch with the variant matching its mesh
+    fn record_instanced<'a, L, A>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+        pipelines: &MeshPipelines,
+        frame_sets: &FrameSets,
+        viewport: &(Viewport, Scissor),
+        batches: impl IntoIterator<Item = &'a InstanceBatch>,
+    ) -> Result<()>
+    where
+        A: CommandBufferAllocator,
+    {
+        let mut bound: Option<&Arc<GraphicsPipeline>> = None;
+        for batch in batches {
+            let pipeline = pipelines.for_mesh(&batch.mesh)?;
+            if !bound.is_some_and(|bound| Arc::ptr_eq(bound, pipeline)) {
+                self.bind_pipeline(builder, pipeline, frame_sets, viewport);
+                bound = Some(pipeline);
+            }
+
+            let instances = self
+                .instance_buffer_allocator
+                .allocate_slice::<InstanceData>(batch.instances.len() as u64)?;
+            instances.write()?.copy_from_slice(&batch.instances);
+
+            let batch_constants = PushConstants {
+                model: Matrix4::identity().into(),
+                color: batch.material.color,
+            };
+            push_constants(builder, pipeline.layout(), 0, batch_constants)?;
+            batch.mesh.draw_instanced(builder, instances)?;
+        }
+
+        Ok(())
+    }
+
+    /// Records the indirect draws of culled GPU scenes with the matching instanced variants
+    fn record_culled<L, A>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+        pipelines: &MeshPipelines,
+        frame_sets: &FrameSets,
+        viewport: &(Viewport, Scissor),
+        culled: &[CulledScene],
+    ) -> Result<()>
+    where
+        A: CommandBufferAllocator,
+    {
+        let mut bound: Option<&Arc<GraphicsPipeline>> = None;
+        for scene in culled {
+            let pipeline = pipelines.get(scene.vertex_layout())?;
+            if !bound.is_some_and(|bound| Arc::ptr_eq(bound, pipeline)) {
+                self.bind_pipeline(builder, pipeline, frame_sets, viewport);
+                bound = Some(pipeline);
+            }
+            scene.record_draws(builder, pipeline.layout())?;
+        }
+        Ok(())
+    }
+
+    /// Binds a pipeline with its dynamic state and the descriptor sets of the view
+    fn bind_pipeline<L, A>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L, A>,
+        pipeline: &Arc<GraphicsPipeline>,
+        frame_sets: &FrameSets,
+        (viewport, scissor): &(Viewport, Scissor),
+    ) where
+        A: CommandBufferAllocator,
+    {
+        builder
+            .bind_pipeline_graphics(pipeline.clone())
+            .set_viewport(0, [viewport.clone()])
+            .set_scissor(0, [*scissor]);
+
+        // Pipelines that do no lighting, like the wireframe overlay, only have the camera set
+        let mut sets = vec![frame_sets.camera.clone()];
+        if pipeline.layout().set_layouts().len() > 1 {
+            sets.push(frame_sets.lights.clone());
+        }
+        builder.bind_descriptor_sets(
+            PipelineBindPoint::Graphics,
+            pipeline.layout().clone(),
+            0,
+            sets,
+        );
+    }
+
+    /// Builds the variants of every scene pipeline for the given vertex layouts
+    fn add_pipeline_variants(&mut self, layouts: &[VertexLayout]) -> Result<()> {
+        let cache = &mut self.pipeline_cache;
+        self.pipeline.add_variants(cache, layouts)?;
+        self.instanced_pipeline.add_variants(cache, layouts)?;
+        if let Some(oit) = &mut self.oit {
+            oit.accumulation.add_variants(cache, layouts)?;
+        }
+        if let Some(wireframe) = &mut self.wireframe_pipeline {
+            wireframe.add_variants(cache, layouts)?;
+        }
+        if let Some(wireframe) = &mut self.instanced_wireframe_pipeline {
+            wireframe.add_variants(cache, layouts)?;
+        }
+        Ok(())
+    }
+
+    /// Uploads a queue's camera matrices and lights as the per-frame descriptor sets
+    fn create_frame_sets(&self, queue: &RenderQueue, aspect: f32) -> Result<FrameSets> {
+        let set_layouts = self.pipeline.standard().layout().set_layouts();
+
+        let uniform_buffer = self.uniform_buffer_allocator.allocate_sized()?;
+        *uniform_buffer.write()? = UniformBufferObject {
+            view: queue.camera.view_matrix().into(),
+            proj: queue.camera.projection_matrix(aspect).into(),
+        };
+        let camera = PersistentDescriptorSet::new(
+            &*self.descriptor_set_allocator,
+            set_layouts[0].clone(),
+            [WriteDescriptorSet::buffer(0, uniform_buffer)],
+        )?;
+
+        // Fall back to a default light so unlit queues are still visible
+        let default_light = [Light::default()];
+        let lights = match queue.lights() {
+            [] => &default_light[..],
+            lights => &lights[..lights.len().min(MAX_LIGHTS)],
+        };
+        let light_buffer = self.uniform_buffer_allocator.allocate_sized()?;
+        let mut light_block = LightBlock {
+            directions: [[0.0; 4]; MAX_LIGHTS],
+            colors: [[0.0; 4]; MAX_LIGHTS],
+            count: lights.len() as i32,
+        };
+        for (index, light) in lights.iter().enumerate() {
+            let direction = light.direction;
+            light_block.directions[index] = [direction.x, direction.y, direction.z, 0.0];
+            let [r, g, b] = light.color.map(|channel| channel * light.intensity);
+            light_block.colors[index] = [r, g, b, 1.0];
+        }
+        *light_buffer.write()? = light_block;
+        let lights = PersistentDescriptorSet::new(
+            &*self.descriptor_set_allocator,
+            set_layouts[1].clone(),
+            [WriteDescriptorSet::buffer(0, light_buffer)],
+        )?;
+
+        Ok(FrameSets { camera, lights })
+    }
+
+    /// Creates a Hi-Z pyramid, clearing it before the next frame
+    fn create_hi_z_pyramid(&mut self, extent: [u32; 2]) -> Result<HiZPyramid> {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )?;
+        let pyramid = HiZPyramid::new(&self.memory_allocator, extent, &mut builder)?;
+        let command_buffer = builder.build()?;
+
+        let future = self
+            .previous_frame_end
+            .take()
+            .unwrap_or_else(|| sync::now(self.device.clone()).boxed())
+            .then_execute(self.queue.clone(), command_buffer)?;
+        self.previous_frame_end = Some(future.boxed());
+        Ok(pyramid)
+    }
+
     /// Recreate the swapchain
     fn recreate_swapchain(&mut self) -> Result<()> {
         // Recreate the swapchain and related resources
@@ -211,111 +1341,113 @@ impl Renderer {
         // Update the renderer's fields
         self.swapchain = swapchain_bundle.swapchain;
         self.swapchain_images = swapchain_bundle.images;
-        
-        // Recreate framebuffers with new swapchain images
-        self.framebuffers = create_framebuffers(&self.swapchain_images, &self.render_pass)?;
-        
-        // Update viewport with new dimensions
-        let dimensions = self.swapchain.image_extent();
-        self.viewport = Viewport {
-            origin: [0.0, 0.0],
-            dimensions: [dimensions[0] as f32, dimensions[1] as f32],
-            depth_range: 0.0..1.0,
-        };
 
-        // Create a new pipeline with the updated viewport
-        self.pipeline = create_pipeline(
-            self.device.clone(),
-            self.render_pass.clone(),
-            self.viewport.clone(),
-        )?;
+        // Follow the new size with targets sized relative to the swapchain
+        let extent = self.swapchain.image_extent();
+        for target in self.render_targets.iter_mut().flatten() {
+            target.resize(&self.memory_allocator, extent)?;
+        }
+        let resized_occluders = self.occlusion.as_ref().and_then(|(id, pyramid)| {
+            let extent = self.render_target(*id)?.extent();
+            (extent != pyramid.extent()).then_some((*id, extent))
+        });
+        if let Some((id, extent)) = resized_occluders {
+            let pyramid = self.create_hi_z_pyramid(extent)?;
+            self.occlusion = Some((id, pyramid));
+        }
+        
+        // Update viewport and scissor with new dimensions; the pipeline is unaffected since
+        // both are dynamic state
+        let (viewport, scissor) = full_viewport(self.swapchain.image_extent());
+        self.viewport = viewport;
+        self.scissor = scissor;
 
         info!("Swapchain recreated successfully");
         Ok(())
     }
 }
 
-/// Creates a render pass compatible with our swapchain
-fn create_render_pass(device: Arc<Device>) -> Result<Arc<RenderPass>> {
-    let render_pass = vulkano::single_pass_renderpass!(
-        device,
-        attachments: {
-            color: {
-                load: Clear,
-                store: Store,
-                format: Format::B8G8R8A8_SRGB,
-                samples: vulkano::image::SampleCount::Sample1,
-            }
-        },
-        pass: {
-            color: [color],
-            depth_stencil: {}
-        }
-    )?;
+/// Derives the base pipeline for drawing into a render target's single subpass
+fn target_pipeline_desc(base_desc: &PipelineDesc, target: &RenderTarget) -> PipelineDesc {
+    let mut desc = base_desc.with_render_pass(target.render_pass().clone()).in_subpass(0);
+    if target.depth().is_none() {
+        desc.depth = DepthDesc::disabled();
+    }
+    desc
+}
 
-    Ok(render_pass)
+/// Returns the render pass the frame graph creates for the scene pass, to build pipelines for
+fn scene_render_pass(
+    cache: &mut RenderGraphCache,
+    device: &Arc<Device>,
+    format: Format,
+    samples: SampleCount,
+) -> Result<Arc<RenderPass>> {
+    let attachments = scene_attachments(format, samples);
+    cache.render_pass_for(device, &attachments, &scene_subpasses(samples))
 }
 
-/// Creates framebuffers from swapchain images
-fn create_framebuffers(
-    swapchain_images: &[Arc<SwapchainImage>],
-    render_pass: &Arc<RenderPass>,
-) -> Result<Vec<Arc<Framebuffer>>> {
-    let framebuffers = swapchain_images
-        .iter()
-        .map(|image| {
-            let view = ImageView::new_default(image.clone())?;
-            
-            Framebuffer::new(
-                render_pass.clone(),
-                FramebufferCreateInfo {
-                    attachments: vec![view],
-                    ..Default::default()
-                },
-            )
-            .map_err(|e| anyhow!("Failed to create framebuffer: {}", e))
-        })
-        .collect::<Result<Vec<_>>>()?;
+/// Creates the OIT accumulation and composite pipelines for the scene render pass of
+/// `base_desc`, multisampled with `samples`
+///
+/// Returns `None` when the device lacks `independent_blend`, in which case OIT materials are
+/// drawn through the sorted transparent path instead.
+fn create_oit_pipelines(
+    pipeline_cache: &mut GraphicsPipelineCache,
+    base_desc: &PipelineDesc,
+    samples: SampleCount,
+) -> Result<Option<OitPipelines>> {
+    let device = base_desc.render_pass.render_pass.device().clone();
+    if !device.enabled_features().independent_blend {
+        warn!("Device lacks `independent_blend`; OIT materials fall back to sorting");
+        return Ok(None);
+    }
+
+    // Multisampled OIT targets are read per sample and averaged
+    let render_pass = base_desc.render_pass.render_pass.clone();
+    let composite_shader = if samples == SampleCount::Sample1 {
+        crate::shaders::oit_composite::load(device.clone())?
+    } else {
+        crate::shaders::oit_composite_ms::load(device.clone())?
+    };
+
+    let accumulation_desc =
+        base_desc.oit_accumulation(crate::shaders::oit_accumulate::load(device.clone())?);
+    let accumulation = MeshPipelines::new(pipeline_cache, accumulation_desc)?;
+    let composite_desc = PipelineDesc {
+        blend: BlendPreset::Alpha,
+        ..PipelineDesc::fullscreen(
+            crate::shaders::fullscreen::load(device)?,
+            composite_shader,
+            RenderPassDesc {
+                render_pass,
+                subpass: TRANSPARENT_SUBPASS,
+            },
+        )
+    };
 
-    Ok(framebuffers)
+    let composite = pipeline_cache.get_or_create(&composite_desc)?;
+
+    Ok(Some(OitPipelines {
+        accumulation,
+        composite,
+    }))
 }
 
-/// Creates a graphics pipeline
-fn create_pipeline(
-    device: Arc<Device>,
-    render_pass: Arc<RenderPass>,
-    viewport: Viewport,
-) -> Result<Arc<GraphicsPipeline>> {
-    // Load shaders
-    use crate::shaders::fragment::fs;
-    use crate::shaders::vertex::vs;
-
-    let vs = vs::load(device.clone())?;
-    let fs = fs::load(device.clone())?;
-
-    // Create pipeline layout
-    let pipeline_layout = PipelineLayout::new(
-        device.clone(), 
-        PipelineLayoutCreateInfo {
-            set_layouts: vec![],
-            push_constant_ranges: vec![],
-            ..Default::default()
-        }
+/// Binds the OIT accumulation and revealage targets as the input attachments read by the
+/// composite pipeline
+fn create_composite_set(
+    descriptor_set_allocator: &StandardDescriptorSetAllocator,
+    composite_pipeline: &Arc<GraphicsPipeline>,
+    [accumulation, revealage]: [Arc<dyn ImageViewAbstract>; 2],
+) -> Result<Arc<PersistentDescriptorSet>> {
+    let descriptor_set = PersistentDescriptorSet::new(
+        descriptor_set_allocator,
+        composite_pipeline.layout().set_layouts()[0].clone(),
+        [
+            WriteDescriptorSet::image_view(0, accumulation),
+            WriteDescriptorSet::image_view(1, revealage),
+        ],
     )?;
-
-    // For render pass, we need to convert to Subpass
-    let subpass = vulkano::render_pass::Subpass::from(render_pass.clone(), 0).unwrap();
-
-    // Create the pipeline - the builder completes with with_pipeline_layout
-    let pipeline = GraphicsPipeline::start()
-        .vertex_input_state(VertexInputState::new())
-        .vertex_shader(vs.entry_point("main").unwrap(), ())
-        .input_assembly_state(InputAssemblyState::new())
-        .viewport_state(ViewportState::viewport_dynamic_scissor_dynamic(1))
-        .fragment_shader(fs.entry_point("main").unwrap(), ())
-        .render_pass(subpass) // Use subpass instead of render_pass
-        .with_pipeline_layout(device.clone(), pipeline_layout)?;
-
-    // The pipeline is already built by with_pipeline_layout
-    Ok(pipeline)
-}
\ No newline at end of file
+    Ok(descriptor_set)
+}
@@ -0,0 +1,429 @@
+use anyhow::{anyhow, Result};
+use nalgebra::{Isometry3, Matrix4, Translation3, UnitQuaternion, Vector3};
+use std::sync::Arc;
+
+use crate::engine::mesh::Mesh;
+use crate::engine::render_queue::{DrawItem, Material, RenderQueue};
+
+/// A local transform: scale first, then rotation, then translation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    /// Rotation and translation relative to the parent
+    pub isometry: Isometry3<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Transform {
+    /// A transform that leaves its node where its parent is
+    pub fn identity() -> Self {
+        Self {
+            isometry: Isometry3::identity(),
+            scale: Vector3::repeat(1.0),
+        }
+    }
+
+    /// A pure translation
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Self {
+            isometry: Translation3::from(translation).into(),
+            ..Self::identity()
+        }
+    }
+
+    /// A transform built from translation, rotation and scale
+    pub fn from_trs(
+        translation: Vector3<f32>,
+        rotation: UnitQuaternion<f32>,
+        scale: Vector3<f32>,
+    ) -> Self {
+        Self {
+            isometry: Isometry3::from_parts(Translation3::from(translation), rotation),
+            scale,
+        }
+    }
+
+    /// Returns the same transform with a different rotation
+    pub fn with_rotation(self, rotation: UnitQuaternion<f32>) -> Self {
+        Self {
+            isometry: Isometry3::from_parts(self.isometry.translation, rotation),
+            ..self
+        }
+    }
+
+    /// Returns the same transform with a uniform scale
+    pub fn with_scale(self, scale: f32) -> Self {
+        Self {
+            scale: Vector3::repeat(scale),
+            ..self
+        }
+    }
+
+    /// Returns the local-to-parent matrix
+    pub fn matrix(&self) -> Matrix4<f32> {
+        self.isometry.to_homogeneous() * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+/// Handle to a node in a scene graph; stale handles of removed nodes never match a new node
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: u32,
+    generation: u32,
+}
+
+/// A node of the scene graph with an optional mesh to draw
+struct Node {
+    name: String,
+    local: Transform,
+    world: Matrix4<f32>,
+    dirty: bool,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    mesh: Option<(Arc<Mesh>, Material)>,
+}
+
+/// A hierarchy of transformed nodes
+///
+/// Changing a node's local transform or parent only marks it dirty; world matrices of the
+/// dirty nodes and their descendants are recomputed together by `update`.
+#[derive(Default)]
+pub struct SceneGraph {
+    nodes: Vec<Option<Node>>,
+    generations: Vec<u32>,
+    free: Vec<u32>,
+    roots: Vec<NodeId>,
+}
+
+impl SceneGraph {
+    /// Creates an empty scene
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a node under `parent`, or as a root when `parent` is `None`
+    pub fn add_node(
+        &mut self,
+        name: &str,
+        parent: Option<NodeId>,
+        local: Transform,
+    ) -> Result<NodeId> {
+        if let Some(parent) = parent {
+            self.node(parent)?;
+        }
+
+        let node = Node {
+            name: name.to_string(),
+            local,
+            world: Matrix4::identity(),
+            dirty: true,
+            parent,
+            children: Vec::new(),
+            mesh: None,
+        };
+
+        // Reuse the slot of a removed node if there is one
+        let id = match self.free.pop() {
+            Some(index) => {
+                self.nodes[index as usize] = Some(node);
+                NodeId {
+                    index,
+                    generation: self.generations[index as usize],
+                }
+            }
+            None => {
+                self.nodes.push(Some(node));
+                self.generations.push(0);
+                NodeId {
+                    index: self.nodes.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        };
+
+        match parent {
+            Some(parent) => self.node_mut(parent)?.children.push(id),
+            None => self.roots.push(id),
+        }
+        Ok(id)
+    }
+
+    /// Adds a node drawing `mesh` with `material`
+    pub fn add_mesh_node(
+        &mut self,
+        name: &str,
+        parent: Option<NodeId>,
+        local: Transform,
+        mesh: Arc<Mesh>,
+        material: Material,
+    ) -> Result<NodeId> {
+        let id = self.add_node(name, parent, local)?;
+        self.node_mut(id)?.mesh = Some((mesh, material));
+        Ok(id)
+    }
+
+    /// Removes a node together with all of its descendants
+    pub fn remove_node(&mut self, id: NodeId) -> Result<()> {
+        let parent = self.node(id)?.parent;
+        self.detach(id, parent)?;
+
+        // Bump the generation of every freed slot so old handles to it are rejected
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let index = id.index as usize;
+            if let Some(node) = self.nodes[index].take() {
+                self.generations[index] = self.generations[index].wrapping_add(1);
+                self.free.push(id.index);
+                stack.extend(node.children);
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves a node under a new parent, or to the roots when `parent` is `None`
+    ///
+    /// The local transform is kept, so the node moves with its new parent.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> Result<()> {
+        // Refuse to make a node a descendant of itself
+        let mut ancestor = parent;
+        while let Some(current) = ancestor {
+            if current == id {
+                return Err(anyhow!("Cannot parent node {:?} under its own descendant", id));
+            }
+            ancestor = self.node(current)?.parent;
+        }
+
+        let old_parent = self.node(id)?.parent;
+        self.detach(id, old_parent)?;
+        match parent {
+            Some(parent) => self.node_mut(parent)?.children.push(id),
+            None => self.roots.push(id),
+        }
+
+        let node = self.node_mut(id)?;
+        node.parent = parent;
+        node.dirty = true;
+        Ok(())
+    }
+
+    /// Returns the nodes without a parent, in insertion order
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    /// Returns the first node with the given name
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        let index = self
+            .nodes
+            .iter()
+            .position(|node| node.as_ref().is_some_and(|node| node.name == name))?;
+        Some(NodeId {
+            index: index as u32,
+            generation: self.generations[index],
+        })
+    }
+
+    /// Returns the parent of a node
+    pub fn parent(&self, id: NodeId) -> Result<Option<NodeId>> {
+        Ok(self.node(id)?.parent)
+    }
+
+    /// Returns the children of a node
+    pub fn children(&self, id: NodeId) -> Result<&[NodeId]> {
+        Ok(&self.node(id)?.children)
+    }
+
+    /// Returns the name of a node
+    pub fn name(&self, id: NodeId) -> Result<&str> {
+        Ok(&self.node(id)?.name)
+    }
+
+    /// Returns the local transform of a node
+    pub fn local_transform(&self, id: NodeId) -> Result<Transform> {
+        Ok(self.node(id)?.local)
+    }
+
+    /// Replaces the local transform of a node, marking it and its subtree dirty
+    pub fn set_local_transform(&mut self, id: NodeId, local: Transform) -> Result<()> {
+        let node = self.node_mut(id)?;
+        node.local = local;
+        node.dirty = true;
+        Ok(())
+    }
+
+    /// Returns the world matrix computed by the last `update`
+    pub fn world_matrix(&self, id: NodeId) -> Result<Matrix4<f32>> {
+        Ok(self.node(id)?.world)
+    }
+
+    /// Returns the mesh and material a node draws, if any
+    pub fn mesh(&self, id: NodeId) -> Result<Option<&(Arc<Mesh>, Material)>> {
+        Ok(self.node(id)?.mesh.as_ref())
+    }
+
+    /// Replaces the mesh and material a node draws, or stops it drawing with `None`
+    pub fn set_mesh(&mut self, id: NodeId, mesh: Option<(Arc<Mesh>, Material)>) -> Result<()> {
+        self.node_mut(id)?.mesh = mesh;
+        Ok(())
+    }
+
+    /// Recomputes the world matrices of dirty nodes and everything below them
+    pub fn update(&mut self) {
+        let mut stack: Vec<(NodeId, Matrix4<f32>, bool)> = self
+            .roots
+            .iter()
+            .rev()
+            .map(|&root| (root, Matrix4::identity(), false))
+            .collect();
+
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let Some(node) = self.nodes[id.index as usize].as_mut() else {
+                continue;
+            };
+
+            let changed = parent_changed || node.dirty;
+            if changed {
+                node.world = parent_world * node.local.matrix();
+                node.dirty = false;
+            }
+
+            let world = node.world;
+            stack.extend(node.children.iter().rev().map(|&child| (child, world, changed)));
+        }
+    }
+
+    /// Updates world matrices and queues a draw for every node with a mesh
+    pub fn queue_draws(&mut self, queue: &mut RenderQueue) {
+        self.update();
+
+        for node in self.nodes.iter().flatten() {
+            if let Some((mesh, material)) = &node.mesh {
+                queue.push(DrawItem {
+                    mesh: mesh.clone(),
+                    model: node.world,
+                    material: *material,
+                });
+            }
+        }
+    }
+
+    /// Returns the number of nodes
+    pub fn len(&self) -> usize {
+        self.nodes.iter().flatten().count()
+    }
+
+    /// Returns whether the scene has no nodes
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    /// Returns whether the node has been added and not removed
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.node(id).is_ok()
+    }
+
+    /// Removes a node from its parent's children or from the roots
+    fn detach(&mut self, id: NodeId, parent: Option<NodeId>) -> Result<()> {
+        let siblings = match parent {
+            Some(parent) => &mut self.node_mut(parent)?.children,
+            None => &mut self.roots,
+        };
+        siblings.retain(|&sibling| sibling != id);
+        Ok(())
+    }
+
+    fn node(&self, id: NodeId) -> Result<&Node> {
+        let index = id.index as usize;
+        self.nodes
+            .get(index)
+            .and_then(Option::as_ref)
+            .filter(|_| self.generations[index] == id.generation)
+            .ok_or_else(|| anyhow!("Scene node {:?} does not exist", id))
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> Result<&mut Node> {
+        let index = id.index as usize;
+        let generation = self.generations.get(index).copied();
+        self.nodes
+            .get_mut(index)
+            .and_then(Option::as_mut)
+            .filter(|_| generation == Some(id.generation))
+            .ok_or_else(|| anyhow!("Scene node {:?} does not exist", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translation(x: f32) -> Transform {
+        Transform::from_translation(Vector3::new(x, 0.0, 0.0))
+    }
+
+    #[test]
+    fn update_recomputes_children_only_below_changed_parents() {
+        let mut scene = SceneGraph::new();
+        let moved = scene.add_node("moved", None, translation(1.0)).unwrap();
+        let moved_child = scene.add_node("moved child", Some(moved), translation(2.0)).unwrap();
+        let still = scene.add_node("still", None, translation(5.0)).unwrap();
+        let still_child = scene.add_node("still child", Some(still), translation(1.0)).unwrap();
+        scene.update();
+        assert_eq!(scene.world_matrix(moved_child).unwrap(), translation(3.0).matrix());
+
+        // A marker in a clean subtree survives the update, showing it was not recomputed
+        let marker = Matrix4::from_element(7.0);
+        scene.node_mut(still_child).unwrap().world = marker;
+        scene.set_local_transform(moved, translation(10.0)).unwrap();
+        scene.update();
+        assert_eq!(scene.world_matrix(moved_child).unwrap(), translation(12.0).matrix());
+        assert_eq!(scene.world_matrix(still_child).unwrap(), marker);
+
+        // Moving the parent recomputes the child even though the child itself is clean
+        scene.set_local_transform(still, translation(-5.0)).unwrap();
+        scene.update();
+        assert_eq!(scene.world_matrix(still_child).unwrap(), translation(-4.0).matrix());
+    }
+
+    #[test]
+    fn reparenting_moves_the_node_with_its_new_parent() {
+        let mut scene = SceneGraph::new();
+        let parent = scene.add_node("parent", None, translation(4.0)).unwrap();
+        let node = scene.add_node("node", None, translation(1.0)).unwrap();
+        scene.update();
+
+        scene.set_parent(node, Some(parent)).unwrap();
+        scene.update();
+        assert_eq!(scene.world_matrix(node).unwrap(), translation(5.0).matrix());
+        assert_eq!(scene.roots(), &[parent]);
+        assert!(scene.set_parent(parent, Some(node)).is_err());
+    }
+
+    #[test]
+    fn stale_ids_are_rejected_after_removal() {
+        let mut scene = SceneGraph::new();
+        let parent = scene.add_node("parent", None, Transform::identity()).unwrap();
+        let child = scene.add_node("child", Some(parent), Transform::identity()).unwrap();
+        scene.remove_node(parent).unwrap();
+        assert!(!scene.contains(parent));
+        assert!(!scene.contains(child));
+        assert!(scene.is_empty());
+
+        // New nodes reuse the freed slots, but the old handles still do not match them
+        let reused = scene.add_node("reused", None, translation(1.0)).unwrap();
+        let other = scene.add_node("other", None, translation(2.0)).unwrap();
+        assert_eq!(scene.len(), 2);
+        for stale in [parent, child] {
+            assert!(scene.name(stale).is_err());
+            assert!(scene.set_local_transform(stale, translation(3.0)).is_err());
+            assert!(scene.remove_node(stale).is_err());
+        }
+        assert_eq!(scene.name(reused).unwrap(), "reused");
+        assert_eq!(scene.name(other).unwrap(), "other");
+        assert_eq!(scene.find("other"), Some(other));
+    }
+}
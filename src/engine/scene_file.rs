@@ -0,0 +1,507 @@
+use anyhow::{anyhow, Context, Result};
+use log::info;
+use nalgebra::{Point3, Quaternion, UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::engine::camera::Camera;
+use crate::engine::mesh::Mesh;
+use crate::engine::pipeline::BlendPreset;
+use crate::engine::render_queue::{Light, Material, TransparencyMode};
+use crate::engine::scene::{NodeId, SceneGraph, Transform};
+
+/// Version written to new scene files; files from newer versions are refused
+pub const SCENE_FORMAT_VERSION: u32 = 1;
+
+/// Asset path of the built-in unit cube
+pub const BUILTIN_CUBE: &str = "builtin:cube";
+
+/// Meshes that scene files refer to by asset path
+#[derive(Default)]
+pub struct MeshLibrary {
+    meshes: HashMap<String, Arc<Mesh>>,
+}
+
+impl MeshLibrary {
+    /// Creates an empty library
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a mesh under an asset path, replacing any mesh already there
+    pub fn insert(&mut self, path: &str, mesh: Arc<Mesh>) {
+        self.meshes.insert(path.to_string(), mesh);
+    }
+
+    /// Returns the mesh registered under an asset path
+    pub fn get(&self, path: &str) -> Result<Arc<Mesh>> {
+        self.meshes
+            .get(path)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unknown mesh asset `{}`", path))
+    }
+
+    /// Returns the asset path a mesh was registered under
+    pub fn path_of(&self, mesh: &Arc<Mesh>) -> Option<&str> {
+        self.meshes
+            .iter()
+            .find(|(_, other)| Arc::ptr_eq(other, mesh))
+            .map(|(path, _)| path.as_str())
+    }
+}
+
+/// A scene as stored on disk
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneFile {
+    /// Format version the file was written with
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera: Option<CameraDesc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lights: Vec<LightDesc>,
+    #[serde(default)]
+    pub nodes: Vec<NodeDesc>,
+}
+
+/// A node with its children nested inside it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeDesc {
+    pub name: String,
+    #[serde(default)]
+    pub transform: TransformDesc,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh: Option<MeshDesc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<NodeDesc>,
+}
+
+/// A local transform; the rotation is a quaternion in `[x, y, z, w]` order
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransformDesc {
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl Default for TransformDesc {
+    fn default() -> Self {
+        Self {
+            translation: [0.0; 3],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            scale: [1.0; 3],
+        }
+    }
+}
+
+impl From<Transform> for TransformDesc {
+    fn from(transform: Transform) -> Self {
+        let rotation = transform.isometry.rotation.coords;
+        Self {
+            translation: transform.isometry.translation.vector.into(),
+            rotation: [rotation.x, rotation.y, rotation.z, rotation.w],
+            scale: transform.scale.into(),
+        }
+    }
+}
+
+impl From<TransformDesc> for Transform {
+    fn from(desc: TransformDesc) -> Self {
+        let [x, y, z, w] = desc.rotation;
+        Transform::from_trs(
+            desc.translation.into(),
+            UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z)),
+            desc.scale.into(),
+        )
+    }
+}
+
+/// A mesh asset drawn with a material
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MeshDesc {
+    /// Path the mesh is registered under in the `MeshLibrary`
+    pub asset: String,
+    #[serde(default)]
+    pub material: MaterialDesc,
+}
+
+/// Material settings as stored on disk
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaterialDesc {
+    pub color: [f32; 4],
+    pub blend: BlendPreset,
+    pub transparency: TransparencyMode,
+}
+
+impl Default for MaterialDesc {
+    fn default() -> Self {
+        Material::default().into()
+    }
+}
+
+impl From<Material> for MaterialDesc {
+    fn from(material: Material) -> Self {
+        Self {
+            color: material.color,
+            blend: material.blend,
+            transparency: material.transparency,
+        }
+    }
+}
+
+impl From<MaterialDesc> for Material {
+    fn from(desc: MaterialDesc) -> Self {
+        Self {
+            color: desc.color,
+            blend: desc.blend,
+            transparency: desc.transparency,
+        }
+    }
+}
+
+/// A camera looking from `position` towards `target` with +Y up
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CameraDesc {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    #[serde(default = "default_fov_y_degrees")]
+    pub fov_y_degrees: f32,
+    #[serde(default = "default_near")]
+    pub near: f32,
+    #[serde(default = "default_far")]
+    pub far: f32,
+}
+
+fn default_fov_y_degrees() -> f32 {
+    Camera::default().fov_y.to_degrees()
+}
+
+fn default_near() -> f32 {
+    Camera::default().near
+}
+
+fn default_far() -> f32 {
+    Camera::default().far
+}
+
+impl From<Camera> for CameraDesc {
+    fn from(camera: Camera) -> Self {
+        let position = camera.position();
+        let forward = camera.transform.rotation * -Vector3::z();
+        Self {
+            position: position.coords.into(),
+            target: (position + forward).coords.into(),
+            fov_y_degrees: camera.fov_y.to_degrees(),
+            near: camera.near,
+            far: camera.far,
+        }
+    }
+}
+
+impl From<CameraDesc> for Camera {
+    fn from(desc: CameraDesc) -> Self {
+        Self {
+            fov_y: desc.fov_y_degrees.to_radians(),
+            near: desc.near,
+            far: desc.far,
+            ..Camera::look_at(
+                Point3::from(desc.position),
+                Point3::from(desc.target),
+                Vector3::y(),
+            )
+        }
+    }
+}
+
+/// A directional light as stored on disk
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LightDesc {
+    /// Direction the light travels in world space
+    pub direction: [f32; 3],
+    #[serde(default = "default_light_color")]
+    pub color: [f32; 3],
+    #[serde(default = "default_light_intensity")]
+    pub intensity: f32,
+}
+
+fn default_light_color() -> [f32; 3] {
+    Light::default().color
+}
+
+fn default_light_intensity() -> f32 {
+    Light::default().intensity
+}
+
+impl From<Light> for LightDesc {
+    fn from(light: Light) -> Self {
+        Self {
+            direction: light.direction.into(),
+            color: light.color,
+            intensity: light.intensity,
+        }
+    }
+}
+
+impl From<LightDesc> for Light {
+    fn from(desc: LightDesc) -> Self {
+        Self {
+            direction: Vector3::from(desc.direction).normalize(),
+            color: desc.color,
+            intensity: desc.intensity,
+        }
+    }
+}
+
+/// Everything a scene file adds to a running application
+pub struct LoadedScene {
+    pub scene: SceneGraph,
+    pub camera: Option<Camera>,
+    pub lights: Vec<Light>,
+}
+
+impl SceneFile {
+    /// Creates an empty scene of the current version
+    pub fn new() -> Self {
+        Self {
+            version: SCENE_FORMAT_VERSION,
+            camera: None,
+            lights: Vec::new(),
+            nodes: Vec::new(),
+        }
+    }
+
+    /// Parses a scene from JSON, checking its version
+    pub fn from_json(json: &str) -> Result<Self> {
+        let file: Self = serde_json::from_str(json)?;
+        if file.version == 0 || file.version > SCENE_FORMAT_VERSION {
+            return Err(anyhow!(
+                "Unsupported scene format version {} (this build reads up to {})",
+                file.version,
+                SCENE_FORMAT_VERSION
+            ));
+        }
+        Ok(file)
+    }
+
+    /// Writes the scene as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Reads a scene file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read scene {}", path.display()))?;
+        let file = Self::from_json(&json)
+            .with_context(|| format!("Failed to parse scene {}", path.display()))?;
+
+        info!("Loaded scene {} with {} root node(s)", path.display(), file.nodes.len());
+        Ok(file)
+    }
+
+    /// Writes the scene to a file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_json()?)?;
+
+        info!("Saved scene to {}", path.display());
+        Ok(())
+    }
+
+    /// Describes a scene graph, looking up the asset path of every mesh in `library`
+    pub fn from_scene(
+        scene: &SceneGraph,
+        library: &MeshLibrary,
+        camera: Option<Camera>,
+        lights: &[Light],
+    ) -> Result<Self> {
+        let nodes = scene
+            .roots()
+            .iter()
+            .map(|&root| describe_node(scene, library, root))
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            camera: camera.map(CameraDesc::from),
+            lights: lights.iter().copied().map(LightDesc::from).collect(),
+            nodes,
+            ..Self::new()
+        })
+    }
+
+    /// Builds a scene graph from the file, resolving meshes through `library`
+    pub fn instantiate(&self, library: &MeshLibrary) -> Result<LoadedScene> {
+        let mut scene = SceneGraph::new();
+        let mut stack: Vec<(&NodeDesc, Option<NodeId>)> =
+            self.nodes.iter().rev().map(|node| (node, None)).collect();
+
+        while let Some((desc, parent)) = stack.pop() {
+            let transform = Transform::from(desc.transform);
+            let id = match &desc.mesh {
+                Some(mesh) => scene.add_mesh_node(
+                    &desc.name,
+                    parent,
+                    transform,
+                    library.get(&mesh.asset)?,
+                    mesh.material.into(),
+                )?,
+                None => scene.add_node(&desc.name, parent, transform)?,
+            };
+            stack.extend(desc.children.iter().rev().map(|child| (child, Some(id))));
+        }
+
+        Ok(LoadedScene {
+            scene,
+            camera: self.camera.map(Camera::from),
+            lights: self.lights.iter().copied().map(Light::from).collect(),
+        })
+    }
+}
+
+impl Default for SceneFile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Describes a node and its subtree
+fn describe_node(scene: &SceneGraph, library: &MeshLibrary, id: NodeId) -> Result<NodeDesc> {
+    let name = scene.name(id)?;
+    let mesh = match scene.mesh(id)? {
+        Some((mesh, material)) => {
+            let asset = library
+                .path_of(mesh)
+                .ok_or_else(|| anyhow!("Node `{}` draws a mesh with no asset path", name))?;
+            Some(MeshDesc {
+                asset: asset.to_string(),
+                material: (*material).into(),
+            })
+        }
+        None => None,
+    };
+    let children = scene
+        .children(id)?
+        .iter()
+        .map(|&child| describe_node(scene, library, child))
+        .collect::<Result<_>>()?;
+
+    Ok(NodeDesc {
+        name: name.to_string(),
+        transform: scene.local_transform(id)?.into(),
+        mesh,
+        children,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_scene() -> SceneFile {
+        SceneFile {
+            camera: Some(CameraDesc {
+                position: [0.0, 1.5, 4.0],
+                target: [0.0, 0.0, 0.0],
+                fov_y_degrees: 45.0,
+                near: 0.1,
+                far: 100.0,
+            }),
+            lights: vec![LightDesc {
+                direction: [-1.0, -1.0, -1.0],
+                color: [1.0, 0.9, 0.8],
+                intensity: 2.0,
+            }],
+            nodes: vec![NodeDesc {
+                name: "parent".to_string(),
+                transform: TransformDesc {
+                    translation: [1.0, 2.0, 3.0],
+                    rotation: [0.0, 0.382_683_43, 0.0, 0.923_879_5],
+                    scale: [2.0, 2.0, 2.0],
+                },
+                mesh: Some(MeshDesc {
+                    asset: BUILTIN_CUBE.to_string(),
+                    material: Material::blended([0.2, 0.4, 0.9, 0.5], BlendPreset::Alpha).into(),
+                }),
+                children: vec![NodeDesc {
+                    name: "child".to_string(),
+                    transform: TransformDesc::default(),
+                    mesh: None,
+                    children: Vec::new(),
+                }],
+            }],
+            ..SceneFile::new()
+        }
+    }
+
+    #[test]
+    fn json_round_trip_preserves_scene() {
+        let scene = sample_scene();
+        let json = scene.to_json().unwrap();
+        assert_eq!(SceneFile::from_json(&json).unwrap(), scene);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let path = std::env::temp_dir().join(format!("scene-{}.json", std::process::id()));
+        let scene = sample_scene();
+        scene.save(&path).unwrap();
+        let loaded = SceneFile::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), scene);
+    }
+
+    #[test]
+    fn missing_fields_use_defaults() {
+        let json = r#"{ "version": 1, "nodes": [{ "name": "empty" }] }"#;
+        let scene = SceneFile::from_json(json).unwrap();
+        assert_eq!(scene.camera, None);
+        assert!(scene.lights.is_empty());
+        assert_eq!(scene.nodes[0].transform, TransformDesc::default());
+        assert_eq!(scene.nodes[0].mesh, None);
+    }
+
+    #[test]
+    fn unsupported_versions_are_rejected() {
+        let newer = format!(r#"{{ "version": {} }}"#, SCENE_FORMAT_VERSION + 1);
+        assert!(SceneFile::from_json(&newer).is_err());
+        assert!(SceneFile::from_json(r#"{ "version": 0 }"#).is_err());
+        assert!(SceneFile::from_json(r#"{ "nodes": [] }"#).is_err());
+    }
+
+    #[test]
+    fn transform_conversion_round_trips() {
+        let desc = sample_scene().nodes[0].transform;
+        let back = TransformDesc::from(Transform::from(desc));
+        for (a, b) in desc.rotation.iter().zip(back.rotation) {
+            assert!((a - b).abs() < 1e-6);
+        }
+        assert_eq!(back.translation, desc.translation);
+        assert_eq!(back.scale, desc.scale);
+    }
+
+    #[test]
+    fn camera_conversion_keeps_view_direction() {
+        let desc = sample_scene().camera.unwrap();
+        let back = CameraDesc::from(Camera::from(desc));
+        let direction = |desc: &CameraDesc| {
+            (Vector3::from(desc.target) - Vector3::from(desc.position)).normalize()
+        };
+        assert!((direction(&back) - direction(&desc)).norm() < 1e-5);
+        assert!((Vector3::from(back.position) - Vector3::from(desc.position)).norm() < 1e-5);
+        assert!((back.fov_y_degrees - desc.fov_y_degrees).abs() < 1e-4);
+    }
+
+    #[test]
+    fn demo_scene_parses() {
+        let scene = SceneFile::from_json(include_str!("../../scenes/demo.json")).unwrap();
+        assert!(!scene.nodes.is_empty());
+    }
+}
@@ -7,65 +7,144 @@ use std::io::Read;
 use std::path::Path;
 
 /// Loads a shader from a file
+///
+/// The source language is detected from the file extension and the entry point is `main`.
 pub fn load_shader(
     device: Arc<Device>,
     shader_type: ShaderType,
     path: &str,
+) -> Result<Arc<ShaderModule>> {
+    load_shader_with_options(device, shader_type, path, &ShaderSourceOptions::default())
+}
+
+/// Loads a shader from a file with an explicit source language and entry point
+pub fn load_shader_with_options(
+    device: Arc<Device>,
+    shader_type: ShaderType,
+    path: &str,
+    options: &ShaderSourceOptions,
 ) -> Result<Arc<ShaderModule>> {
     // Read the shader file
     let mut file = File::open(Path::new(path))?;
     let mut shader_code = String::new();
     file.read_to_string(&mut shader_code)?;
 
-    // Create the shader module
-    // Convert GLSL to SPIR-V using shaderc
-    let mut compiler = shaderc::Compiler::new().ok_or_else(|| anyhow::anyhow!("Failed to create shader compiler"))?;
-    let binary = match shader_type {
-        ShaderType::Vertex => {
-            let binary = compiler.compile_into_spirv(
-                &shader_code,
-                shaderc::ShaderKind::Vertex,
-                path,
-                "main",
-                None,
-            )?;
-            binary.as_binary_u8().to_vec()
-        },
-        ShaderType::Fragment => {
-            let binary = compiler.compile_into_spirv(
-                &shader_code,
-                shaderc::ShaderKind::Fragment,
-                path,
-                "main",
-                None,
-            )?;
-            binary.as_binary_u8().to_vec()
-        },
-        ShaderType::Compute => {
-            let binary = compiler.compile_into_spirv(
-                &shader_code,
-                shaderc::ShaderKind::Compute,
-                path,
-                "main",
-                None,
-            )?;
-            binary.as_binary_u8().to_vec()
-        },
-    };
+    let binary = compile_to_spirv(&shader_code, shader_type, path, options)?;
 
     // Create the shader module from SPIR-V
     let shader_module = unsafe {
-        ShaderModule::from_bytes(device, &binary)?
+        ShaderModule::from_words(device, &binary)?
     };
 
     Ok(shader_module)
 }
 
+/// Compiles GLSL or HLSL source to SPIR-V words
+///
+/// `name` is used in compiler messages and to detect the language when `options` leaves it unset.
+fn compile_to_spirv(
+    source: &str,
+    shader_type: ShaderType,
+    name: &str,
+    options: &ShaderSourceOptions,
+) -> Result<Vec<u32>> {
+    let language = options
+        .language
+        .unwrap_or_else(|| ShaderLanguage::from_path(name));
+    let entry_point = options.entry_point.as_deref().unwrap_or("main");
+
+    // Convert GLSL or HLSL to SPIR-V using shaderc
+    let compiler = shaderc::Compiler::new().ok_or_else(|| anyhow::anyhow!("Failed to create shader compiler"))?;
+    let mut compile_options = shaderc::CompileOptions::new()
+        .ok_or_else(|| anyhow::anyhow!("Failed to create shader compile options"))?;
+    match language {
+        ShaderLanguage::Glsl => {
+            compile_options.set_source_language(shaderc::SourceLanguage::GLSL);
+        }
+        ShaderLanguage::Hlsl => {
+            compile_options.set_source_language(shaderc::SourceLanguage::HLSL);
+            // Map `register(bN, spaceM)` onto set M, binding N
+            compile_options.set_hlsl_io_mapping(true);
+        }
+    }
+
+    let binary = compiler.compile_into_spirv(
+        source,
+        shader_type.shader_kind(),
+        name,
+        entry_point,
+        Some(&compile_options),
+    )?;
+
+    Ok(binary.as_binary().to_vec())
+}
+
 /// Shader types
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ShaderType {
     Vertex,
     Fragment,
     Compute,
+    Geometry,
+    TessellationControl,
+    TessellationEvaluation,
+}
+
+impl ShaderType {
+    /// Returns the shaderc kind used to compile this stage
+    fn shader_kind(self) -> shaderc::ShaderKind {
+        match self {
+            ShaderType::Vertex => shaderc::ShaderKind::Vertex,
+            ShaderType::Fragment => shaderc::ShaderKind::Fragment,
+            ShaderType::Compute => shaderc::ShaderKind::Compute,
+            ShaderType::Geometry => shaderc::ShaderKind::Geometry,
+            ShaderType::TessellationControl => shaderc::ShaderKind::TessControl,
+            ShaderType::TessellationEvaluation => shaderc::ShaderKind::TessEvaluation,
+        }
+    }
+}
+
+/// Source languages accepted by the shader loader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderLanguage {
+    Glsl,
+    Hlsl,
+}
+
+impl ShaderLanguage {
+    /// Detects the source language from a file extension, defaulting to GLSL
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("hlsl") | Some("hlsli") | Some("fx") => ShaderLanguage::Hlsl,
+            _ => ShaderLanguage::Glsl,
+        }
+    }
+}
+
+/// Options controlling how a shader source file is compiled
+#[derive(Debug, Clone, Default)]
+pub struct ShaderSourceOptions {
+    /// Source language, or `None` to detect it from the file extension
+    pub language: Option<ShaderLanguage>,
+    /// Entry point name, or `None` for `main`
+    ///
+    /// The SPIR-V module keeps this name, so look it up with the same name via `entry_point`.
+    pub entry_point: Option<String>,
+}
+
+impl ShaderSourceOptions {
+    /// Options for an HLSL source with the given entry point
+    pub fn hlsl(entry_point: &str) -> Self {
+        Self {
+            language: Some(ShaderLanguage::Hlsl),
+            entry_point: Some(entry_point.to_string()),
+        }
+    }
 }
 
 /// A struct to manage shader modules
@@ -73,6 +152,9 @@ pub struct ShaderManager {
     vertex_shader: Option<Arc<ShaderModule>>,
     fragment_shader: Option<Arc<ShaderModule>>,
     compute_shader: Option<Arc<ShaderModule>>,
+    geometry_shader: Option<Arc<ShaderModule>>,
+    tessellation_control_shader: Option<Arc<ShaderModule>>,
+    tessellation_evaluation_shader: Option<Arc<ShaderModule>>,
 }
 
 impl ShaderManager {
@@ -82,6 +164,9 @@ impl ShaderManager {
             vertex_shader: None,
             fragment_shader: None,
             compute_shader: None,
+            geometry_shader: None,
+            tessellation_control_shader: None,
+            tessellation_evaluation_shader: None,
         }
     }
 
@@ -106,6 +191,58 @@ impl ShaderManager {
         Ok(shader)
     }
 
+    /// Loads a geometry shader
+    pub fn load_geometry_shader(&mut self, device: Arc<Device>, path: &str) -> Result<Arc<ShaderModule>> {
+        let shader = load_shader(device, ShaderType::Geometry, path)?;
+        self.geometry_shader = Some(shader.clone());
+        Ok(shader)
+    }
+
+    /// Loads a tessellation control shader
+    pub fn load_tessellation_control_shader(&mut self, device: Arc<Device>, path: &str) -> Result<Arc<ShaderModule>> {
+        let shader = load_shader(device, ShaderType::TessellationControl, path)?;
+        self.tessellation_control_shader = Some(shader.clone());
+        Ok(shader)
+    }
+
+    /// Loads a tessellation evaluation shader
+    pub fn load_tessellation_evaluation_shader(&mut self, device: Arc<Device>, path: &str) -> Result<Arc<ShaderModule>> {
+        let shader = load_shader(device, ShaderType::TessellationEvaluation, path)?;
+        self.tessellation_evaluation_shader = Some(shader.clone());
+        Ok(shader)
+    }
+
+    /// Loads a shader of any stage with explicit language and entry point options
+    pub fn load_shader_with_options(
+        &mut self,
+        device: Arc<Device>,
+        shader_type: ShaderType,
+        path: &str,
+        options: &ShaderSourceOptions,
+    ) -> Result<Arc<ShaderModule>> {
+        let shader = load_shader_with_options(device, shader_type, path, options)?;
+        let slot = match shader_type {
+            ShaderType::Vertex => &mut self.vertex_shader,
+            ShaderType::Fragment => &mut self.fragment_shader,
+            ShaderType::Compute => &mut self.compute_shader,
+            ShaderType::Geometry => &mut self.geometry_shader,
+            ShaderType::TessellationControl => &mut self.tessellation_control_shader,
+            ShaderType::TessellationEvaluation => &mut self.tessellation_evaluation_shader,
+        };
+        *slot = Some(shader.clone());
+        Ok(shader)
+    }
+
+    /// Loads the engine's default vertex and fragment shaders from `shaders/` at runtime
+    ///
+    /// These are the same files the compile-time `shader!` modules are generated from, so calling
+    /// this again after editing them picks up the changes without rebuilding.
+    pub fn load_default_shaders(&mut self, device: Arc<Device>) -> Result<()> {
+        self.load_vertex_shader(device.clone(), crate::shaders::vertex::PATH)?;
+        self.load_fragment_shader(device, crate::shaders::fragment::PATH)?;
+        Ok(())
+    }
+
     /// Gets the vertex shader
     pub fn get_vertex_shader(&self) -> Option<Arc<ShaderModule>> {
         self.vertex_shader.clone()
@@ -121,11 +258,29 @@ impl ShaderManager {
         self.compute_shader.clone()
     }
 
+    /// Gets the geometry shader
+    pub fn get_geometry_shader(&self) -> Option<Arc<ShaderModule>> {
+        self.geometry_shader.clone()
+    }
+
+    /// Gets the tessellation control shader
+    pub fn get_tessellation_control_shader(&self) -> Option<Arc<ShaderModule>> {
+        self.tessellation_control_shader.clone()
+    }
+
+    /// Gets the tessellation evaluation shader
+    pub fn get_tessellation_evaluation_shader(&self) -> Option<Arc<ShaderModule>> {
+        self.tessellation_evaluation_shader.clone()
+    }
+
     /// Unloads all shaders
     pub fn unload_all(&mut self) {
         self.vertex_shader = None;
         self.fragment_shader = None;
         self.compute_shader = None;
+        self.geometry_shader = None;
+        self.tessellation_control_shader = None;
+        self.tessellation_evaluation_shader = None;
     }
 
     /// Unloads the vertex shader
@@ -142,6 +297,17 @@ impl ShaderManager {
     pub fn unload_compute_shader(&mut self) {
         self.compute_shader = None;
     }
+
+    /// Unloads the geometry shader
+    pub fn unload_geometry_shader(&mut self) {
+        self.geometry_shader = None;
+    }
+
+    /// Unloads the tessellation control and evaluation shaders
+    pub fn unload_tessellation_shaders(&mut self) {
+        self.tessellation_control_shader = None;
+        self.tessellation_evaluation_shader = None;
+    }
 }
 
 impl Drop for ShaderManager {
@@ -150,3 +316,55 @@ impl Drop for ShaderManager {
         self.unload_all();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn language_is_detected_from_the_extension() {
+        assert_eq!(ShaderLanguage::from_path("shaders/blur.hlsl"), ShaderLanguage::Hlsl);
+        assert_eq!(ShaderLanguage::from_path("shaders/common.HLSLI"), ShaderLanguage::Hlsl);
+        assert_eq!(ShaderLanguage::from_path("shaders/lit.fx"), ShaderLanguage::Hlsl);
+        assert_eq!(ShaderLanguage::from_path("shaders/lit.glsl"), ShaderLanguage::Glsl);
+        assert_eq!(ShaderLanguage::from_path("shaders/vertex.vert"), ShaderLanguage::Glsl);
+        assert_eq!(ShaderLanguage::from_path("shaders/fragment.frag"), ShaderLanguage::Glsl);
+        assert_eq!(ShaderLanguage::from_path("shaders/cull.comp"), ShaderLanguage::Glsl);
+        assert_eq!(ShaderLanguage::from_path("shaders/notes.txt"), ShaderLanguage::Glsl);
+        assert_eq!(ShaderLanguage::from_path("shaders/noext"), ShaderLanguage::Glsl);
+    }
+
+    // Needs a working shaderc library; run with `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn hlsl_compiles_with_a_custom_entry_point() {
+        let source = r#"
+            RWStructuredBuffer<float> values : register(u0, space0);
+
+            [numthreads(64, 1, 1)]
+            void CSMain(uint3 id : SV_DispatchThreadID) {
+                values[id.x] *= 2.0;
+            }
+        "#;
+        let words = compile_to_spirv(
+            source,
+            ShaderType::Compute,
+            "double.hlsl",
+            &ShaderSourceOptions::hlsl("CSMain"),
+        )
+        .unwrap();
+
+        // SPIR-V magic number, then the entry point name as a nul-terminated literal string
+        assert_eq!(words[0], 0x0723_0203);
+        let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        assert!(bytes.windows(7).any(|window| window == b"CSMain\0"));
+
+        let missing = compile_to_spirv(
+            source,
+            ShaderType::Compute,
+            "double.hlsl",
+            &ShaderSourceOptions::hlsl("main"),
+        );
+        assert!(missing.is_err());
+    }
+}
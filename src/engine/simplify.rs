@@ -0,0 +1,480 @@
+use nalgebra::{Matrix4, Point3, Vector3, Vector4};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::engine::buffer::Vertex;
+
+/// Weight of the planes that keep open borders in place, relative to surface planes
+const BORDER_WEIGHT: f64 = 10.0;
+
+/// Indices of a simplified mesh and the error it was simplified to
+#[derive(Debug, Clone, Default)]
+pub struct Simplified {
+    /// Triangles referring to the original vertices
+    pub indices: Vec<u32>,
+    /// Largest collapse error, roughly the distance the surface moved in model units
+    pub error: f32,
+}
+
+/// Weighted sum of squared distances to a set of planes, as a symmetric 4x4 matrix
+#[derive(Clone, Copy)]
+struct Quadric {
+    matrix: Matrix4<f64>,
+    weight: f64,
+}
+
+impl Quadric {
+    fn zero() -> Self {
+        Self {
+            matrix: Matrix4::zeros(),
+            weight: 0.0,
+        }
+    }
+
+    /// Quadric of the plane through `point` with unit `normal`
+    fn plane(normal: Vector3<f64>, point: &Point3<f64>, weight: f64) -> Self {
+        let plane = Vector4::new(normal.x, normal.y, normal.z, -normal.dot(&point.coords));
+        Self {
+            matrix: plane * plane.transpose() * weight,
+            weight,
+        }
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.matrix += other.matrix;
+        self.weight += other.weight;
+    }
+
+    /// Returns the weighted mean of the squared distances from `point` to the planes
+    fn error(&self, point: &Point3<f64>) -> f64 {
+        if self.weight <= 0.0 {
+            return 0.0;
+        }
+        let point = point.to_homogeneous();
+        (point.transpose() * self.matrix * point)[0].max(0.0) / self.weight
+    }
+}
+
+/// A candidate collapse of one position into a neighbouring one, cheapest first in the heap
+struct Collapse {
+    cost: f64,
+    from: usize,
+    to: usize,
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+/// Working state of a simplification: triangles over welded positions
+struct Simplifier<'a> {
+    vertices: &'a [Vertex],
+    /// Position group of every vertex; vertices split only by normals or UVs share a group
+    group_of: Vec<usize>,
+    members: Vec<Vec<u32>>,
+    positions: Vec<Point3<f64>>,
+    quadrics: Vec<Quadric>,
+    /// Group each group was collapsed into, or itself while it is still alive
+    parent: Vec<usize>,
+    versions: Vec<u32>,
+    /// Triangles touching each group, including ones removed since
+    adjacency: Vec<Vec<usize>>,
+    triangles: Vec<[u32; 3]>,
+    alive: Vec<bool>,
+    live_triangles: usize,
+}
+
+impl<'a> Simplifier<'a> {
+    fn new(vertices: &'a [Vertex], indices: &[u32]) -> Self {
+        let mut group_by_position = HashMap::new();
+        let mut group_of = Vec::with_capacity(vertices.len());
+        let mut members: Vec<Vec<u32>> = Vec::new();
+        let mut positions = Vec::new();
+        for (index, vertex) in vertices.iter().enumerate() {
+            let key = vertex.position.map(f32::to_bits);
+            let group = *group_by_position.entry(key).or_insert_with(|| {
+                members.push(Vec::new());
+                positions.push(Point3::from(vertex.position).cast::<f64>());
+                members.len() - 1
+            });
+            group_of.push(group);
+            members[group].push(index as u32);
+        }
+
+        let group_count = members.len();
+        let mut simplifier = Self {
+            vertices,
+            group_of,
+            members,
+            positions,
+            quadrics: vec![Quadric::zero(); group_count],
+            parent: (0..group_count).collect(),
+            versions: vec![0; group_count],
+            adjacency: vec![Vec::new(); group_count],
+            triangles: Vec::new(),
+            alive: Vec::new(),
+            live_triangles: 0,
+        };
+
+        for triangle in indices.chunks_exact(3) {
+            let triangle = [triangle[0], triangle[1], triangle[2]];
+            let groups = triangle.map(|index| simplifier.group_of[index as usize]);
+            if groups[0] == groups[1] || groups[1] == groups[2] || groups[0] == groups[2] {
+                continue;
+            }
+            let id = simplifier.triangles.len();
+            for group in groups {
+                simplifier.adjacency[group].push(id);
+            }
+            simplifier.triangles.push(triangle);
+            simplifier.alive.push(true);
+            simplifier.live_triangles += 1;
+        }
+
+        simplifier.accumulate_quadrics();
+        simplifier
+    }
+
+    /// Adds the area-weighted plane of every triangle, and planes standing on open border edges
+    fn accumulate_quadrics(&mut self) {
+        let mut edge_uses: HashMap<(usize, usize), usize> = HashMap::new();
+        for id in 0..self.triangles.len() {
+            let groups = self.groups(id);
+            for corner in 0..3 {
+                let (a, b) = (groups[corner], groups[(corner + 1) % 3]);
+                *edge_uses.entry((a.min(b), a.max(b))).or_default() += 1;
+            }
+        }
+
+        for id in 0..self.triangles.len() {
+            let groups = self.groups(id);
+            let cross = self.cross(groups);
+            let area = cross.norm() * 0.5;
+            let Some(normal) = cross.try_normalize(f64::EPSILON) else {
+                continue;
+            };
+            let plane = Quadric::plane(normal, &self.positions[groups[0]], area);
+            for group in groups {
+                self.quadrics[group].add(&plane);
+            }
+
+            for corner in 0..3 {
+                let (a, b) = (groups[corner], groups[(corner + 1) % 3]);
+                if edge_uses[&(a.min(b), a.max(b))] != 1 {
+                    continue;
+                }
+                let edge = self.positions[b] - self.positions[a];
+                let border_normal = edge.cross(&normal);
+                let Some(border_normal) = border_normal.try_normalize(f64::EPSILON) else {
+                    continue;
+                };
+                let weight = BORDER_WEIGHT * edge.norm_squared();
+                let border = Quadric::plane(border_normal, &self.positions[a], weight);
+                self.quadrics[a].add(&border);
+                self.quadrics[b].add(&border);
+            }
+        }
+    }
+
+    /// Returns the live group a group was collapsed into
+    fn find(&self, mut group: usize) -> usize {
+        while self.parent[group] != group {
+            group = self.parent[group];
+        }
+        group
+    }
+
+    fn groups(&self, triangle: usize) -> [usize; 3] {
+        self.triangles[triangle].map(|index| self.find(self.group_of[index as usize]))
+    }
+
+    /// Returns the unit normal of a triangle over groups, or `None` if it has no area
+    fn normal(&self, groups: [usize; 3]) -> Option<Vector3<f64>> {
+        self.cross(groups).try_normalize(f64::EPSILON)
+    }
+
+    /// Returns the cross product of two triangle edges, twice the area in length
+    fn cross(&self, groups: [usize; 3]) -> Vector3<f64> {
+        let [a, b, c] = groups.map(|group| self.positions[group]);
+        (b - a).cross(&(c - a))
+    }
+
+    /// Returns the cheaper direction of collapsing the edge between two groups
+    fn candidate(&self, a: usize, b: usize) -> Collapse {
+        let mut quadric = self.quadrics[a];
+        quadric.add(&self.quadrics[b]);
+        let (cost_into_b, cost_into_a) = (
+            quadric.error(&self.positions[b]),
+            quadric.error(&self.positions[a]),
+        );
+        let (from, to, cost) = if cost_into_b <= cost_into_a {
+            (a, b, cost_into_b)
+        } else {
+            (b, a, cost_into_a)
+        };
+        Collapse {
+            cost,
+            from,
+            to,
+            versions: (self.versions[from], self.versions[to]),
+        }
+    }
+
+    /// Returns the live groups sharing a live triangle with `group`
+    fn neighbours(&self, group: usize) -> Vec<usize> {
+        let mut neighbours: Vec<usize> = self.adjacency[group]
+            .iter()
+            .filter(|&&id| self.alive[id])
+            .flat_map(|&id| self.groups(id))
+            .filter(|&other| other != group)
+            .collect();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        neighbours
+    }
+
+    /// Returns whether moving `from` onto `to` keeps every remaining triangle facing the same way
+    fn keeps_orientation(&self, from: usize, to: usize) -> bool {
+        self.adjacency[from]
+            .iter()
+            .filter(|&&id| self.alive[id])
+            .all(|&id| {
+                let groups = self.groups(id);
+                if groups.contains(&to) {
+                    return true;
+                }
+                let moved = groups.map(|group| if group == from { to } else { group });
+                match (self.normal(groups), self.normal(moved)) {
+                    (Some(before), Some(after)) => before.dot(&after) > 0.2,
+                    _ => false,
+                }
+            })
+    }
+
+    /// Moves `from` onto `to`, removing the triangles that become degenerate
+    fn collapse(&mut self, from: usize, to: usize) {
+        self.parent[from] = to;
+        let quadric = self.quadrics[from];
+        self.quadrics[to].add(&quadric);
+        self.versions[to] += 1;
+
+        let moved = std::mem::take(&mut self.adjacency[from]);
+        for &id in &moved {
+            if !self.alive[id] {
+                continue;
+            }
+            let [a, b, c] = self.groups(id);
+            if a == b || b == c || a == c {
+                self.alive[id] = false;
+                self.live_triangles -= 1;
+            }
+        }
+        self.adjacency[to].extend(moved);
+        self.adjacency[to].retain(|&id| self.alive[id]);
+    }
+
+    /// Collapses the cheapest edges until the target triangle count or error is reached
+    fn run(&mut self, target_triangles: usize, max_error: f64) -> f64 {
+        let mut heap = BinaryHeap::new();
+        for group in 0..self.members.len() {
+            for neighbour in self.neighbours(group) {
+                if group < neighbour {
+                    heap.push(self.candidate(group, neighbour));
+                }
+            }
+        }
+
+        let max_cost = max_error * max_error;
+        let mut reached = 0.0f64;
+        while self.live_triangles > target_triangles {
+            let Some(collapse) = heap.pop() else {
+                break;
+            };
+            let (from, to) = (collapse.from, collapse.to);
+            let stale = self.parent[from] != from
+                || self.parent[to] != to
+                || collapse.versions != (self.versions[from], self.versions[to]);
+            if stale {
+                continue;
+            }
+            if collapse.cost > max_cost {
+                break;
+            }
+            if !self.keeps_orientation(from, to) {
+                continue;
+            }
+
+            self.collapse(from, to);
+            reached = reached.max(collapse.cost);
+            for neighbour in self.neighbours(to) {
+                heap.push(self.candidate(to, neighbour));
+            }
+        }
+        reached.sqrt()
+    }
+
+    /// Returns the vertex of `group` that best matches the attributes of vertex `index`
+    fn closest_member(&self, group: usize, index: u32) -> u32 {
+        let vertex = &self.vertices[index as usize];
+        let distance = |candidate: &u32| {
+            let other = &self.vertices[*candidate as usize];
+            let normal = Vector3::from(vertex.normal).dot(&Vector3::from(other.normal));
+            let uv = (0..2)
+                .map(|axis| (vertex.tex_coords[axis] - other.tex_coords[axis]).powi(2))
+                .sum::<f32>();
+            uv - normal
+        };
+        self.members[group]
+            .iter()
+            .copied()
+            .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+            .unwrap_or(index)
+    }
+
+    /// Returns the remaining triangles, with collapsed corners moved to the surviving vertices
+    fn indices(&self) -> Vec<u32> {
+        let mut indices = Vec::with_capacity(self.live_triangles * 3);
+        for (id, triangle) in self.triangles.iter().enumerate() {
+            if !self.alive[id] {
+                continue;
+            }
+            for &index in triangle {
+                let original = self.group_of[index as usize];
+                let group = self.find(original);
+                indices.push(if group == original {
+                    index
+                } else {
+                    self.closest_member(group, index)
+                });
+            }
+        }
+        indices
+    }
+}
+
+/// Simplifies a triangle mesh with quadric error metrics
+///
+/// Repeatedly collapses the edge whose removal moves the surface least until at most
+/// `target_index_count` indices remain or the next collapse would move the surface further
+/// than `max_error`. Vertices with the same position are collapsed together, so seams of
+/// split normals or UVs stay closed, and open borders are kept in place. The returned
+/// indices refer to the original vertices, so every level of detail can share them.
+pub fn simplify(
+    vertices: &[Vertex],
+    indices: &[u32],
+    target_index_count: usize,
+    max_error: f32,
+) -> Simplified {
+    let mut simplifier = Simplifier::new(vertices, indices);
+    let error = simplifier.run(target_index_count / 3, f64::from(max_error));
+    Simplified {
+        indices: simplifier.indices(),
+        error: error as f32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::primitives::{plane, uv_sphere};
+
+    fn corners(vertices: &[Vertex], triangle: &[u32]) -> [Vector3<f32>; 3] {
+        [0, 1, 2].map(|corner| Vector3::from(vertices[triangle[corner] as usize].position))
+    }
+
+    /// Returns the area-weighted normal of a triangle
+    fn cross(vertices: &[Vertex], triangle: &[u32]) -> Vector3<f32> {
+        let [a, b, c] = corners(vertices, triangle);
+        (b - a).cross(&(c - a))
+    }
+
+    #[test]
+    fn simplification_reaches_the_target_triangle_count() {
+        let (vertices, indices) = uv_sphere(1.0, 32, 16);
+        let target = indices.len() / 4;
+        let simplified = simplify(&vertices, &indices, target, f32::INFINITY);
+
+        assert_eq!(simplified.indices.len() % 3, 0);
+        assert!(simplified.indices.len() <= target);
+        assert!(simplified.indices.len() >= target / 2);
+        assert!(simplified.error > 0.0 && simplified.error < 0.5);
+    }
+
+    #[test]
+    fn simplification_keeps_open_borders_in_place() {
+        let (vertices, indices) = plane(2.0, 2.0, 8, 8);
+        let simplified = simplify(&vertices, &indices, 24, f32::INFINITY);
+        assert!(simplified.indices.len() < indices.len());
+
+        // The outline is kept, so the remaining triangles still cover the whole square
+        let area: f32 = simplified
+            .indices
+            .chunks_exact(3)
+            .map(|triangle| cross(&vertices, triangle).norm() / 2.0)
+            .sum();
+        assert!((area - 4.0).abs() < 1e-4, "area {}", area);
+        for corner in [[-1.0, 1.0], [1.0, 1.0], [-1.0, -1.0], [1.0, -1.0]] {
+            assert!(simplified.indices.iter().any(|&index| {
+                let position = vertices[index as usize].position;
+                position[0] == corner[0] && position[2] == corner[1]
+            }));
+        }
+    }
+
+    #[test]
+    fn simplification_does_not_flip_faces() {
+        let (vertices, indices) = plane(2.0, 2.0, 8, 8);
+        let simplified = simplify(&vertices, &indices, 24, f32::INFINITY);
+        for triangle in simplified.indices.chunks_exact(3) {
+            assert!(cross(&vertices, triangle).y > 0.0);
+        }
+
+        let (vertices, indices) = uv_sphere(1.0, 32, 16);
+        let simplified = simplify(&vertices, &indices, indices.len() / 8, f32::INFINITY);
+        for triangle in simplified.indices.chunks_exact(3) {
+            let [a, b, c] = corners(&vertices, triangle);
+            let center = (a + b + c) / 3.0;
+            assert!(cross(&vertices, triangle).dot(&center) > 0.0);
+        }
+    }
+
+    #[test]
+    fn error_limit_stops_simplification() {
+        let (vertices, indices) = uv_sphere(1.0, 32, 16);
+        let simplified = simplify(&vertices, &indices, 0, 1e-4);
+        assert!(simplified.indices.len() > indices.len() / 2);
+        assert!(simplified.error <= 1e-4);
+    }
+
+    #[test]
+    fn empty_and_degenerate_input_is_handled() {
+        let simplified = simplify(&[], &[], 0, f32::INFINITY);
+        assert!(simplified.indices.is_empty());
+        assert_eq!(simplified.error, 0.0);
+
+        // A degenerate triangle next to a real one, with a target above the input size
+        let (vertices, _) = plane(1.0, 1.0, 1, 1);
+        let indices = [0, 0, 1, 0, 2, 1];
+        let simplified = simplify(&vertices, &indices, 12, f32::INFINITY);
+        assert_eq!(simplified.indices.len() % 3, 0);
+        assert!(simplified.indices.len() <= indices.len());
+        assert!(simplified.indices.iter().all(|&index| (index as usize) < vertices.len()));
+    }
+}
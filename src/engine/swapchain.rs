@@ -38,6 +38,10 @@ pub fn create_swapchain(
     // Get dimensions from surface capabilities
     let dimensions = surface_capabilities.current_extent.unwrap_or([800, 600]);
 
+    // Allow copying presented images out for screenshots where the surface supports it
+    let image_usage = vulkano::image::ImageUsage::COLOR_ATTACHMENT
+        | (surface_capabilities.supported_usage_flags & vulkano::image::ImageUsage::TRANSFER_SRC);
+
     // Create the swapchain and its images
     let (swapchain, images) = Swapchain::new(
         device.clone(),
@@ -47,7 +51,7 @@ pub fn create_swapchain(
             image_format: Some(format.0),
             image_color_space: format.1,
             image_extent: dimensions,
-            image_usage: vulkano::image::ImageUsage::COLOR_ATTACHMENT,
+            image_usage,
             composite_alpha: vulkano::swapchain::CompositeAlpha::Opaque,
             ..Default::default()
         },
@@ -0,0 +1,98 @@
+use vulkano::pipeline::graphics::vertex_input::Vertex as VertexType;
+
+use crate::engine::buffer::Vertex;
+
+/// A vertex type that can be uploaded as one stream of a mesh
+///
+/// A mesh reads its attributes from one or more streams, each bound to its own vertex buffer.
+/// Field names are matched against the vertex shader inputs, so a stream may carry attributes
+/// no shader reads yet; only the inputs of the pipeline's vertex shader have to be present.
+pub trait VertexStream: VertexType + Copy {
+    /// Returns the model-space positions of the vertices, if this stream carries them
+    ///
+    /// Exactly one stream of a mesh has to provide positions; they define its bounds.
+    fn positions(_vertices: &[Self]) -> Option<Vec<[f32; 3]>> {
+        None
+    }
+}
+
+impl VertexStream for Vertex {
+    fn positions(vertices: &[Self]) -> Option<Vec<[f32; 3]>> {
+        Some(vertices.iter().map(|vertex| vertex.position).collect())
+    }
+}
+
+/// Positions only, e.g. for depth-only geometry or as the first of several streams
+#[derive(Default, Debug, Clone, Copy, vulkano::pipeline::graphics::vertex_input::Vertex)]
+#[repr(C)]
+pub struct PositionVertex {
+    #[format(R32G32B32_SFLOAT)]
+    pub position: [f32; 3],
+}
+
+unsafe impl bytemuck::Pod for PositionVertex {}
+unsafe impl bytemuck::Zeroable for PositionVertex {}
+
+impl VertexStream for PositionVertex {
+    fn positions(vertices: &[Self]) -> Option<Vec<[f32; 3]>> {
+        Some(vertices.iter().map(|vertex| vertex.position).collect())
+    }
+}
+
+/// Tangents for normal mapping, with the bitangent sign in `w`
+///
+/// Matches the output of `mesh_processing::generate_tangents`.
+#[derive(Default, Debug, Clone, Copy, vulkano::pipeline::graphics::vertex_input::Vertex)]
+#[repr(C)]
+pub struct TangentVertex {
+    #[format(R32G32B32A32_SFLOAT)]
+    pub tangent: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for TangentVertex {}
+unsafe impl bytemuck::Zeroable for TangentVertex {}
+
+impl VertexStream for TangentVertex {}
+
+/// Linear RGBA vertex colors
+#[derive(Default, Debug, Clone, Copy, vulkano::pipeline::graphics::vertex_input::Vertex)]
+#[repr(C)]
+pub struct ColorVertex {
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for ColorVertex {}
+unsafe impl bytemuck::Zeroable for ColorVertex {}
+
+impl VertexStream for ColorVertex {}
+
+/// A second UV set, e.g. for lightmaps; the first one lives in `Vertex::tex_coords`
+#[derive(Default, Debug, Clone, Copy, vulkano::pipeline::graphics::vertex_input::Vertex)]
+#[repr(C)]
+pub struct TexCoordVertex {
+    #[format(R32G32_SFLOAT)]
+    pub tex_coords1: [f32; 2],
+}
+
+unsafe impl bytemuck::Pod for TexCoordVertex {}
+unsafe impl bytemuck::Zeroable for TexCoordVertex {}
+
+impl VertexStream for TexCoordVertex {}
+
+/// Up to four joints influencing a vertex and their weights, for skinning
+///
+/// Read as a `uvec4 joints` and `vec4 weights` pair; the weights should sum to one.
+#[derive(Default, Debug, Clone, Copy, vulkano::pipeline::graphics::vertex_input::Vertex)]
+#[repr(C)]
+pub struct SkinVertex {
+    #[format(R16G16B16A16_UINT)]
+    pub joints: [u16; 4],
+    #[format(R32G32B32A32_SFLOAT)]
+    pub weights: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for SkinVertex {}
+unsafe impl bytemuck::Zeroable for SkinVertex {}
+
+impl VertexStream for SkinVertex {}
@@ -1,14 +1,30 @@
 use anyhow::Result;
 use log::info;
+use nalgebra::{Point3, UnitQuaternion, Vector3};
 use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use vulkano::instance::{Instance, InstanceCreateInfo};
-use winit::event::{Event, WindowEvent};
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::WindowBuilder;
 
 mod engine;
 mod shaders;
+use engine::buffer::{create_cube, InstanceData};
+use engine::camera::Camera;
+use engine::mesh::Mesh;
+use engine::pipeline::{BlendPreset, DEPTH_FORMAT};
+use engine::primitives;
+use engine::recording::{EncoderCommand, FrameSink, Recorder};
+use engine::render_graph::ImageSize;
+use engine::render_queue::{InstanceBatch, Material, RenderQueue};
+use engine::render_target::RenderTargetDesc;
 use engine::renderer::Renderer;
+use engine::scene::{NodeId, SceneGraph, Transform};
+use engine::scene_file::{MeshLibrary, SceneFile, BUILTIN_CUBE};
+
+/// Frame rate of recordings started from the demo
+const RECORDING_FPS: f64 = 60.0;
 
 fn main() -> Result<()> {
     // Initialize logging
@@ -44,6 +60,64 @@ fn main() -> Result<()> {
     // Create a renderer, passing both instance and surface
     let mut renderer = Renderer::new(instance, surface)?;
 
+    // Upload the demo geometry and set up a camera looking at it
+    let (cube_vertices, cube_indices) = create_cube();
+    let cube = Mesh::new(renderer.memory_allocator(), &cube_vertices, &cube_indices)?;
+    let camera = Camera::look_at(
+        Point3::new(0.0, 1.5, 4.0),
+        Point3::origin(),
+        Vector3::y(),
+    );
+    let mut render_queue = RenderQueue::new(camera);
+    let start_time = Instant::now();
+
+    // Register the meshes that scene files can refer to
+    let mut mesh_library = MeshLibrary::new();
+    mesh_library.insert(BUILTIN_CUBE, cube.clone());
+    for (path, (vertices, indices)) in [
+        ("builtin:sphere", primitives::uv_sphere(0.5, 32, 16)),
+        ("builtin:icosphere", primitives::icosphere(0.5, 3)),
+        ("builtin:plane", primitives::plane(1.0, 1.0, 1, 1)),
+        ("builtin:cylinder", primitives::cylinder(0.5, 1.0, 32)),
+        ("builtin:cone", primitives::cone(0.5, 1.0, 32)),
+        ("builtin:capsule", primitives::capsule(0.25, 0.5, 32, 8)),
+        ("builtin:torus", primitives::torus(0.375, 0.125, 48, 24)),
+    ] {
+        mesh_library.insert(path, Mesh::new(renderer.memory_allocator(), &vertices, &indices)?);
+    }
+
+    // Open the scene given with `--scene <path>`, or build the animated demo scene
+    let (mut scene, animation) = match arg_value("--scene") {
+        Some(path) => {
+            let loaded = SceneFile::load(&path)?.instantiate(&mesh_library)?;
+            if let Some(camera) = loaded.camera {
+                render_queue.camera = camera;
+            }
+            for light in loaded.lights {
+                render_queue.push_light(light);
+            }
+            (loaded.scene, None)
+        }
+        None => {
+            let (scene, animation) = build_demo_scene(&cube)?;
+            (scene, Some(animation))
+        }
+    };
+
+    // Add a floor of GPU-culled cubes with `--gpu-grid <cubes per side>`, occlusion-culled
+    // behind a half-resolution depth prepass of the scene
+    let mut occluders = None;
+    if let Some(side) = arg_value("--gpu-grid") {
+        let side: u32 = side.parse()?;
+        let grid = build_cube_grid(&cube, side);
+        render_queue.push_gpu_scene(renderer.create_gpu_scene(&[grid])?);
+
+        let desc = RenderTargetDesc::depth_only(ImageSize::SwapchainRelative(0.5), DEPTH_FORMAT);
+        let target = renderer.create_render_target(desc)?;
+        renderer.set_occlusion_target(Some(target))?;
+        occluders = Some(target);
+    }
+
     // Run the event loop
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
@@ -55,9 +129,157 @@ fn main() -> Result<()> {
             } => {
                 *control_flow = ControlFlow::Exit;
             }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F1),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                // Toggle the debug wireframe overlay
+                if let Err(e) = renderer.toggle_wireframe_overlay() {
+                    eprintln!("Error toggling wireframe overlay: {}", e);
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F2),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                // Step through the supported MSAA sample counts
+                if let Err(e) = renderer.cycle_sample_count() {
+                    eprintln!("Error changing MSAA sample count: {}", e);
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F3),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                // Toggle frustum culling, reporting what the last frame skipped
+                let stats = renderer.cull_stats();
+                info!(
+                    "Last frame culled {} of {} draws, {} visible",
+                    stats.culled,
+                    stats.tested,
+                    stats.visible()
+                );
+                renderer.set_frustum_culling(!renderer.frustum_culling());
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F11),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                // Start or stop recording at a fixed 60 fps timestep, to numbered PNGs or, with
+                // `RECORD_VIDEO` set, to an MP4 through ffmpeg
+                let result = if renderer.is_recording() {
+                    renderer.stop_recording().map(|_| ())
+                } else {
+                    let timestamp = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |elapsed| elapsed.as_millis());
+                    let sink = if std::env::var_os("RECORD_VIDEO").is_some() {
+                        FrameSink::Encoder(EncoderCommand::ffmpeg(format!(
+                            "recordings/{}.mp4",
+                            timestamp
+                        )))
+                    } else {
+                        FrameSink::ImageSequence(format!("recordings/{}", timestamp).into())
+                    };
+                    std::fs::create_dir_all("recordings")
+                        .map_err(Into::into)
+                        .and_then(|_| Recorder::new(sink, RECORDING_FPS))
+                        .and_then(|recorder| renderer.start_recording(recorder))
+                };
+                if let Err(e) = result {
+                    eprintln!("Error toggling recording: {}", e);
+                }
+            }
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(VirtualKeyCode::F12),
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                // Save the next frame as a timestamped screenshot
+                let timestamp = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_millis());
+                let path = format!("screenshots/frame-{}.png", timestamp);
+                if let Err(e) = renderer.capture_frame(path) {
+                    eprintln!("Error capturing frame: {}", e);
+                }
+            }
+            Event::LoopDestroyed => {
+                // Finish any recording in progress so the encoder can flush its output
+                if let Err(e) = renderer.stop_recording() {
+                    eprintln!("Error finishing recording: {}", e);
+                }
+
+                // Persist compiled pipelines for the next launch
+                if let Err(e) = renderer.save_pipeline_cache() {
+                    eprintln!("Error saving pipeline cache: {}", e);
+                }
+            }
             Event::MainEventsCleared => {
+                // While recording, animation follows simulated rather than wall-clock time
+                let angle = renderer
+                    .recording_time()
+                    .unwrap_or_else(|| start_time.elapsed())
+                    .as_secs_f32();
+                if let Some(animation) = &animation {
+                    if let Err(e) = animation.apply(&mut scene, angle) {
+                        eprintln!("Error animating scene: {}", e);
+                    }
+                }
+
+                // Draw the occluders' depth from the main camera before the frame culls against it
+                if let Some(occluders) = occluders {
+                    let mut prepass = RenderQueue::new(render_queue.camera);
+                    scene.queue_draws(&mut prepass);
+                    renderer.render_to_target(occluders, prepass);
+                }
+
                 // Render a frame
-                if let Err(e) = renderer.render_frame() {
+                if let Err(e) = renderer.render_scene(&mut scene, &mut render_queue) {
                     eprintln!("Error rendering frame: {}", e);
                     *control_flow = ControlFlow::Exit;
                 }
@@ -65,4 +287,110 @@ fn main() -> Result<()> {
             _ => (),
         }
     });
-}
\ No newline at end of file
+}
+
+/// Nodes of the built-in demo scene that spin over time
+struct DemoAnimation {
+    spinner: NodeId,
+    glass_cubes: Vec<(NodeId, f32)>,
+}
+
+impl DemoAnimation {
+    /// Spins the animated nodes to `angle`; their children follow through the hierarchy
+    fn apply(&self, scene: &mut SceneGraph, angle: f32) -> Result<()> {
+        let spin = UnitQuaternion::from_euler_angles(0.0, angle, 0.0);
+        let local = scene.local_transform(self.spinner)?.with_rotation(spin);
+        scene.set_local_transform(self.spinner, local)?;
+
+        for &(node, offset) in &self.glass_cubes {
+            let spin = UnitQuaternion::from_euler_angles(0.0, angle + offset, 0.0);
+            let local = scene.local_transform(node)?.with_rotation(spin);
+            scene.set_local_transform(node, local)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds the demo scene: a spinning cube carrying a small moon, with blended cubes in front
+fn build_demo_scene(cube: &Arc<Mesh>) -> Result<(SceneGraph, DemoAnimation)> {
+    let mut scene = SceneGraph::new();
+    let spinner = scene.add_mesh_node(
+        "spinner",
+        None,
+        Transform::identity(),
+        cube.clone(),
+        Material::default(),
+    )?;
+    scene.add_mesh_node(
+        "moon",
+        Some(spinner),
+        Transform::from_translation(Vector3::new(1.2, 0.0, 0.0)).with_scale(0.3),
+        cube.clone(),
+        Material::opaque([0.8, 0.8, 0.8]),
+    )?;
+    scene.add_mesh_node(
+        "alpha cube",
+        None,
+        Transform::from_translation(Vector3::new(-0.6, 0.0, 1.0)),
+        cube.clone(),
+        Material::blended([0.2, 0.4, 0.9, 0.5], BlendPreset::Alpha),
+    )?;
+    scene.add_mesh_node(
+        "additive cube",
+        None,
+        Transform::from_translation(Vector3::new(0.6, 0.0, 1.5)),
+        cube.clone(),
+        Material::blended([0.9, 0.8, 0.2, 1.0], BlendPreset::Additive),
+    )?;
+
+    // Two intersecting glass cubes that sorting alone cannot order correctly
+    let mut glass_cubes = Vec::new();
+    for (offset, color) in [(-0.2, [0.2, 0.9, 0.4, 0.4]), (0.2, [0.9, 0.3, 0.8, 0.4])] {
+        let node = scene.add_mesh_node(
+            "glass cube",
+            None,
+            Transform::from_translation(Vector3::new(offset, 1.0, 0.5)),
+            cube.clone(),
+            Material::order_independent(color),
+        )?;
+        glass_cubes.push((node, offset));
+    }
+
+    Ok((scene, DemoAnimation { spinner, glass_cubes }))
+}
+
+/// Builds a square grid of small cubes below the demo scene, tinted across the grid
+fn build_cube_grid(cube: &Arc<Mesh>, side: u32) -> InstanceBatch {
+    let spacing = 1.5;
+    let offset = (side as f32 - 1.0) * spacing * 0.5;
+    let mut instances = Vec::with_capacity((side * side) as usize);
+    for z in 0..side {
+        for x in 0..side {
+            let translation = Vector3::new(
+                x as f32 * spacing - offset,
+                -1.5,
+                z as f32 * spacing - offset,
+            );
+            let model = Transform::from_translation(translation).with_scale(0.5).matrix();
+            let tint = [x as f32 / side as f32, 0.5, z as f32 / side as f32, 1.0];
+            instances.push(InstanceData::with_color(model, tint));
+        }
+    }
+
+    InstanceBatch {
+        mesh: cube.clone(),
+        material: Material::opaque([1.0, 1.0, 1.0]),
+        instances,
+    }
+}
+
+/// Returns the value following `name` on the command line, e.g. the path of `--scene <path>`
+fn arg_value(name: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == name {
+            return args.next();
+        }
+    }
+    None
+}
@@ -0,0 +1,15 @@
+/// Path of the fullscreen triangle vertex shader source, relative to the crate root
+pub const PATH: &str = "shaders/fullscreen.vert";
+
+pub mod vs {
+    use vulkano_shaders::shader;
+
+    shader! {
+        ty: "vertex",
+        path: "shaders/fullscreen.vert",
+    }
+}
+
+pub fn load(device: std::sync::Arc<vulkano::device::Device>) -> Result<std::sync::Arc<vulkano::shader::ShaderModule>, vulkano::shader::ShaderCreationError> {
+    vs::load(device)
+}
@@ -0,0 +1,30 @@
+/// Path of the GPU culling compute shader source, relative to the crate root
+pub const PATH: &str = "shaders/gpu_cull.comp";
+
+pub mod cs {
+    use vulkano_shaders::shader;
+
+    shader! {
+        ty: "compute",
+        path: "shaders/gpu_cull.comp",
+    }
+}
+
+/// The same shader built with `HI_Z`, additionally testing against a depth pyramid
+pub mod cs_hi_z {
+    use vulkano_shaders::shader;
+
+    shader! {
+        ty: "compute",
+        path: "shaders/gpu_cull.comp",
+        define: [("HI_Z", "1")],
+    }
+}
+
+pub fn load(device: std::sync::Arc<vulkano::device::Device>) -> Result<std::sync::Arc<vulkano::shader::ShaderModule>, vulkano::shader::ShaderCreationError> {
+    cs::load(device)
+}
+
+pub fn load_hi_z(device: std::sync::Arc<vulkano::device::Device>) -> Result<std::sync::Arc<vulkano::shader::ShaderModule>, vulkano::shader::ShaderCreationError> {
+    cs_hi_z::load(device)
+}
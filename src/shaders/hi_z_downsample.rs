@@ -0,0 +1,15 @@
+/// Path of the Hi-Z pyramid downsampling compute shader source, relative to the crate root
+pub const PATH: &str = "shaders/hi_z_downsample.comp";
+
+pub mod cs {
+    use vulkano_shaders::shader;
+
+    shader! {
+        ty: "compute",
+        path: "shaders/hi_z_downsample.comp",
+    }
+}
+
+pub fn load(device: std::sync::Arc<vulkano::device::Device>) -> Result<std::sync::Arc<vulkano::shader::ShaderModule>, vulkano::shader::ShaderCreationError> {
+    cs::load(device)
+}
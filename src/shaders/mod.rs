@@ -1,3 +1,11 @@
 // Shader module exports
 pub mod vertex;
 pub mod fragment;
+pub mod wireframe;
+pub mod fullscreen;
+pub mod oit_accumulate;
+pub mod oit_composite;
+pub mod oit_composite_ms;
+pub mod instanced;
+pub mod gpu_cull;
+pub mod hi_z_downsample;
@@ -0,0 +1,15 @@
+/// Path of the weighted blended OIT accumulation fragment shader source, relative to the crate root
+pub const PATH: &str = "shaders/oit_accumulate.frag";
+
+pub mod fs {
+    use vulkano_shaders::shader;
+
+    shader! {
+        ty: "fragment",
+        path: "shaders/oit_accumulate.frag",
+    }
+}
+
+pub fn load(device: std::sync::Arc<vulkano::device::Device>) -> Result<std::sync::Arc<vulkano::shader::ShaderModule>, vulkano::shader::ShaderCreationError> {
+    fs::load(device)
+}